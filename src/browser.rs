@@ -0,0 +1,251 @@
+//
+// [browser]
+// ; site = <last title segment> | <domain>
+// site = Azure DevOps Services | dev.azure.com
+//
+// Configured sites are tried before the built-in ones.
+//
+
+use config::{self, Config};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Browser {
+    Edge,
+    Chrome,
+    Firefox,
+}
+
+impl Browser {
+    pub fn from_image_name(image_name: &str) -> Option<Browser> {
+        match image_name.to_lowercase().as_str() {
+            // MicrosoftEdge*.exe is the universal app, msedge.exe the chromium based one
+            "microsoftedge.exe" | "microsoftedgecp.exe" | "msedge.exe" => Some(Browser::Edge),
+            "chrome.exe" => Some(Browser::Chrome),
+            "firefox.exe" => Some(Browser::Firefox),
+            _ => None,
+        }
+    }
+
+    fn suffixes(&self) -> &'static [&'static str] {
+        match *self {
+            Browser::Edge => &["Microsoft Edge"],
+            Browser::Chrome => &["Google Chrome"],
+            Browser::Firefox => &["Mozilla Firefox Private Browsing", "Mozilla Firefox"],
+        }
+    }
+}
+
+// "CreateFileW - Google Search and 2 more pages ‎- Microsoft Edge" => "CreateFileW - Google Search"
+pub fn page_title(browser: Browser, window_title: &str) -> String {
+    // edge decorates its suffix with LEFT-TO-RIGHT MARK and ZERO WIDTH SPACE
+    let title: String = window_title
+        .chars()
+        .filter(|&c| c != '\u{200b}' && c != '\u{200e}')
+        .collect();
+    let mut title = title.trim();
+
+    for suffix in browser.suffixes() {
+        if title.ends_with(suffix) {
+            title = title[..title.len() - suffix.len()].trim_end();
+            title = title.trim_end_matches(['-', '\u{2014}']).trim_end();
+            break;
+        }
+    }
+
+    strip_more_pages(title).to_string()
+}
+
+// edge appends " and N more page(s)" when several tabs are grouped into one window
+fn strip_more_pages(title: &str) -> &str {
+    if let Some(position) = title.rfind(" and ") {
+        let rest = &title[position + " and ".len()..];
+        let mut words = rest.split(' ');
+        let is_count = words
+            .next()
+            .is_some_and(|t| !t.is_empty() && t.chars().all(|c| c.is_ascii_digit()));
+        if is_count
+            && words.next() == Some("more")
+            && words.next().is_some_and(|t| t == "page" || t == "pages")
+            && words.next().is_none()
+        {
+            return title[..position].trim_end();
+        }
+    }
+    title
+}
+
+#[derive(Default)]
+pub struct SiteResolver {
+    // (last title segment, domain)
+    sites: Vec<(String, String)>,
+}
+
+const BUILTIN_SITES: &[(&str, &str)] = &[
+    ("Google Search", "google.com"),
+    ("Gmail", "mail.google.com"),
+    ("YouTube", "youtube.com"),
+    ("GitHub", "github.com"),
+    ("Stack Overflow", "stackoverflow.com"),
+    ("Wikipedia", "wikipedia.org"),
+    ("Reddit", "reddit.com"),
+    ("Twitter", "twitter.com"),
+    ("Facebook", "facebook.com"),
+    ("LinkedIn", "linkedin.com"),
+    ("Outlook", "outlook.com"),
+];
+
+impl SiteResolver {
+    pub fn from_config(config: &Config) -> Result<SiteResolver, String> {
+        let mut sites = Vec::new();
+        for (key, value) in config.entries("browser") {
+            let arguments = config::split_list(value);
+            if key != "site" || arguments.len() != 2 {
+                return Err(format!(
+                    "[browser] {} = {}: expected site = <title> | <domain>",
+                    key, value
+                ));
+            }
+            sites.push((arguments[0].clone(), arguments[1].clone()));
+        }
+        Ok(SiteResolver { sites })
+    }
+
+    // best effort: a page without a title shows its address, and most sites append their
+    // name to the title
+    pub fn resolve(&self, page_title: &str) -> String {
+        if let Some(host) = host_from_address(page_title) {
+            return host;
+        }
+
+        let last_segment = [" - ", " | ", " \u{00b7} ", " \u{2014} "]
+            .iter()
+            .filter_map(|separator| page_title.rfind(separator).map(|t| t + separator.len()))
+            .max()
+            .map_or(page_title, |t| &page_title[t..])
+            .trim();
+
+        let configured = self.sites.iter().map(|(t, d)| (t.as_str(), d.as_str()));
+        let builtin = BUILTIN_SITES.iter().cloned();
+        configured
+            .chain(builtin)
+            .find(|&(title, _)| title.eq_ignore_ascii_case(last_segment))
+            .map(|(_, domain)| domain.to_string())
+            .unwrap_or_default()
+    }
+}
+
+// "https://www.example.com/path" or "example.com/path" => "example.com"
+fn host_from_address(text: &str) -> Option<String> {
+    if text.is_empty() || text.contains(char::is_whitespace) {
+        return None;
+    }
+    let address = match text.find("://") {
+        Some(t) => &text[t + 3..],
+        None => text,
+    };
+    let host = address.split(['/', '?', '#']).next().unwrap_or("");
+    let host = host.split(':').next().unwrap_or("").to_lowercase();
+    let is_host = host.contains('.')
+        && !host.starts_with('.')
+        && !host.ends_with('.')
+        && host
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '-');
+    if !is_host {
+        return None;
+    }
+    Some(host.trim_start_matches("www.").to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn edge_marks_and_grouped_pages_are_removed() {
+        let title = "CreateFileW - Google Search and 2 more pages \u{200e}- Microsoft\u{200b} Edge";
+        assert_eq!(
+            page_title(Browser::Edge, title),
+            "CreateFileW - Google Search"
+        );
+    }
+
+    #[test]
+    fn browser_suffix_is_removed() {
+        assert_eq!(
+            page_title(Browser::Chrome, "Issues \u{00b7} GitHub - Google Chrome"),
+            "Issues \u{00b7} GitHub"
+        );
+        assert_eq!(
+            page_title(
+                Browser::Firefox,
+                "Reddit \u{2014} Mozilla Firefox Private Browsing"
+            ),
+            "Reddit"
+        );
+        assert_eq!(page_title(Browser::Firefox, "Mozilla Firefox"), "");
+        // another browser's suffix is part of the title
+        assert_eq!(
+            page_title(Browser::Edge, "a - Google Chrome"),
+            "a - Google Chrome"
+        );
+    }
+
+    #[test]
+    fn only_a_trailing_page_count_is_stripped() {
+        assert_eq!(strip_more_pages("Inbox and 1 more page"), "Inbox");
+        assert_eq!(strip_more_pages("Tom and Jerry"), "Tom and Jerry");
+        assert_eq!(
+            strip_more_pages("Cats and 3 more pages about dogs"),
+            "Cats and 3 more pages about dogs"
+        );
+        assert_eq!(
+            strip_more_pages("Cats and many more pages"),
+            "Cats and many more pages"
+        );
+        assert_eq!(strip_more_pages("and 3 more pages"), "and 3 more pages");
+    }
+
+    #[test]
+    fn addresses_are_reduced_to_the_host() {
+        let host = |t| host_from_address(t);
+        assert_eq!(
+            host("https://www.Example.com:8080/a?b#c"),
+            Some("example.com".to_string())
+        );
+        assert_eq!(host("docs.rs/winapi"), Some("docs.rs".to_string()));
+        assert_eq!(host("localhost"), None);
+        assert_eq!(host("see example.com"), None);
+        assert_eq!(host(".hidden"), None);
+        assert_eq!(host(""), None);
+    }
+
+    #[test]
+    fn site_comes_from_the_last_title_segment() {
+        let resolver = SiteResolver::default();
+        assert_eq!(resolver.resolve("rust - Google Search"), "google.com");
+        assert_eq!(resolver.resolve("Issues \u{00b7} GitHub"), "github.com");
+        assert_eq!(resolver.resolve("https://example.org/x"), "example.org");
+        assert_eq!(resolver.resolve("Untitled"), "");
+    }
+
+    #[test]
+    fn configured_sites_come_first() {
+        let config = Config::parse(
+            "[browser]\nsite = GitHub | github.example.com\nsite = Azure DevOps Services | dev.azure.com\n",
+        )
+        .unwrap();
+        let resolver = SiteResolver::from_config(&config).unwrap();
+        assert_eq!(
+            resolver.resolve("Boards - Azure DevOps Services"),
+            "dev.azure.com"
+        );
+        assert_eq!(
+            resolver.resolve("Pull requests - github"),
+            "github.example.com"
+        );
+
+        let bad = Config::parse("[browser]\nsite = GitHub\n").unwrap();
+        assert!(SiteResolver::from_config(&bad).is_err());
+    }
+}
@@ -0,0 +1,377 @@
+//
+// record-usage [<command>] [<arguments>]
+//
+// Without a command the tracker runs, as it always has when started from the shortcut.
+// Everything else borrows the console of whoever started us and exits with one of the
+// codes below.
+//
+
+use collector::{self, CollectorSettings};
+use config::{self, Config};
+use csv;
+use export;
+use ipc::{self, Command};
+use json;
+use logger::{self, Entry};
+use report;
+use rules::Rules;
+use upload::{self, UploadSettings};
+use webhook::{self, Delivery, WebhookSettings};
+use win32helper;
+use {run_tracker, INSTANCE_MUTEX_NAME};
+
+use std::collections::HashSet;
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+
+pub const EXIT_OK: i32 = 0;
+pub const EXIT_FAILURE: i32 = 1;
+// unknown command or bad arguments
+pub const EXIT_USAGE: i32 = 2;
+// a command for the running tracker found none
+pub const EXIT_NOT_RUNNING: i32 = 3;
+// run found another tracker, or import found one writing the log
+pub const EXIT_ALREADY_RUNNING: i32 = 4;
+
+const USAGE: &str = "\
+usage: record-usage [<command>] [<arguments>]
+
+  run [--console]          start the tracker (the default), --console shows its output
+  status                   show whether the tracker is running and today's total
+  pause                    stop recording until resumed
+  resume                   start recording again
+  flush                    write out what the tracker has not written yet
+  reload                   read the configuration again
+  stop                     exit the tracker
+  report [--by app|project|site] [--on-screen] [--file <log file>]
+         [--sessions raw|smoothed] [--focus] [--tolerance <seconds>]
+                           sum up the log, list its sessions or measure focus per day
+  export [--format log|ics|toggl|clockify|timesheet] [--by app|project|site]
+         [--min-minutes <minutes>] [--tolerance <seconds>] [--email <address>]
+         [--from <yyyy-mm-dd>] [--to <yyyy-mm-dd>] [--file <log file>] [--output <file>]
+                           copy the entries of a date range, or the sessions made of
+                           them, to stdout by default
+  import <file> [--file <log file>]
+                           add the entries of another log that are not there yet
+  config check [<file>]    load the configuration and report the first error
+  webhook test             post a test event to each webhook of [notify]
+  upload [--file <log file>]
+                           ship what the collector of [upload] does not have yet
+  collector                collect the logs of a team, see [collector]
+  --version                print the version
+  --help                   print this";
+
+pub struct CliError {
+    pub exit_code: i32,
+    pub message: String,
+}
+
+impl CliError {
+    pub fn new(exit_code: i32, message: &str) -> CliError {
+        CliError {
+            exit_code,
+            message: message.to_string(),
+        }
+    }
+
+    pub fn usage(message: &str) -> CliError {
+        CliError::new(EXIT_USAGE, message)
+    }
+
+    pub fn failure(message: &str) -> CliError {
+        CliError::new(EXIT_FAILURE, message)
+    }
+}
+
+impl From<String> for CliError {
+    fn from(message: String) -> CliError {
+        CliError::failure(&message)
+    }
+}
+
+impl From<win32helper::Win32Error> for CliError {
+    fn from(e: win32helper::Win32Error) -> CliError {
+        CliError::failure(&e.to_string())
+    }
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+// the process exit code
+pub fn run(args: &[String]) -> i32 {
+    let command = args.first().map(|t| t.as_str()).unwrap_or("run");
+    if command != "run" {
+        // we are a windows subsystem app, borrow the console of whoever started us
+        win32helper::attach_console(win32helper::ATTACH_PARENT_PROCESS).ok();
+    }
+
+    let args = if args.is_empty() { args } else { &args[1..] };
+    let result = match command {
+        "run" => run_command(args),
+        "status" => status(args),
+        "pause" => control(args, Command::Pause),
+        "resume" => control(args, Command::Resume),
+        "flush" => control(args, Command::Flush),
+        "reload" => control(args, Command::Reload),
+        "stop" => control(args, Command::Stop),
+        "report" => report::run(args),
+        "export" => export::run(args),
+        "import" => import(args),
+        "config" => config_command(args),
+        "webhook" => webhook_command(args),
+        "upload" => upload_command(args),
+        "collector" => collector::run(args),
+        "--version" | "-V" => {
+            println!("record-usage {}", env!("CARGO_PKG_VERSION"));
+            Ok(())
+        }
+        "--help" | "-h" | "help" => {
+            println!("{}", USAGE);
+            Ok(())
+        }
+        _ => Err(CliError::usage(&format!(
+            "unknown command: {}\n\n{}",
+            command, USAGE
+        ))),
+    };
+    match result {
+        Ok(()) => EXIT_OK,
+        Err(e) => {
+            eprintln!("{}", e);
+            e.exit_code
+        }
+    }
+}
+
+fn no_arguments(args: &[String]) -> Result<(), CliError> {
+    match args.first() {
+        Some(arg) => Err(CliError::usage(&format!("unknown argument: {}", arg))),
+        None => Ok(()),
+    }
+}
+
+fn run_command(args: &[String]) -> Result<(), CliError> {
+    let mut console = false;
+    for arg in args {
+        match arg.as_str() {
+            "--console" => console = true,
+            _ => return Err(CliError::usage(&format!("unknown argument: {}", arg))),
+        }
+    }
+    run_tracker(console)
+}
+
+fn send_command(command: Command) -> Result<json::Value, CliError> {
+    match ipc::send_command(command)? {
+        Some(t) => Ok(t),
+        None => Err(CliError::new(
+            EXIT_NOT_RUNNING,
+            "record-usage is not running",
+        )),
+    }
+}
+
+// e.g. "Visual Studio Code, 2:41:07 today"
+pub fn format_status(status: &json::Value) -> String {
+    let flag = |key: &str| status.get(key).and_then(|t| t.as_bool()).unwrap_or(false);
+    let application = status.get("application").and_then(|t| t.as_str());
+    let current = if flag("paused") {
+        "paused"
+    } else if flag("idle") {
+        "idle"
+    } else {
+        application.filter(|t| !t.is_empty()).unwrap_or("recording")
+    };
+    let today_in_seconds = status.get("today_in_seconds").and_then(|t| t.as_u64());
+    let mut text = format!(
+        "{}, {} today",
+        current,
+        report::format_duration(today_in_seconds.unwrap_or(0))
+    );
+    if let Some(e) = status.get("config_error").and_then(|t| t.as_str()) {
+        text += &format!("\nconfiguration not applied: {}", e);
+    }
+    text
+}
+
+fn status(args: &[String]) -> Result<(), CliError> {
+    no_arguments(args)?;
+    println!("{}", format_status(&send_command(Command::Status)?));
+    Ok(())
+}
+
+fn control(args: &[String], command: Command) -> Result<(), CliError> {
+    no_arguments(args)?;
+    send_command(command)?;
+    Ok(())
+}
+
+// yyyy-mm-dd, as (year, month, day) so that it compares in order
+pub fn parse_date(text: &str) -> Option<(u16, u16, u16)> {
+    let mut parts = text.split('-').map(|t| t.parse::<u16>().ok());
+    let date = (parts.next()??, parts.next()??, parts.next()??);
+    if parts.next().is_some() || date.1 < 1 || date.1 > 12 || date.2 < 1 || date.2 > 31 {
+        return None;
+    }
+    Some(date)
+}
+
+pub fn date_argument(option: &str, value: Option<&String>) -> Result<(u16, u16, u16), CliError> {
+    let value = value.map(|t| t.as_str()).unwrap_or("");
+    parse_date(value).ok_or(CliError::usage(&format!(
+        "{} {}: expected yyyy-mm-dd",
+        option, value
+    )))
+}
+
+pub fn seconds_argument(option: &str, value: Option<&String>) -> Result<u32, CliError> {
+    let value = value.map(|t| t.as_str()).unwrap_or("");
+    value
+        .parse()
+        .map_err(|_| CliError::usage(&format!("{} {}: expected seconds", option, value)))
+}
+
+pub fn file_argument(option: &str, value: Option<&String>) -> Result<String, CliError> {
+    value
+        .cloned()
+        .ok_or(CliError::usage(&format!("{} needs a file name", option)))
+}
+
+fn entry_line(entry: &Entry) -> Vec<u8> {
+    let mut line = Vec::new();
+    logger::write_entry(&mut line, entry).unwrap();
+    line
+}
+
+// The entries are appended as they are, so a log imported twice stays the same. The
+// tracker holds on to its entries until it flushes, so it must not be running meanwhile.
+fn import(args: &[String]) -> Result<(), CliError> {
+    let mut source = None;
+    let mut file_name = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--file" => file_name = Some(file_argument(arg, args.next())?),
+            _ if source.is_none() && !arg.starts_with("--") => source = Some(arg.clone()),
+            _ => return Err(CliError::usage(&format!("unknown argument: {}", arg))),
+        }
+    }
+    let source = source.ok_or(CliError::usage("import needs a file name"))?;
+    let file_name = file_name.unwrap_or_else(logger::log_file_name);
+
+    // taking the mutex also keeps a tracker from starting until we are done
+    if win32helper::is_app_already_runniing(INSTANCE_MUTEX_NAME) {
+        return Err(CliError::new(
+            EXIT_ALREADY_RUNNING,
+            "record-usage is running, exit it before importing",
+        ));
+    }
+
+    let existing: HashSet<Vec<u8>> = match fs::metadata(&file_name) {
+        Ok(_) => report::read_entries(&file_name)?
+            .iter()
+            .map(entry_line)
+            .collect(),
+        Err(_) => HashSet::new(),
+    };
+    let text = fs::read_to_string(&source).map_err(|e| format!("{}: {}", source, e))?;
+    let lines: Vec<Vec<u8>> = csv::parse_records(&text)
+        .iter()
+        .filter_map(|t| Entry::from_record(t))
+        .filter(|t| t.duration_in_seconds > 0)
+        .map(|t| entry_line(&t))
+        .filter(|t| !existing.contains(t))
+        .collect();
+
+    let mut file = OpenOptions::new()
+        .append(true)
+        .create(true)
+        .open(&file_name)
+        .map_err(|e| format!("{}: {}", file_name, e))?;
+    for line in &lines {
+        file.write_all(line)
+            .map_err(|e| format!("{}: {}", file_name, e))?;
+    }
+    println!("{} entries imported", lines.len());
+    Ok(())
+}
+
+fn config_command(args: &[String]) -> Result<(), CliError> {
+    match args.first().map(|t| t.as_str()) {
+        Some("check") => {}
+        Some(t) => return Err(CliError::usage(&format!("unknown config command: {}", t))),
+        None => return Err(CliError::usage("config needs a command, e.g. config check")),
+    }
+    if args.len() > 2 {
+        return Err(CliError::usage(&format!("unknown argument: {}", args[2])));
+    }
+    let file_name = args
+        .get(1)
+        .cloned()
+        .unwrap_or_else(config::config_file_name);
+    let config = Config::load(&file_name)?;
+    Rules::from_config(&config)?;
+    CollectorSettings::from_config(&config)?;
+    println!("{}: ok", file_name);
+    Ok(())
+}
+
+// Right away and once, bypassing the outbox, e.g. to try a webhook against a local stand-in.
+fn webhook_command(args: &[String]) -> Result<(), CliError> {
+    match args.first().map(|t| t.as_str()) {
+        Some("test") => {}
+        Some(t) => return Err(CliError::usage(&format!("unknown webhook command: {}", t))),
+        None => {
+            return Err(CliError::usage(
+                "webhook needs a command, e.g. webhook test",
+            ))
+        }
+    }
+    no_arguments(&args[1..])?;
+    let config = Config::load(&config::config_file_name())?;
+    let settings = WebhookSettings::from_config(&config)?;
+    if settings.urls.is_empty() {
+        return Err(CliError::failure("no webhook in [notify]"));
+    }
+
+    let event = webhook::event("test", json::object(vec![]));
+    let mut failed = false;
+    for url in &settings.urls {
+        let delivery = Delivery::new(url, &settings.secret, &event);
+        match delivery.post() {
+            Ok(()) => println!("{}: ok", delivery.url()),
+            Err(e) => {
+                println!("{}: {}", delivery.url(), e);
+                failed = true;
+            }
+        }
+    }
+    if failed {
+        return Err(CliError::failure("not every webhook answered 2xx"));
+    }
+    Ok(())
+}
+
+// The tracker uploads on its own after every flush; this is for when it is not running.
+fn upload_command(args: &[String]) -> Result<(), CliError> {
+    let mut file_name = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--file" => file_name = Some(file_argument(arg, args.next())?),
+            _ => return Err(CliError::usage(&format!("unknown argument: {}", arg))),
+        }
+    }
+    let config = Config::load(&config::config_file_name())?;
+    let settings = UploadSettings::from_config(&config)?;
+    let file_name = file_name.unwrap_or_else(logger::log_file_name);
+    let offset = upload::upload(&settings, &file_name, None)?;
+    println!("{}: the collector has {} bytes", file_name, offset);
+    Ok(())
+}
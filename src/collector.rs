@@ -0,0 +1,599 @@
+//
+// record-usage collector
+//
+// Collects the logs of a team, each client uploading its own (see [upload]), and sums them
+// up per user and for the whole team.
+//
+// [collector]
+// ; where to listen, every address by default
+// listen = 0.0.0.0:9090
+// ; a log per user, record-usage-collector next to the log by default
+// directory = D:\usage
+// ; <user> | <token>, one per client; the user names the log, letters, digits, - _ and . only,
+// ; and has one client at most
+// client = alice | 9f2c4e0b7d1a...
+// client = bob | 41d0aa53c8e6...
+//
+// Every request carries "Authorization: Bearer <token>" of a client:
+//
+// GET  /api/offset                   {"user": "alice", "offset": 123456}, how much of the
+//                                    log the collector has
+// POST /api/upload?offset=<offset>   more of the log, from that offset on; answers the new
+//                                    offset, or 409 with the one it has when they differ
+// GET  /api/report[?date=yyyy-mm-dd][&by=app|project|site]
+//                                    {"date": ..., "by": ..., "users": [{"name": "alice",
+//                                     "active_seconds": ..., "items": [{"name": ...,
+//                                     "seconds": ...}, ...]}, ...], "team": [...]}
+//
+// A client only ever writes its own log, but any of them may read the team's totals.
+//
+
+use cli::{self, CliError};
+use config::{self, Config};
+use csv;
+use http;
+use json;
+use logger::Entry;
+use report::{self, Grouping};
+use win32helper;
+
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::net::{TcpListener, TcpStream};
+use std::str;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+
+const MAX_UPLOAD_SIZE: usize = 1024 * 1024;
+const MAX_CONNECTIONS: usize = 16;
+
+pub struct Client {
+    pub user: String,
+    pub token: String,
+}
+
+pub struct CollectorSettings {
+    pub listen: String,
+    pub directory: String,
+    pub clients: Vec<Client>,
+}
+
+impl Default for CollectorSettings {
+    fn default() -> CollectorSettings {
+        CollectorSettings {
+            listen: "0.0.0.0:9090".to_string(),
+            directory: env::var("LOCALAPPDATA").unwrap() + "\\record-usage-collector",
+            clients: Vec::new(),
+        }
+    }
+}
+
+fn is_user_name(name: &str) -> bool {
+    !name.is_empty()
+        && !name.starts_with('.')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.')
+}
+
+impl CollectorSettings {
+    pub fn from_config(config: &Config) -> Result<CollectorSettings, String> {
+        let mut settings = CollectorSettings::default();
+        for (key, value) in config.entries("collector") {
+            match key {
+                "listen" => settings.listen = value.to_string(),
+                "directory" => settings.directory = value.to_string(),
+                "client" => {
+                    let arguments = config::split_list(value);
+                    if arguments.len() != 2
+                        || !is_user_name(&arguments[0])
+                        || arguments[1].is_empty()
+                    {
+                        return Err(format!(
+                            "[collector] {} = {}: expected client = <user> | <token>",
+                            key, value
+                        ));
+                    }
+                    // two clients would write to the same log, each from its own offset; the
+                    // file names do not tell case apart
+                    if settings
+                        .clients
+                        .iter()
+                        .any(|t| t.user.eq_ignore_ascii_case(&arguments[0]))
+                    {
+                        return Err(format!(
+                            "[collector] {} = {}: user already has a client",
+                            key, value
+                        ));
+                    }
+                    if settings.clients.iter().any(|t| t.token == arguments[1]) {
+                        return Err(format!(
+                            "[collector] {} = {}: token already in use",
+                            key, value
+                        ));
+                    }
+                    settings.clients.push(Client {
+                        user: arguments[0].clone(),
+                        token: arguments[1].clone(),
+                    });
+                }
+                _ => return Err(format!("[collector] {}: unknown setting", key)),
+            }
+        }
+        Ok(settings)
+    }
+
+    fn log_file_name(&self, user: &str) -> String {
+        format!("{}\\{}.csv", self.directory, user)
+    }
+
+    // takes as long for a wrong token as for a right one
+    fn authenticate(&self, request: &http::Request) -> Option<&Client> {
+        let token = request.header("Authorization")?.trim();
+        if token.len() < 7 || !token[..7].eq_ignore_ascii_case("Bearer ") {
+            return None;
+        }
+        let token = token[7..].trim().as_bytes();
+        let mut found = None;
+        for client in &self.clients {
+            let expected = client.token.as_bytes();
+            let mut difference = (expected.len() != token.len()) as u8;
+            for (index, &byte) in expected.iter().enumerate() {
+                difference |= byte ^ token.get(index).cloned().unwrap_or(0);
+            }
+            if difference == 0 {
+                found = Some(client);
+            }
+        }
+        found
+    }
+}
+
+fn file_size(file_name: &str) -> u64 {
+    fs::metadata(file_name).map(|t| t.len()).unwrap_or(0)
+}
+
+fn offset_response(offset: u64) -> String {
+    json::object(vec![("offset", offset.into())]).to_string()
+}
+
+// The piece goes where the log of the user ends, and only if that is where the client
+// says it starts, so a piece sent twice is taken once.
+fn upload(
+    settings: &CollectorSettings,
+    user: &str,
+    request: &http::Request,
+) -> (&'static str, String) {
+    let file_name = settings.log_file_name(user);
+    let size = file_size(&file_name);
+    let offset = match request
+        .parameter("offset")
+        .and_then(|t| t.parse::<u64>().ok())
+    {
+        Some(t) => t,
+        None => return ("400 Bad Request", "expected ?offset=<offset>".to_string()),
+    };
+    if offset != size {
+        return ("409 Conflict", offset_response(size));
+    }
+    // a piece is whole records of the log, anything else would spoil the log it is added to
+    let text = match str::from_utf8(&request.body) {
+        Ok(t) => t,
+        Err(_) => return ("400 Bad Request", "expected UTF-8".to_string()),
+    };
+    if csv::last_record_end(&request.body) != Some(request.body.len()) {
+        return ("400 Bad Request", "expected whole records".to_string());
+    }
+    if let Some(index) = csv::parse_records(text)
+        .iter()
+        .position(|t| Entry::from_record(t).is_none())
+    {
+        return (
+            "400 Bad Request",
+            format!("record {} is not an entry of the log", index + 1),
+        );
+    }
+
+    let result = OpenOptions::new()
+        .append(true)
+        .create(true)
+        .open(&file_name)
+        .and_then(|mut file| file.write_all(&request.body));
+    match result {
+        Ok(()) => ("200 OK", offset_response(size + request.body.len() as u64)),
+        Err(e) => {
+            println!("{}: {}", file_name, e);
+            ("500 Internal Server Error", String::new())
+        }
+    }
+}
+
+fn items(totals: Vec<(String, u64)>) -> json::Value {
+    json::Value::Array(
+        totals
+            .into_iter()
+            .map(|(name, seconds)| {
+                json::object(vec![("name", name.into()), ("seconds", seconds.into())])
+            })
+            .collect(),
+    )
+}
+
+fn team_report(settings: &CollectorSettings, request: &http::Request) -> (&'static str, String) {
+    let date = match request.parameter("date") {
+        Some(t) => match cli::parse_date(t) {
+            Some(t) => t,
+            None => return ("400 Bad Request", "expected ?date=yyyy-mm-dd".to_string()),
+        },
+        None => {
+            let now = win32helper::get_local_time();
+            (now.wYear, now.wMonth, now.wDay)
+        }
+    };
+    let by = request.parameter("by").unwrap_or("app");
+    let grouping = match Grouping::parse(by) {
+        Some(t) => t,
+        None => {
+            return (
+                "400 Bad Request",
+                "expected &by=app|project|site".to_string(),
+            )
+        }
+    };
+
+    let mut users = Vec::new();
+    let mut team: Vec<Entry> = Vec::new();
+    let mut names: Vec<&str> = settings.clients.iter().map(|t| t.user.as_str()).collect();
+    names.sort();
+    for name in names {
+        let entries: Vec<Entry> = report::read_entries(&settings.log_file_name(name))
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|t| (t.timestamp.wYear, t.timestamp.wMonth, t.timestamp.wDay) == date)
+            .filter(|t| !t.activity.idle)
+            .collect();
+        let active_in_seconds: u64 = entries.iter().map(|t| t.duration_in_seconds as u64).sum();
+        users.push(json::object(vec![
+            ("name", name.into()),
+            ("active_seconds", active_in_seconds.into()),
+            ("items", items(report::summarize(&entries, grouping))),
+        ]));
+        team.extend(entries);
+    }
+    let body = json::object(vec![
+        (
+            "date",
+            format!("{}-{:02}-{:02}", date.0, date.1, date.2).into(),
+        ),
+        ("by", by.into()),
+        ("users", json::Value::Array(users)),
+        ("team", items(report::summarize(&team, grouping))),
+    ]);
+    ("200 OK", body.to_string())
+}
+
+fn handle_connection(mut stream: TcpStream, settings: &CollectorSettings, uploads: &Mutex<()>) {
+    let request = match http::read_request(&mut stream, MAX_UPLOAD_SIZE) {
+        Ok(t) => t,
+        Err(e) => {
+            http::write_response(&mut stream, "400 Bad Request", "text/plain", &e).ok();
+            return;
+        }
+    };
+    let (status, body) = match settings.authenticate(&request) {
+        None => ("401 Unauthorized", String::new()),
+        Some(client) => match (request.method.as_str(), request.path.as_str()) {
+            ("GET", "/api/offset") => {
+                let size = file_size(&settings.log_file_name(&client.user));
+                let body = json::object(vec![
+                    ("user", client.user.as_str().into()),
+                    ("offset", size.into()),
+                ]);
+                ("200 OK", body.to_string())
+            }
+            ("POST", "/api/upload") => {
+                // one at a time, so that the offset checked is the one written at
+                let _lock = uploads.lock().unwrap();
+                let (status, body) = upload(settings, &client.user, &request);
+                println!("{}: {} bytes, {}", client.user, request.body.len(), status);
+                (status, body)
+            }
+            ("GET", "/api/report") => team_report(settings, &request),
+            _ => ("404 Not Found", String::new()),
+        },
+    };
+    let content_type = if status.starts_with('2') || status.starts_with("409") {
+        "application/json"
+    } else {
+        "text/plain"
+    };
+    if let Err(e) = http::write_response(&mut stream, status, content_type, &body) {
+        println!("{}", e);
+    }
+}
+
+// The connections served at a time; the next one is not accepted before one of them is
+// done, so a flood of clients waits in the backlog instead of taking a thread each.
+struct Slots {
+    count: Mutex<usize>,
+    freed: Condvar,
+}
+
+// given back when dropped, even by a handler that panicked
+struct Slot(Arc<Slots>);
+
+impl Slots {
+    fn take(slots: &Arc<Slots>) -> Slot {
+        let mut count = slots.count.lock().unwrap();
+        while *count == MAX_CONNECTIONS {
+            count = slots.freed.wait(count).unwrap();
+        }
+        *count += 1;
+        Slot(slots.clone())
+    }
+}
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        *self.0.count.lock().unwrap() -= 1;
+        self.0.freed.notify_one();
+    }
+}
+
+// runs until the process is ended
+pub fn run(args: &[String]) -> Result<(), CliError> {
+    if let Some(arg) = args.first() {
+        return Err(CliError::usage(&format!("unknown argument: {}", arg)));
+    }
+    let config = Config::load(&config::config_file_name())?;
+    let settings = Arc::new(CollectorSettings::from_config(&config)?);
+    if settings.clients.is_empty() {
+        return Err(CliError::failure("no client in [collector]"));
+    }
+    fs::create_dir_all(&settings.directory)
+        .map_err(|e| format!("{}: {}", settings.directory, e))?;
+    let listener = TcpListener::bind(settings.listen.as_str())
+        .map_err(|e| format!("{}: {}", settings.listen, e))?;
+    println!(
+        "Collecting for {} clients on {} into {}",
+        settings.clients.len(),
+        settings.listen,
+        settings.directory
+    );
+
+    let uploads = Arc::new(Mutex::new(()));
+    let slots = Arc::new(Slots {
+        count: Mutex::new(0),
+        freed: Condvar::new(),
+    });
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(t) => t,
+            Err(e) => {
+                println!("{}", e);
+                continue;
+            }
+        };
+        let slot = Slots::take(&slots);
+        let settings = settings.clone();
+        let uploads = uploads.clone();
+        thread::spawn(move || {
+            handle_connection(stream, &settings, &uploads);
+            drop(slot);
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::Url;
+    use logger::{self, parse_timestamp, Activity};
+    use std::net::TcpListener;
+
+    fn collector(name: &str) -> Arc<CollectorSettings> {
+        let directory = env::temp_dir().join(format!("record-usage-collector-{}", name));
+        fs::remove_dir_all(&directory).ok();
+        fs::create_dir_all(&directory).unwrap();
+        let client = |user: &str, token: &str| Client {
+            user: user.to_string(),
+            token: token.to_string(),
+        };
+        Arc::new(CollectorSettings {
+            listen: String::new(),
+            directory: directory.to_string_lossy().into_owned(),
+            clients: vec![client("alice", "alice-token"), client("bob", "bob-token")],
+        })
+    }
+
+    // one request, served the way run() serves it
+    fn ask(
+        settings: &Arc<CollectorSettings>,
+        method: &str,
+        path: &str,
+        token: &str,
+        body: &[u8],
+    ) -> http::Response {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = Url::parse(&format!(
+            "http://127.0.0.1:{}{}",
+            listener.local_addr().unwrap().port(),
+            path
+        ))
+        .unwrap();
+        let server_settings = settings.clone();
+        let server = thread::spawn(move || {
+            let stream = listener.accept().unwrap().0;
+            handle_connection(stream, &server_settings, &Mutex::new(()));
+        });
+        let authorization = format!("Bearer {}", token);
+        let headers = [("Authorization", authorization.as_str())];
+        let response = http::request(method, &url, &headers, "text/csv", body).unwrap();
+        server.join().unwrap();
+        response
+    }
+
+    // two entries, the first with an environment that spans lines
+    fn log_text() -> String {
+        let entry = |time: &str, environment: Vec<(String, String)>| logger::Entry {
+            timestamp: parse_timestamp(time).unwrap(),
+            duration_in_seconds: 10,
+            activity: Activity {
+                image_path: "C:\\Code.exe".to_string(),
+                environment,
+                ..Default::default()
+            },
+        };
+        let mut text = Vec::new();
+        let environment = vec![
+            ("A".to_string(), "1".to_string()),
+            ("B".to_string(), "2".to_string()),
+        ];
+        logger::write_entry(&mut text, &entry("2026-10-19 9:00:00", environment)).unwrap();
+        logger::write_entry(&mut text, &entry("2026-10-19 9:00:10", Vec::new())).unwrap();
+        String::from_utf8(text).unwrap()
+    }
+
+    #[test]
+    fn clients_from_config() {
+        let parse = |text: &str| {
+            CollectorSettings::from_config(
+                &Config::parse(&format!("[collector]\n{}", text)).unwrap(),
+            )
+        };
+        let settings =
+            parse("listen = 127.0.0.1:9999\ndirectory = D:\\usage\nclient = alice | a\nclient = bob | b\n")
+                .unwrap();
+        assert_eq!(settings.listen, "127.0.0.1:9999");
+        assert_eq!(settings.log_file_name("alice"), "D:\\usage\\alice.csv");
+        assert_eq!(settings.clients.len(), 2);
+        assert_eq!(settings.clients[1].user, "bob");
+        assert_eq!(settings.clients[1].token, "b");
+
+        assert!(parse("client = alice\n").is_err());
+        assert!(parse("client = alice | \n").is_err());
+        assert!(parse("client = ..\\alice | a\n").is_err());
+        assert!(parse("client = .alice | a\n").is_err());
+        assert_eq!(
+            parse("client = alice | a\nclient = bob | a\n")
+                .err()
+                .unwrap(),
+            "[collector] client = bob | a: token already in use"
+        );
+        assert_eq!(
+            parse("client = alice | a\nclient = Alice | b\n")
+                .err()
+                .unwrap(),
+            "[collector] client = Alice | b: user already has a client"
+        );
+        assert!(parse("port = 9090\n").is_err());
+    }
+
+    #[test]
+    fn token_names_the_client() {
+        let settings = collector("token");
+        let response = ask(&settings, "GET", "/api/offset", "bob-token", b"");
+        assert_eq!(response.status, 200);
+        let body = json::parse(&response.body).unwrap();
+        assert_eq!(body.get("user").and_then(|t| t.as_str()), Some("bob"));
+        assert_eq!(body.get("offset").and_then(|t| t.as_u64()), Some(0));
+
+        assert_eq!(
+            ask(&settings, "GET", "/api/offset", "bob-toke", b"").status,
+            401
+        );
+        assert_eq!(
+            ask(&settings, "GET", "/api/offset", "bob-token2", b"").status,
+            401
+        );
+        assert_eq!(ask(&settings, "GET", "/api/offset", "", b"").status, 401);
+    }
+
+    #[test]
+    fn upload_goes_where_the_log_ends() {
+        let settings = collector("offset");
+        let text = log_text();
+        let response = ask(
+            &settings,
+            "POST",
+            "/api/upload?offset=0",
+            "alice-token",
+            text.as_bytes(),
+        );
+        assert_eq!(response.status, 200);
+        assert_eq!(response.body, offset_response(text.len() as u64));
+        assert_eq!(
+            fs::read_to_string(settings.log_file_name("alice")).unwrap(),
+            text
+        );
+
+        // the same piece again is not taken twice
+        let response = ask(
+            &settings,
+            "POST",
+            "/api/upload?offset=0",
+            "alice-token",
+            text.as_bytes(),
+        );
+        assert_eq!(response.status, 409);
+        assert_eq!(response.body, offset_response(text.len() as u64));
+        // nor one from beyond the end
+        let path = format!("/api/upload?offset={}", text.len() + 1);
+        assert_eq!(
+            ask(&settings, "POST", &path, "alice-token", text.as_bytes()).status,
+            409
+        );
+        assert_eq!(
+            ask(
+                &settings,
+                "POST",
+                "/api/upload",
+                "alice-token",
+                text.as_bytes()
+            )
+            .status,
+            400
+        );
+
+        // the other user's log is untouched
+        let response = ask(&settings, "GET", "/api/offset", "bob-token", b"");
+        assert_eq!(
+            json::parse(&response.body)
+                .unwrap()
+                .get("offset")
+                .and_then(|t| t.as_u64()),
+            Some(0)
+        );
+    }
+
+    #[test]
+    fn upload_is_whole_records_of_the_log() {
+        let settings = collector("records");
+        let text = log_text();
+        let upload = |body: &[u8]| {
+            ask(
+                &settings,
+                "POST",
+                "/api/upload?offset=0",
+                "alice-token",
+                body,
+            )
+        };
+
+        // cut at the end of a line inside the quoted environment
+        let inside = text.find("A=1\n").unwrap() + "A=1\n".len();
+        let response = upload(&text.as_bytes()[..inside]);
+        assert_eq!(response.status, 400);
+        assert_eq!(response.body, "expected whole records");
+        assert_eq!(upload(&text.as_bytes()[..text.len() - 1]).status, 400);
+        assert_eq!(upload(b"\xff\n").status, 400);
+        let response = upload(format!("{}garbage\n", text).as_bytes());
+        assert_eq!(response.status, 400);
+        assert_eq!(response.body, "record 3 is not an entry of the log");
+
+        assert_eq!(file_size(&settings.log_file_name("alice")), 0);
+        assert_eq!(upload(text.as_bytes()).status, 200);
+    }
+}
@@ -0,0 +1,141 @@
+//
+// record-usage.ini lives next to the log file:
+//
+//     ; comment
+//     [section]
+//     key = value
+//     key = another value
+//
+// Keys may repeat within a section; every occurrence is kept in order.
+//
+
+use std::env;
+use std::fs;
+use std::io;
+
+pub fn config_file_name() -> String {
+    env::var("LOCALAPPDATA").unwrap() + "\\record-usage.ini"
+}
+
+struct Section {
+    name: String,
+    entries: Vec<(String, String)>,
+}
+
+#[derive(Default)]
+pub struct Config {
+    sections: Vec<Section>,
+}
+
+impl Config {
+    // a missing file is not an error, it just means all defaults
+    pub fn load(file_name: &str) -> Result<Config, String> {
+        match fs::read_to_string(file_name) {
+            Ok(text) => Config::parse(&text).map_err(|e| format!("{}: {}", file_name, e)),
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(Config::default()),
+            Err(e) => Err(format!("{}: {}", file_name, e)),
+        }
+    }
+
+    pub fn parse(text: &str) -> Result<Config, String> {
+        let mut config = Config::default();
+        for (index, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with(';') || line.starts_with('#') {
+                continue;
+            }
+            if line.starts_with('[') {
+                if !line.ends_with(']') {
+                    return Err(format!("line {}: unterminated section name", index + 1));
+                }
+                config.sections.push(Section {
+                    name: line[1..line.len() - 1].trim().to_lowercase(),
+                    entries: Vec::new(),
+                });
+                continue;
+            }
+            let separator = match line.find('=') {
+                Some(t) => t,
+                None => return Err(format!("line {}: expected key = value", index + 1)),
+            };
+            let section = match config.sections.last_mut() {
+                Some(t) => t,
+                None => return Err(format!("line {}: key outside of a section", index + 1)),
+            };
+            section.entries.push((
+                line[..separator].trim().to_lowercase(),
+                line[separator + 1..].trim().to_string(),
+            ));
+        }
+        Ok(config)
+    }
+
+    pub fn has_section(&self, section: &str) -> bool {
+        self.sections.iter().any(|s| s.name == section)
+    }
+
+    pub fn entries<'a>(&'a self, section: &'a str) -> impl Iterator<Item = (&'a str, &'a str)> {
+        self.sections
+            .iter()
+            .filter(move |s| s.name == section)
+            .flat_map(|s| s.entries.iter())
+            .map(|(key, value)| (key.as_str(), value.as_str()))
+    }
+}
+
+pub fn parse_bool(value: &str) -> Option<bool> {
+    match value.to_lowercase().as_str() {
+        "yes" | "true" | "on" | "1" => Some(true),
+        "no" | "false" | "off" | "0" => Some(false),
+        _ => None,
+    }
+}
+
+// "a | b | \" c \"" => ["a", "b", " c "]; quotes keep surrounding whitespace
+pub fn split_list(value: &str) -> Vec<String> {
+    value
+        .split('|')
+        .map(|item| {
+            let item = item.trim();
+            if item.len() >= 2 && item.starts_with('"') && item.ends_with('"') {
+                item[1..item.len() - 1].to_string()
+            } else {
+                item.to_string()
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn list_items_are_trimmed_unless_quoted() {
+        assert_eq!(split_list("a | b | \" c \""), vec!["a", "b", " c "]);
+        assert_eq!(
+            split_list("Code.exe|-2|\" - \""),
+            vec!["Code.exe", "-2", " - "]
+        );
+    }
+
+    #[test]
+    fn list_keeps_empty_items() {
+        assert_eq!(split_list("pwsh.exe"), vec!["pwsh.exe"]);
+        assert_eq!(split_list("a || \"\""), vec!["a", "", ""]);
+        assert_eq!(split_list("\""), vec!["\""]);
+    }
+
+    #[test]
+    fn entries_keep_their_order() {
+        let config =
+            Config::parse("; comment\n[Project]\nTitle = a | b\n\n[other]\nx=1\n").unwrap();
+        assert!(config.has_section("project"));
+        let entries: Vec<_> = config.entries("project").collect();
+        assert_eq!(entries, vec![("title", "a | b")]);
+        assert_eq!(
+            Config::parse("x = 1\n").err().unwrap(),
+            "line 1: key outside of a section"
+        );
+    }
+}
@@ -0,0 +1,183 @@
+use std::borrow::Cow;
+
+// Fields are only quoted when they have to be, so that lines without separators in them
+// keep the plain look of the original four-column format.
+pub fn field<'a>(field: &'a str) -> Cow<'a, str> {
+    if field.contains([',', '"', '\r', '\n']) {
+        Cow::Owned(format!("\"{}\"", field.replace('"', "\"\"")))
+    } else {
+        Cow::Borrowed(field)
+    }
+}
+
+// Fields are separated by ", "; a quoted field may span lines.
+pub fn parse_records(text: &str) -> Vec<Vec<String>> {
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut in_quotes = false;
+    let mut at_field_start = true;
+
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if in_quotes {
+            if c != '"' {
+                field.push(c);
+            } else if chars.peek() == Some(&'"') {
+                field.push('"');
+                chars.next();
+            } else {
+                in_quotes = false;
+            }
+            continue;
+        }
+        match c {
+            ' ' if at_field_start => {}
+            '"' if at_field_start => {
+                in_quotes = true;
+                at_field_start = false;
+            }
+            ',' => {
+                record.push(field.split_off(0));
+                at_field_start = true;
+            }
+            '\r' => {}
+            '\n' => {
+                record.push(field.split_off(0));
+                records.push(record.split_off(0));
+                at_field_start = true;
+            }
+            _ => {
+                field.push(c);
+                at_field_start = false;
+            }
+        }
+    }
+    if !field.is_empty() || !record.is_empty() {
+        record.push(field);
+        records.push(record);
+    }
+    records
+}
+
+// Finds where records end by the rules of parse_records: a newline in a quoted field ends
+// nothing. The text may come a part at a time.
+pub struct RecordScanner {
+    in_quotes: bool,
+    at_field_start: bool,
+    // seen in a quoted field; it ends the field unless another one follows
+    quote: bool,
+}
+
+impl Default for RecordScanner {
+    fn default() -> RecordScanner {
+        RecordScanner {
+            in_quotes: false,
+            at_field_start: true,
+            quote: false,
+        }
+    }
+}
+
+impl RecordScanner {
+    // just past the newline of the last record that ends in the part, if any
+    pub fn scan(&mut self, part: &[u8]) -> Option<usize> {
+        let mut end = None;
+        for (index, &c) in part.iter().enumerate() {
+            if self.quote {
+                self.quote = false;
+                if c == b'"' {
+                    continue;
+                }
+                self.in_quotes = false;
+            }
+            if self.in_quotes {
+                self.quote = c == b'"';
+                continue;
+            }
+            match c {
+                b' ' if self.at_field_start => {}
+                b'"' if self.at_field_start => {
+                    self.in_quotes = true;
+                    self.at_field_start = false;
+                }
+                b',' => self.at_field_start = true,
+                b'\r' => {}
+                b'\n' => {
+                    end = Some(index + 1);
+                    self.at_field_start = true;
+                }
+                _ => self.at_field_start = false,
+            }
+        }
+        end
+    }
+}
+
+// None when no record is complete
+pub fn last_record_end(text: &[u8]) -> Option<usize> {
+    RecordScanner::default().scan(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn doubled_quotes_are_one_quote() {
+        let records = parse_records("1, \"say \"\"hi\"\", then go\", x\n");
+        assert_eq!(records, vec![vec!["1", "say \"hi\", then go", "x"]]);
+    }
+
+    #[test]
+    fn quoted_field_spans_lines() {
+        let records = parse_records("1, \"a\r\nb\", c\n2, d\n");
+        assert_eq!(records, vec![vec!["1", "a\r\nb", "c"], vec!["2", "d"]]);
+    }
+
+    #[test]
+    fn last_record_needs_no_newline() {
+        assert_eq!(parse_records("1, , \"\""), vec![vec!["1", "", ""]]);
+        assert!(parse_records("").is_empty());
+    }
+
+    #[test]
+    fn field_round_trips() {
+        let text = "a, \"b\"\r\nc";
+        let line = format!("{}, {}\n", field(text), field("plain"));
+        assert_eq!(field("plain"), "plain");
+        assert_eq!(parse_records(&line), vec![vec![text, "plain"]]);
+    }
+
+    #[test]
+    fn records_end_outside_quotes() {
+        assert_eq!(last_record_end(b""), None);
+        assert_eq!(last_record_end(b"1, a"), None);
+        assert_eq!(last_record_end(b"1, a\n2, b"), Some(5));
+        assert_eq!(last_record_end(b"1, a\r\n2, b\r\n"), Some(12));
+        // the newlines of a quoted field, even after an escaped quote
+        assert_eq!(last_record_end(b"1, \"a\nb\"\"\nc"), None);
+        assert_eq!(last_record_end(b"1, \"a\nb\"\"\nc\"\n2"), Some(13));
+        // a quote that does not start a field is part of it
+        assert_eq!(last_record_end(b"1, a\"b\n2"), Some(7));
+    }
+
+    #[test]
+    fn records_end_across_parts() {
+        let mut scanner = RecordScanner::default();
+        assert_eq!(scanner.scan(b"1, \"a\n"), None);
+        assert_eq!(scanner.scan(b"b\""), None);
+        assert_eq!(scanner.scan(b"\"\n"), None);
+        assert_eq!(scanner.scan(b"\"\n2, c\n"), Some(7));
+    }
+
+    #[test]
+    fn record_end_agrees_with_parsing() {
+        let text = "1, \"x\ny\", z\n2, \"\"\"\", w\n3, \"open\n";
+        let end = last_record_end(text.as_bytes()).unwrap();
+        assert_eq!(
+            parse_records(&text[..end]),
+            vec![vec!["1", "x\ny", "z"], vec!["2", "\"", "w"]]
+        );
+    }
+}
@@ -0,0 +1,378 @@
+//
+// record-usage export [--format <format>] [--by app|project|site] [--min-minutes <minutes>]
+//                     [--tolerance <seconds>] [--email <address>]
+//                     [--from <yyyy-mm-dd>] [--to <yyyy-mm-dd>] [--file <log file>] [--output <file>]
+//
+// log        the entries as they are, in the format of the log itself
+// ics        an iCalendar event per session
+// toggl      CSV that both Toggl Track and Clockify import, one row per session
+// clockify   the same
+// timesheet  CSV with a row per session and its length in hours
+//
+// Sessions are smoothed with the tolerance of [sessions] unless one is given, and those
+// shorter than the minimum, 5 minutes by default, are left out. Times are local, as in the
+// log; iCalendar events carry no time zone and show at the same wall clock time wherever
+// they are opened.
+//
+
+use cli::{self, CliError};
+use csv;
+use logger::{self, Entry};
+use report::{self, Grouping};
+use session::{self, Session, SessionSettings};
+use sha256;
+use win32helper;
+
+use std::fs::File;
+use std::io::{self, Write};
+
+extern crate winapi;
+use self::winapi::um::minwinbase;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Format {
+    Log,
+    ICalendar,
+    Toggl,
+    Timesheet,
+}
+
+impl Format {
+    pub fn parse(name: &str) -> Option<Format> {
+        match name {
+            "log" | "csv" => Some(Format::Log),
+            "ics" | "icalendar" => Some(Format::ICalendar),
+            "toggl" | "clockify" => Some(Format::Toggl),
+            "timesheet" => Some(Format::Timesheet),
+            _ => None,
+        }
+    }
+}
+
+fn date(time: &minwinbase::SYSTEMTIME) -> String {
+    format!("{}-{:02}-{:02}", time.wYear, time.wMonth, time.wDay)
+}
+
+fn time_of_day(time: &minwinbase::SYSTEMTIME) -> String {
+    format!("{:02}:{:02}:{:02}", time.wHour, time.wMinute, time.wSecond)
+}
+
+// 20261019T093000, the form of DATE-TIME in RFC 5545
+fn ics_date_time(time: &minwinbase::SYSTEMTIME) -> String {
+    format!(
+        "{}{:02}{:02}T{:02}{:02}{:02}",
+        time.wYear, time.wMonth, time.wDay, time.wHour, time.wMinute, time.wSecond
+    )
+}
+
+fn ics_text(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace("\r\n", "\\n")
+        .replace('\n', "\\n")
+}
+
+// lines longer than 75 octets are folded, the continuation starting with a space
+fn write_ics_line<W: Write>(writer: &mut W, line: &str) -> io::Result<()> {
+    let mut length = 0;
+    for c in line.chars() {
+        if length + c.len_utf8() > 75 {
+            writer.write_all(b"\r\n ")?;
+            length = 1;
+        }
+        write!(writer, "{}", c)?;
+        length += c.len_utf8();
+    }
+    writer.write_all(b"\r\n")
+}
+
+// The same session exports to the same identifier whatever the range, so importing again
+// updates. Smoothing may move the end, never the start.
+fn ics_uid(session: &Session) -> String {
+    let key = sha256::to_hex(&sha256::sha256(session.key.as_bytes()));
+    format!(
+        "{}-{}@record-usage",
+        ics_date_time(&session.start),
+        &key[..16]
+    )
+}
+
+fn write_ics<W: Write>(writer: &mut W, sessions: &[Session]) -> io::Result<()> {
+    let now = ics_date_time(&win32helper::get_system_time());
+    write_ics_line(writer, "BEGIN:VCALENDAR")?;
+    write_ics_line(writer, "VERSION:2.0")?;
+    write_ics_line(writer, "PRODID:-//record-usage//export//EN")?;
+    for session in sessions {
+        let start = ics_date_time(&session.start);
+        let mut description = format!(
+            "{} active",
+            report::format_duration(session.duration_in_seconds as u64)
+        );
+        if !session.activity.project.is_empty() && session.activity.project != session.key {
+            description += &format!(", {}", session.activity.project);
+        }
+        write_ics_line(writer, "BEGIN:VEVENT")?;
+        write_ics_line(writer, &format!("UID:{}", ics_uid(session)))?;
+        write_ics_line(writer, &format!("DTSTAMP:{}Z", now))?;
+        write_ics_line(writer, &format!("DTSTART:{}", start))?;
+        write_ics_line(writer, &format!("DTEND:{}", ics_date_time(&session.end)))?;
+        write_ics_line(writer, &format!("SUMMARY:{}", ics_text(&session.key)))?;
+        write_ics_line(writer, &format!("DESCRIPTION:{}", ics_text(&description)))?;
+        write_ics_line(writer, "END:VEVENT")?;
+    }
+    write_ics_line(writer, "END:VCALENDAR")
+}
+
+// Email, Start date, Start time and Duration are what both of them require
+fn write_toggl<W: Write>(writer: &mut W, sessions: &[Session], email: &str) -> io::Result<()> {
+    writeln!(
+        writer,
+        "Email,Project,Description,Start date,Start time,Duration"
+    )?;
+    for session in sessions {
+        writeln!(
+            writer,
+            "{},{},{},{},{},{}",
+            csv::field(email),
+            csv::field(&session.activity.project),
+            csv::field(&session.key),
+            date(&session.start),
+            time_of_day(&session.start),
+            report::format_duration(session.length_in_seconds() as u64)
+        )?;
+    }
+    Ok(())
+}
+
+fn write_timesheet<W: Write>(writer: &mut W, sessions: &[Session]) -> io::Result<()> {
+    writeln!(writer, "Date,Start,End,Hours,Activity,Project")?;
+    for session in sessions {
+        writeln!(
+            writer,
+            "{},{},{},{:.2},{},{}",
+            date(&session.start),
+            time_of_day(&session.start),
+            time_of_day(&session.end),
+            session.length_in_seconds() as f64 / 3600.0,
+            csv::field(&session.key),
+            csv::field(&session.activity.project)
+        )?;
+    }
+    Ok(())
+}
+
+fn write_export<W: Write>(
+    writer: &mut W,
+    format: Format,
+    entries: &[Entry],
+    sessions: &[Session],
+    email: &str,
+) -> io::Result<()> {
+    match format {
+        Format::Log => {
+            for entry in entries {
+                logger::write_entry(writer, entry)?;
+            }
+            Ok(())
+        }
+        Format::ICalendar => write_ics(writer, sessions),
+        Format::Toggl => write_toggl(writer, sessions, email),
+        Format::Timesheet => write_timesheet(writer, sessions),
+    }
+}
+
+pub fn run(args: &[String]) -> Result<(), CliError> {
+    let mut format = Format::Log;
+    let mut grouping = Grouping::Application;
+    let mut min_length_in_minutes = 5;
+    let mut tolerance_in_seconds = None;
+    let mut email = String::new();
+    let mut from = None;
+    let mut to = None;
+    let mut file_name = None;
+    let mut output = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--format" => {
+                let name = args.next().map(|t| t.as_str()).unwrap_or("");
+                format = Format::parse(name)
+                    .ok_or(CliError::usage(&format!("unknown format: {}", name)))?;
+            }
+            "--by" => {
+                let name = args.next().map(|t| t.as_str()).unwrap_or("");
+                grouping = Grouping::parse(name)
+                    .ok_or(CliError::usage(&format!("unknown grouping: {}", name)))?;
+            }
+            "--min-minutes" => {
+                let value = args.next().map(|t| t.as_str()).unwrap_or("");
+                min_length_in_minutes = value.parse().map_err(|_| {
+                    CliError::usage(&format!("--min-minutes {}: expected minutes", value))
+                })?;
+            }
+            "--tolerance" => tolerance_in_seconds = Some(cli::seconds_argument(arg, args.next())?),
+            "--email" => email = args.next().cloned().unwrap_or_default(),
+            "--from" => from = Some(cli::date_argument(arg, args.next())?),
+            "--to" => to = Some(cli::date_argument(arg, args.next())?),
+            "--file" => file_name = Some(cli::file_argument(arg, args.next())?),
+            "--output" => output = Some(cli::file_argument(arg, args.next())?),
+            _ => return Err(CliError::usage(&format!("unknown argument: {}", arg))),
+        }
+    }
+    if format == Format::Toggl && email.is_empty() {
+        return Err(CliError::usage("--format toggl and clockify need --email"));
+    }
+
+    let entries: Vec<Entry> =
+        report::read_entries(&file_name.unwrap_or_else(logger::log_file_name))?
+            .into_iter()
+            .filter(|t| {
+                let date = (t.timestamp.wYear, t.timestamp.wMonth, t.timestamp.wDay);
+                from.is_none_or(|from| date >= from) && to.is_none_or(|to| date <= to)
+            })
+            .collect();
+    let sessions = if format == Format::Log {
+        Vec::new()
+    } else {
+        let tolerance_in_seconds = match tolerance_in_seconds {
+            Some(t) => t,
+            None => SessionSettings::load()?.tolerance_in_seconds,
+        };
+        let mut sessions =
+            session::smooth(session::sessions(&entries, grouping), tolerance_in_seconds);
+        sessions.retain(|t| t.length_in_seconds() >= min_length_in_minutes * 60);
+        sessions
+    };
+
+    let result = match output {
+        Some(ref output) => File::create(output)
+            .and_then(|mut file| write_export(&mut file, format, &entries, &sessions, &email)),
+        None => {
+            let stdout = io::stdout();
+            let mut stdout = stdout.lock();
+            write_export(&mut stdout, format, &entries, &sessions, &email)
+        }
+    };
+    result.map_err(|e| CliError::failure(&format!("{}: {}", output.unwrap_or_default(), e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use logger::{parse_timestamp, Activity};
+
+    fn session(start: &str, end: &str, key: &str, project: &str) -> Session {
+        Session {
+            start: parse_timestamp(start).unwrap(),
+            end: parse_timestamp(end).unwrap(),
+            duration_in_seconds: 0,
+            key: key.to_string(),
+            activity: Activity {
+                project: project.to_string(),
+                ..Default::default()
+            },
+            interruptions: 0,
+        }
+    }
+
+    fn ics_line(line: &str) -> String {
+        let mut buffer = Vec::new();
+        write_ics_line(&mut buffer, line).unwrap();
+        String::from_utf8(buffer).unwrap()
+    }
+
+    #[test]
+    fn ics_lines_fold_at_75_octets() {
+        let line = "x".repeat(160);
+        let folded = ics_line(&line);
+        let lines: Vec<&str> = folded.trim_end_matches("\r\n").split("\r\n").collect();
+        assert_eq!(
+            lines.iter().map(|t| t.len()).collect::<Vec<_>>(),
+            vec![75, 75, 12]
+        );
+        assert!(lines[1].starts_with(' ') && lines[2].starts_with(' '));
+        assert_eq!(folded.replace("\r\n ", ""), line + "\r\n");
+
+        assert_eq!(ics_line(&"x".repeat(75)), "x".repeat(75) + "\r\n");
+    }
+
+    #[test]
+    fn ics_lines_do_not_split_characters() {
+        // two octets each, so the first line ends after 74
+        let line = "\u{e9}".repeat(40);
+        let folded = ics_line(&line);
+        let lines: Vec<&str> = folded.trim_end_matches("\r\n").split("\r\n").collect();
+        assert_eq!(lines[0].len(), 74);
+        assert_eq!(lines[1].len(), 1 + 6);
+        assert_eq!(folded.replace("\r\n ", ""), line + "\r\n");
+    }
+
+    #[test]
+    fn ics_text_is_escaped() {
+        assert_eq!(ics_text("a,b;c\\d"), "a\\,b\\;c\\\\d");
+        assert_eq!(ics_text("one\r\ntwo\nthree"), "one\\ntwo\\nthree");
+    }
+
+    #[test]
+    fn uid_follows_start_and_key() {
+        let uid = ics_uid(&session(
+            "2026-10-19 9:30:00",
+            "2026-10-19 10:00:00",
+            "Code",
+            "",
+        ));
+        assert!(uid.starts_with("20261019T093000-"));
+        assert!(uid.ends_with("@record-usage"));
+        let hash = &uid["20261019T093000-".len()..uid.len() - "@record-usage".len()];
+        assert_eq!(hash.len(), 16);
+        assert!(hash.chars().all(|c| c.is_ascii_hexdigit()));
+
+        // a longer export of the same session keeps its identifier
+        let later_end = session("2026-10-19 9:30:00", "2026-10-19 11:00:00", "Code", "x");
+        assert_eq!(ics_uid(&later_end), uid);
+        let other_key = session("2026-10-19 9:30:00", "2026-10-19 10:00:00", "Firefox", "");
+        assert_ne!(ics_uid(&other_key), uid);
+        let other_start = session("2026-10-20 9:30:00", "2026-10-20 10:00:00", "Code", "");
+        assert_ne!(ics_uid(&other_start), uid);
+    }
+
+    #[test]
+    fn toggl_rows_are_quoted_where_needed() {
+        let sessions = vec![
+            session(
+                "2026-10-19 9:05:00",
+                "2026-10-19 10:35:30",
+                "Code",
+                "monitor",
+            ),
+            session("2026-10-19 11:00:00", "2026-10-19 11:10:00", "a, \"b\"", ""),
+        ];
+        let mut buffer = Vec::new();
+        write_toggl(&mut buffer, &sessions, "me@example.com").unwrap();
+        assert_eq!(
+            String::from_utf8(buffer).unwrap(),
+            "Email,Project,Description,Start date,Start time,Duration\n\
+             me@example.com,monitor,Code,2026-10-19,09:05:00,1:30:30\n\
+             me@example.com,,\"a, \"\"b\"\"\",2026-10-19,11:00:00,0:10:00\n"
+        );
+    }
+
+    #[test]
+    fn timesheet_rows_count_hours() {
+        let sessions = vec![session(
+            "2026-10-19 9:00:00",
+            "2026-10-19 10:30:00",
+            "Code",
+            "monitor",
+        )];
+        let mut buffer = Vec::new();
+        write_timesheet(&mut buffer, &sessions).unwrap();
+        assert_eq!(
+            String::from_utf8(buffer).unwrap(),
+            "Date,Start,End,Hours,Activity,Project\n\
+             2026-10-19,09:00:00,10:30:00,1.50,Code,monitor\n"
+        );
+    }
+}
@@ -0,0 +1,188 @@
+//
+// How fragmented a day was, rather than how long it was:
+//
+// switches         moves from one application to another while active
+// longest block    the longest smoothed session, short interruptions included
+// median session   of the smoothed sessions
+// time to return   how long it takes to get back to an application after leaving it, the
+//                  median over every return before the next break
+// focus score      the percentage of active time spent in blocks of 20 minutes or more
+//
+// Idle time and time the tracker was not running are breaks; nothing is counted across one.
+//
+
+use logger::Entry;
+use report::Grouping;
+use session::{self, Session};
+use win32helper;
+use CHECK_INTERNVAL_IN_SECONDS;
+
+extern crate winapi;
+use self::winapi::um::minwinbase;
+
+const FOCUS_BLOCK_IN_SECONDS: u32 = 20 * 60;
+
+pub struct FocusMetrics {
+    pub active_in_seconds: u64,
+    pub switches: u32,
+    pub longest_block_in_seconds: u32,
+    pub median_session_in_seconds: u32,
+    pub median_time_to_return_in_seconds: Option<u32>,
+    pub focus_score: u32,
+}
+
+impl FocusMetrics {
+    pub fn switches_per_hour(&self) -> f64 {
+        if self.active_in_seconds == 0 {
+            return 0.0;
+        }
+        self.switches as f64 * 3600.0 / self.active_in_seconds as f64
+    }
+}
+
+fn seconds(time: &minwinbase::SYSTEMTIME) -> u64 {
+    win32helper::system_time_to_seconds(time).unwrap_or(0)
+}
+
+fn median(mut values: Vec<u32>) -> Option<u32> {
+    if values.is_empty() {
+        return None;
+    }
+    values.sort();
+    let middle = values.len() / 2;
+    if values.len().is_multiple_of(2) {
+        Some((values[middle - 1] + values[middle]) / 2)
+    } else {
+        Some(values[middle])
+    }
+}
+
+// raw sessions split where there was a break between two of them
+fn stretches(sessions: &[Session]) -> Vec<&[Session]> {
+    let mut stretches = Vec::new();
+    let mut first = 0;
+    for index in 1..sessions.len() {
+        let end = seconds(&sessions[index - 1].end);
+        let start = seconds(&sessions[index].start);
+        if start > end + CHECK_INTERNVAL_IN_SECONDS as u64 {
+            stretches.push(&sessions[first..index]);
+            first = index;
+        }
+    }
+    if first < sessions.len() {
+        stretches.push(&sessions[first..]);
+    }
+    stretches
+}
+
+// entries of one day, in the order they were logged
+pub fn focus_metrics(entries: &[Entry], tolerance_in_seconds: u32) -> FocusMetrics {
+    let raw = session::sessions(entries, Grouping::Application);
+    let smoothed = session::smooth(raw.clone(), tolerance_in_seconds);
+
+    let mut times_to_return = Vec::new();
+    let mut switches = 0;
+    for stretch in stretches(&raw) {
+        switches += stretch.len() as u32 - 1;
+        for (index, left) in stretch.iter().enumerate() {
+            let returned = stretch[index + 1..].iter().find(|t| t.key == left.key);
+            if let Some(returned) = returned {
+                times_to_return
+                    .push(seconds(&returned.start).saturating_sub(seconds(&left.end)) as u32);
+            }
+        }
+    }
+
+    let active_in_seconds: u64 = raw.iter().map(|t| t.duration_in_seconds as u64).sum();
+    let lengths: Vec<u32> = smoothed.iter().map(|t| t.length_in_seconds()).collect();
+    let in_blocks: u64 = lengths
+        .iter()
+        .filter(|&&t| t >= FOCUS_BLOCK_IN_SECONDS)
+        .map(|&t| t as u64)
+        .sum();
+    FocusMetrics {
+        active_in_seconds,
+        switches,
+        longest_block_in_seconds: lengths.iter().cloned().max().unwrap_or(0),
+        median_session_in_seconds: median(lengths).unwrap_or(0),
+        median_time_to_return_in_seconds: median(times_to_return),
+        // smoothing hands interruptions to the blocks, which may then exceed the active time
+        focus_score: (in_blocks * 100)
+            .checked_div(active_in_seconds)
+            .map_or(0, |t| t.min(100) as u32),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use logger::{parse_timestamp, Activity};
+
+    fn entry(time: &str, duration_in_seconds: u32, application: &str) -> Entry {
+        Entry {
+            timestamp: parse_timestamp(&format!("2026-10-19 {}", time)).unwrap(),
+            duration_in_seconds,
+            activity: Activity {
+                product_name: application.to_string(),
+                idle: application.is_empty(),
+                ..Default::default()
+            },
+        }
+    }
+
+    // ten minutes of Code, a glance at the chat, and fifteen more
+    fn glance() -> Vec<Entry> {
+        vec![
+            entry("9:00:00", 600, "Code"),
+            entry("9:10:00", 20, "Chat"),
+            entry("9:10:20", 900, "Code"),
+        ]
+    }
+
+    #[test]
+    fn switches_and_returns_are_counted() {
+        let metrics = focus_metrics(&glance(), 30);
+        assert_eq!(metrics.active_in_seconds, 1520);
+        assert_eq!(metrics.switches, 2);
+        assert_eq!(metrics.median_time_to_return_in_seconds, Some(20));
+        assert!((metrics.switches_per_hour() - 2.0 * 3600.0 / 1520.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn longest_block_takes_in_short_interruptions() {
+        let metrics = focus_metrics(&glance(), 30);
+        assert_eq!(metrics.longest_block_in_seconds, 1520);
+        assert_eq!(metrics.median_session_in_seconds, 1520);
+        assert_eq!(metrics.focus_score, 100);
+
+        // without smoothing neither part of the Code session is a block
+        let metrics = focus_metrics(&glance(), 10);
+        assert_eq!(metrics.longest_block_in_seconds, 900);
+        assert_eq!(metrics.median_session_in_seconds, 600);
+        assert_eq!(metrics.focus_score, 0);
+        assert_eq!(metrics.switches, 2);
+    }
+
+    #[test]
+    fn nothing_is_counted_across_a_break() {
+        let entries = vec![
+            entry("9:00:00", 60, "Code"),
+            entry("9:01:00", 600, ""),
+            entry("9:11:00", 60, "Chat"),
+            entry("9:12:00", 60, "Code"),
+        ];
+        let metrics = focus_metrics(&entries, 30);
+        assert_eq!(metrics.active_in_seconds, 180);
+        assert_eq!(metrics.switches, 1);
+        assert_eq!(metrics.median_time_to_return_in_seconds, None);
+    }
+
+    #[test]
+    fn empty_day() {
+        let metrics = focus_metrics(&[], 30);
+        assert_eq!(metrics.switches, 0);
+        assert_eq!(metrics.longest_block_in_seconds, 0);
+        assert_eq!(metrics.focus_score, 0);
+        assert_eq!(metrics.switches_per_hour(), 0.0);
+    }
+}
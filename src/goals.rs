@@ -0,0 +1,393 @@
+//
+// [goals]
+// ; at_most / at_least = <name> | app, project or site | <value>, <value>, ... | <h:mm a day>
+// at_most  = Social | site | facebook.com, twitter.com, reddit.com | 1:00
+// at_least = Coding | app | Code.exe, devenv.exe | 3:00
+//
+// An app is matched by image name or application name. The value "*" matches anything
+// that is not empty, and a site matches its subdomains as well. Today's totals are checked
+// after every sample, and each goal notifies once a day, when it is crossed.
+//
+
+use config::{self, Config};
+use json;
+use logger::{self, Activity};
+use report::{self, Grouping};
+use win32helper;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum GoalKind {
+    AtMost,
+    AtLeast,
+}
+
+impl GoalKind {
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            GoalKind::AtMost => "at_most",
+            GoalKind::AtLeast => "at_least",
+        }
+    }
+}
+
+#[derive(Clone, PartialEq)]
+pub struct Goal {
+    pub name: String,
+    pub kind: GoalKind,
+    pub grouping: Grouping,
+    pub values: Vec<String>,
+    pub target_in_seconds: u32,
+}
+
+impl Goal {
+    fn matches_value(&self, text: &str, value: &str) -> bool {
+        if text.is_empty() {
+            return false;
+        }
+        if value == "*" || text.eq_ignore_ascii_case(value) {
+            return true;
+        }
+        // "old.reddit.com" for "reddit.com"
+        self.grouping == Grouping::Site
+            && text.len() > value.len()
+            && text
+                .to_lowercase()
+                .ends_with(&format!(".{}", value.to_lowercase()))
+    }
+
+    pub fn matches(&self, activity: &Activity) -> bool {
+        if activity.idle {
+            return false;
+        }
+        self.values.iter().any(|value| match self.grouping {
+            Grouping::Application => {
+                self.matches_value(activity.image_name(), value)
+                    || self.matches_value(activity.application_name(), value)
+            }
+            Grouping::Project => self.matches_value(&activity.project, value),
+            Grouping::Site => self.matches_value(&activity.site, value),
+        })
+    }
+}
+
+// "3:00" or "0:45", in seconds
+fn parse_hours_and_minutes(text: &str) -> Result<u32, String> {
+    let mut parts = text.splitn(2, ':').map(|t| t.trim().parse::<u32>());
+    let (hours, minutes) = match (parts.next(), parts.next()) {
+        (Some(Ok(hours)), Some(Ok(minutes))) if minutes < 60 => (hours, minutes),
+        _ => return Err(format!("{}: expected h:mm", text)),
+    };
+    hours
+        .checked_mul(60)
+        .and_then(|t| t.checked_add(minutes))
+        .and_then(|t| t.checked_mul(60))
+        .ok_or_else(|| format!("{}: too long", text))
+}
+
+pub fn goals_from_config(config: &Config) -> Result<Vec<Goal>, String> {
+    let mut goals = Vec::new();
+    for (key, value) in config.entries("goals") {
+        let error = || {
+            format!(
+                "[goals] {} = {}: expected {} = <name> | app, project or site | <values> | <h:mm>",
+                key, value, key
+            )
+        };
+        let kind = match key {
+            "at_most" => GoalKind::AtMost,
+            "at_least" => GoalKind::AtLeast,
+            _ => return Err(format!("[goals] {}: expected at_most or at_least", key)),
+        };
+        let arguments = config::split_list(value);
+        if arguments.len() != 4 || arguments[0].is_empty() {
+            return Err(error());
+        }
+        goals.push(Goal {
+            name: arguments[0].clone(),
+            kind,
+            grouping: Grouping::parse(&arguments[1]).ok_or_else(error)?,
+            values: arguments[2]
+                .split(',')
+                .map(|t| t.trim().to_string())
+                .filter(|t| !t.is_empty())
+                .collect(),
+            target_in_seconds: parse_hours_and_minutes(&arguments[3])
+                .map_err(|e| format!("[goals] {} = {}: {}", key, value, e))?,
+        });
+    }
+    Ok(goals)
+}
+
+#[derive(Clone)]
+pub struct GoalProgress {
+    pub goal: Goal,
+    pub today_in_seconds: u32,
+    // notified today, or found crossed already
+    pub crossed: bool,
+}
+
+impl GoalProgress {
+    fn is_crossed(&self) -> bool {
+        match self.goal.kind {
+            GoalKind::AtMost => self.today_in_seconds > self.goal.target_in_seconds,
+            GoalKind::AtLeast => self.today_in_seconds >= self.goal.target_in_seconds,
+        }
+    }
+
+    pub fn percent(&self) -> u32 {
+        if self.goal.target_in_seconds == 0 {
+            return 100;
+        }
+        (self.today_in_seconds as u64 * 100 / self.goal.target_in_seconds as u64) as u32
+    }
+}
+
+pub struct Notification {
+    pub title: String,
+    pub text: String,
+    pub progress: GoalProgress,
+}
+
+impl Notification {
+    fn new(progress: &GoalProgress) -> Notification {
+        let goal = &progress.goal;
+        let target = report::format_duration(goal.target_in_seconds as u64);
+        let text = match goal.kind {
+            GoalKind::AtMost => format!("More than {} today", target),
+            GoalKind::AtLeast => format!("{} reached today", target),
+        };
+        Notification {
+            title: goal.name.clone(),
+            text,
+            progress: progress.clone(),
+        }
+    }
+
+    // the members of the goal event
+    pub fn to_json(&self) -> json::Value {
+        let goal = &self.progress.goal;
+        json::object(vec![
+            ("goal", goal.name.as_str().into()),
+            ("kind", goal.kind.as_str().into()),
+            ("target_seconds", (goal.target_in_seconds as u64).into()),
+            (
+                "today_seconds",
+                (self.progress.today_in_seconds as u64).into(),
+            ),
+            ("message", format!("{}: {}", self.title, self.text).into()),
+        ])
+    }
+}
+
+fn today() -> (u16, u16, u16) {
+    let now = win32helper::get_local_time();
+    (now.wYear, now.wMonth, now.wDay)
+}
+
+// today's totals of the goals in use
+pub struct GoalTracker {
+    day: (u16, u16, u16),
+    progress: Vec<GoalProgress>,
+}
+
+impl GoalTracker {
+    pub fn new() -> GoalTracker {
+        GoalTracker {
+            day: today(),
+            progress: Vec::new(),
+        }
+    }
+
+    pub fn progress(&self) -> &[GoalProgress] {
+        &self.progress
+    }
+
+    // Goals that stay keep their totals. New ones start from today's log, which lacks what
+    // is still waiting for a flush, and do not notify for what was crossed before.
+    pub fn set_goals(&mut self, goals: &[Goal]) {
+        let mut entries = None;
+        let mut progress = Vec::with_capacity(goals.len());
+        for goal in goals {
+            if let Some(t) = self.progress.iter().find(|t| t.goal == *goal) {
+                progress.push(t.clone());
+                continue;
+            }
+            let entries = entries.get_or_insert_with(|| {
+                report::read_entries(&logger::log_file_name())
+                    .unwrap_or_default()
+                    .into_iter()
+                    .filter(|t| {
+                        (t.timestamp.wYear, t.timestamp.wMonth, t.timestamp.wDay) == self.day
+                    })
+                    .collect::<Vec<_>>()
+            });
+            let mut t = GoalProgress {
+                goal: goal.clone(),
+                today_in_seconds: entries
+                    .iter()
+                    .filter(|t| goal.matches(&t.activity))
+                    .map(|t| t.duration_in_seconds)
+                    .sum(),
+                crossed: false,
+            };
+            t.crossed = t.is_crossed();
+            progress.push(t);
+        }
+        self.progress = progress;
+    }
+
+    // a sample of the given length was logged; returns the goals it crossed
+    pub fn add_sample(&mut self, activity: &Activity, seconds: u32) -> Vec<Notification> {
+        let day = today();
+        if day != self.day {
+            self.day = day;
+            for t in self.progress.iter_mut() {
+                t.today_in_seconds = 0;
+                t.crossed = false;
+            }
+        }
+
+        let mut notifications = Vec::new();
+        for t in self.progress.iter_mut() {
+            if !t.goal.matches(activity) {
+                continue;
+            }
+            t.today_in_seconds += seconds;
+            if !t.crossed && t.is_crossed() {
+                t.crossed = true;
+                notifications.push(Notification::new(t));
+            }
+        }
+        notifications
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn goals(text: &str) -> Result<Vec<Goal>, String> {
+        goals_from_config(&Config::parse(&format!("[goals]\n{}\n", text)).unwrap())
+    }
+
+    fn goal(grouping: Grouping, values: &[&str]) -> Goal {
+        Goal {
+            name: "Goal".to_string(),
+            kind: GoalKind::AtMost,
+            grouping,
+            values: values.iter().map(|t| t.to_string()).collect(),
+            target_in_seconds: 3600,
+        }
+    }
+
+    #[test]
+    fn hours_and_minutes() {
+        assert_eq!(parse_hours_and_minutes("3:00"), Ok(3 * 3600));
+        assert_eq!(parse_hours_and_minutes(" 0 : 45 "), Ok(45 * 60));
+        assert!(parse_hours_and_minutes("1:60").is_err());
+        assert!(parse_hours_and_minutes("90").is_err());
+        assert!(parse_hours_and_minutes("1:-5").is_err());
+        assert_eq!(
+            parse_hours_and_minutes("1193046:28"),
+            Ok(1_193_046 * 3600 + 28 * 60)
+        );
+        assert_eq!(
+            parse_hours_and_minutes("1193046:29"),
+            Err("1193046:29: too long".to_string())
+        );
+        assert!(parse_hours_and_minutes("4294967295:00").is_err());
+    }
+
+    #[test]
+    fn goals_are_parsed() {
+        let goals = goals(
+            "at_most = Social | site | facebook.com, , reddit.com | 1:00\n\
+             at_least = Coding | app | Code.exe | 3:00",
+        )
+        .unwrap();
+        assert_eq!(goals.len(), 2);
+        assert_eq!(goals[0].name, "Social");
+        assert!(goals[0].kind == GoalKind::AtMost);
+        assert!(goals[0].grouping == Grouping::Site);
+        assert_eq!(goals[0].values, vec!["facebook.com", "reddit.com"]);
+        assert_eq!(goals[0].target_in_seconds, 3600);
+        assert!(goals[1].kind == GoalKind::AtLeast);
+        assert_eq!(goals[1].target_in_seconds, 3 * 3600);
+    }
+
+    #[test]
+    fn bad_goals_are_refused() {
+        assert_eq!(
+            goals("at_some = Social | site | x.com | 1:00")
+                .err()
+                .unwrap(),
+            "[goals] at_some: expected at_most or at_least"
+        );
+        assert!(goals("at_most = Social | site | x.com").is_err());
+        assert!(goals("at_most = | site | x.com | 1:00").is_err());
+        assert!(goals("at_most = Social | window | x.com | 1:00").is_err());
+        assert_eq!(
+            goals("at_most = Social | site | x.com | 99999999:00")
+                .err()
+                .unwrap(),
+            "[goals] at_most = Social | site | x.com | 99999999:00: 99999999:00: too long"
+        );
+    }
+
+    #[test]
+    fn apps_match_by_image_or_application_name() {
+        let goal = goal(Grouping::Application, &["code.exe", "Firefox"]);
+        let mut activity = Activity {
+            image_path: "C:\\VS Code\\Code.exe".to_string(),
+            ..Default::default()
+        };
+        assert!(goal.matches(&activity));
+        activity.image_path = "C:\\firefox\\firefox.exe".to_string();
+        activity.product_name = "Firefox".to_string();
+        assert!(goal.matches(&activity));
+        activity.product_name = "Thunderbird".to_string();
+        assert!(!goal.matches(&activity));
+    }
+
+    #[test]
+    fn sites_match_their_subdomains() {
+        let goal = goal(Grouping::Site, &["reddit.com"]);
+        let site = |site: &str| Activity {
+            site: site.to_string(),
+            ..Default::default()
+        };
+        assert!(goal.matches(&site("reddit.com")));
+        assert!(goal.matches(&site("old.Reddit.com")));
+        assert!(!goal.matches(&site("notreddit.com")));
+        assert!(!goal.matches(&site("")));
+    }
+
+    #[test]
+    fn star_matches_anything_but_nothing_and_idle() {
+        let goal = goal(Grouping::Project, &["*"]);
+        let mut activity = Activity {
+            project: "monitor".to_string(),
+            ..Default::default()
+        };
+        assert!(goal.matches(&activity));
+        activity.idle = true;
+        assert!(!goal.matches(&activity));
+        assert!(!goal.matches(&Activity::default()));
+    }
+
+    #[test]
+    fn progress_is_crossed_by_kind() {
+        let mut progress = GoalProgress {
+            goal: goal(Grouping::Site, &["x.com"]),
+            today_in_seconds: 3600,
+            crossed: false,
+        };
+        assert!(!progress.is_crossed());
+        assert_eq!(progress.percent(), 100);
+        progress.goal.kind = GoalKind::AtLeast;
+        assert!(progress.is_crossed());
+        progress.today_in_seconds = 1800;
+        assert!(!progress.is_crossed());
+        assert_eq!(progress.percent(), 50);
+    }
+}
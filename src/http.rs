@@ -0,0 +1,309 @@
+//
+// Just enough HTTP/1.1 for the webhooks, the collector and its uploader: plain http, one
+// request per connection, bodies sized by Content-Length.
+//
+
+use std::fmt;
+use std::io::{Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+const TIMEOUT_IN_SECONDS: u64 = 10;
+const MAX_HEADER_SIZE: usize = 16 * 1024;
+// the answers we ask for are a few bytes; anything this big is not one of them
+const MAX_RESPONSE_SIZE: usize = 1024 * 1024;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Url {
+    pub host: String,
+    pub port: u16,
+    // with the query, if any
+    pub path: String,
+}
+
+impl Url {
+    // http://host[:port][/path]
+    pub fn parse(url: &str) -> Result<Url, String> {
+        let rest = if url.len() > 7 && url[..7].eq_ignore_ascii_case("http://") {
+            &url[7..]
+        } else {
+            return Err(format!("{}: expected http://<host>[:<port>]/<path>", url));
+        };
+        let (authority, path) = match rest.find('/') {
+            Some(t) => (&rest[..t], &rest[t..]),
+            None => (rest, "/"),
+        };
+        let (host, port) = match authority.rfind(':') {
+            Some(t) => (
+                &authority[..t],
+                authority[t + 1..]
+                    .parse()
+                    .map_err(|_| format!("{}: invalid port", url))?,
+            ),
+            None => (authority, 80),
+        };
+        if host.is_empty() {
+            return Err(format!("{}: no host", url));
+        }
+        Ok(Url {
+            host: host.to_string(),
+            port,
+            path: path.to_string(),
+        })
+    }
+
+    // "/api/upload" on top of "http://collector:9090/usage/" is "/usage/api/upload"
+    pub fn join(&self, path: &str) -> Url {
+        Url {
+            host: self.host.clone(),
+            port: self.port,
+            path: format!("{}{}", self.path.trim_end_matches('/'), path),
+        }
+    }
+}
+
+impl fmt::Display for Url {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "http://{}:{}{}", self.host, self.port, self.path)
+    }
+}
+
+pub struct Response {
+    pub status: u16,
+    pub body: String,
+}
+
+impl Response {
+    pub fn is_success(&self) -> bool {
+        self.status >= 200 && self.status < 300
+    }
+}
+
+// where the blank line that ends the header starts
+fn find_header_end(data: &[u8]) -> Option<usize> {
+    data.windows(4).position(|t| t == b"\r\n\r\n")
+}
+
+fn header_value<'a>(header: &'a str, name: &str) -> Option<&'a str> {
+    header.lines().skip(1).find_map(|line| {
+        let separator = line.find(':')?;
+        if line[..separator].trim().eq_ignore_ascii_case(name) {
+            Some(line[separator + 1..].trim())
+        } else {
+            None
+        }
+    })
+}
+
+// The server closes the connection after answering, so the answer is whatever came.
+pub fn request(
+    method: &str,
+    url: &Url,
+    headers: &[(&str, &str)],
+    content_type: &str,
+    body: &[u8],
+) -> Result<Response, String> {
+    let timeout = Duration::from_secs(TIMEOUT_IN_SECONDS);
+    let address = (url.host.as_str(), url.port)
+        .to_socket_addrs()
+        .map_err(|e| format!("{}: {}", url.host, e))?
+        .next()
+        .ok_or(format!("{}: no address", url.host))?;
+    let mut stream = TcpStream::connect_timeout(&address, timeout)
+        .map_err(|e| format!("{}: {}", url.host, e))?;
+    stream.set_read_timeout(Some(timeout)).ok();
+    stream.set_write_timeout(Some(timeout)).ok();
+
+    let mut request = format!(
+        "{} {} HTTP/1.1\r\nHost: {}:{}\r\nContent-Length: {}\r\nConnection: close\r\n",
+        method,
+        url.path,
+        url.host,
+        url.port,
+        body.len()
+    );
+    if !body.is_empty() {
+        request += &format!("Content-Type: {}\r\n", content_type);
+    }
+    for &(name, value) in headers {
+        request += &format!("{}: {}\r\n", name, value);
+    }
+    request += "\r\n";
+    stream
+        .write_all(request.as_bytes())
+        .and_then(|_| stream.write_all(body))
+        .map_err(|e| format!("{}: {}", url.host, e))?;
+
+    // "HTTP/1.1 204 No Content"
+    let mut answer = Vec::new();
+    stream
+        .take(MAX_RESPONSE_SIZE as u64 + 1)
+        .read_to_end(&mut answer)
+        .map_err(|e| format!("{}: {}", url.host, e))?;
+    if answer.len() > MAX_RESPONSE_SIZE {
+        return Err(format!(
+            "{}: answer of more than {} bytes",
+            url.host, MAX_RESPONSE_SIZE
+        ));
+    }
+    let header_end = find_header_end(&answer).unwrap_or(answer.len());
+    let header = String::from_utf8_lossy(&answer[..header_end]).into_owned();
+    let status = header
+        .split(' ')
+        .nth(1)
+        .and_then(|t| t.parse().ok())
+        .ok_or(format!("{}: not an HTTP answer", url.host))?;
+    Ok(Response {
+        status,
+        body: String::from_utf8_lossy(&answer[(header_end + 4).min(answer.len())..]).into_owned(),
+    })
+}
+
+pub struct Request {
+    pub method: String,
+    // without the query
+    pub path: String,
+    pub query: String,
+    header: String,
+    pub body: Vec<u8>,
+}
+
+impl Request {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        header_value(&self.header, name)
+    }
+
+    // the first value of the parameter, as it is
+    pub fn parameter(&self, name: &str) -> Option<&str> {
+        self.query.split('&').find_map(|t| {
+            let mut pair = t.splitn(2, '=');
+            match (pair.next(), pair.next()) {
+                (Some(key), Some(value)) if key == name => Some(value),
+                _ => None,
+            }
+        })
+    }
+}
+
+// A request with a body of more than the given size is an error.
+pub fn read_request(stream: &mut TcpStream, max_body_size: usize) -> Result<Request, String> {
+    let timeout = Duration::from_secs(TIMEOUT_IN_SECONDS);
+    stream.set_read_timeout(Some(timeout)).ok();
+    stream.set_write_timeout(Some(timeout)).ok();
+
+    let mut data = Vec::new();
+    let mut buffer = [0u8; 4096];
+    let header_end = loop {
+        if let Some(t) = find_header_end(&data) {
+            break t;
+        }
+        if data.len() > MAX_HEADER_SIZE {
+            return Err("header too large".to_string());
+        }
+        let size = stream.read(&mut buffer).map_err(|e| e.to_string())?;
+        if size == 0 {
+            return Err("connection closed".to_string());
+        }
+        data.extend_from_slice(&buffer[..size]);
+    };
+    let header = String::from_utf8_lossy(&data[..header_end]).into_owned();
+    let body_size: usize = match header_value(&header, "Content-Length") {
+        Some(t) => t
+            .parse()
+            .map_err(|_| "invalid Content-Length".to_string())?,
+        None => 0,
+    };
+    if body_size > max_body_size {
+        return Err(format!("body of {} bytes is too large", body_size));
+    }
+    let mut body = data.split_off(header_end + 4);
+    while body.len() < body_size {
+        let size = stream.read(&mut buffer).map_err(|e| e.to_string())?;
+        if size == 0 {
+            return Err("connection closed".to_string());
+        }
+        body.extend_from_slice(&buffer[..size]);
+    }
+    body.truncate(body_size);
+
+    let (method, target) = {
+        let mut parts = header.lines().next().unwrap_or("").split(' ');
+        match (parts.next(), parts.next()) {
+            (Some(method), Some(target)) => (method.to_string(), target.to_string()),
+            _ => return Err("not an HTTP request".to_string()),
+        }
+    };
+    let mut target = target.splitn(2, '?');
+    Ok(Request {
+        method,
+        path: target.next().unwrap_or("").to_string(),
+        query: target.next().unwrap_or("").to_string(),
+        header,
+        body,
+    })
+}
+
+// e.g. "200 OK"; the connection is closed after it
+pub fn write_response(
+    stream: &mut TcpStream,
+    status: &str,
+    content_type: &str,
+    body: &str,
+) -> Result<(), String> {
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    );
+    stream
+        .write_all(response.as_bytes())
+        .and_then(|_| stream.flush())
+        .map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+    use std::thread;
+
+    // answers one request with the given body
+    fn serve_once(body: String) -> (Url, thread::JoinHandle<()>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = Url::parse(&format!(
+            "http://127.0.0.1:{}/api",
+            listener.local_addr().unwrap().port()
+        ))
+        .unwrap();
+        let server = thread::spawn(move || {
+            let mut stream = listener.accept().unwrap().0;
+            read_request(&mut stream, 1024).unwrap();
+            // the client may hang up before the end
+            write_response(&mut stream, "200 OK", "text/plain", &body).ok();
+        });
+        (url, server)
+    }
+
+    #[test]
+    fn answer_is_read() {
+        let (url, server) = serve_once("offset=42".to_string());
+        let response = request("POST", &url, &[], "text/plain", b"x").unwrap();
+        server.join().unwrap();
+        assert_eq!(response.status, 200);
+        assert!(response.is_success());
+        assert_eq!(response.body, "offset=42");
+    }
+
+    #[test]
+    fn oversized_answer_is_refused() {
+        let (url, server) = serve_once("x".repeat(MAX_RESPONSE_SIZE));
+        let error = request("GET", &url, &[], "", b"").err().unwrap();
+        server.join().unwrap();
+        assert_eq!(
+            error,
+            format!("127.0.0.1: answer of more than {} bytes", MAX_RESPONSE_SIZE)
+        );
+    }
+}
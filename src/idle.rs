@@ -0,0 +1,207 @@
+//
+// [idle]
+// ; seconds without keyboard or mouse input before the user counts as away, 0 to disable
+// threshold = 300
+// ; never idle while one of these is in the foreground, e.g. video players and meetings
+// never = vlc.exe | Teams.exe | zoom.exe
+//
+// Time spent away is taken off the entry that was active and logged as an idle entry
+// instead. Watching or presenting produces no input either, so a fullscreen foreground
+// window, or sound from the foreground application, also counts as being there.
+//
+
+use config::{self, Config};
+use logger::Activity;
+use win32helper;
+
+// where the time since the last input comes from; None when it cannot be told
+pub trait IdleSource {
+    fn idle_time_in_seconds(&self) -> Option<u32>;
+}
+
+// GetLastInputInfo covers the whole session, not just our own windows
+pub struct LastInputIdleSource;
+
+impl IdleSource for LastInputIdleSource {
+    fn idle_time_in_seconds(&self) -> Option<u32> {
+        match win32helper::get_idle_time_in_seconds() {
+            Ok(t) => Some(t),
+            Err(e) => {
+                println!("{}", e);
+                None
+            }
+        }
+    }
+}
+
+// why a sample without input still counts as active
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ActiveWithoutInput {
+    NeverIdleApp,
+    Fullscreen,
+    AudioPlayback,
+}
+
+pub struct IdleDetector {
+    source: Box<dyn IdleSource + Send>,
+    threshold_in_seconds: u32,
+    never_idle_image_names: Vec<String>,
+}
+
+impl Default for IdleDetector {
+    fn default() -> IdleDetector {
+        IdleDetector {
+            source: Box::new(LastInputIdleSource),
+            threshold_in_seconds: 300,
+            never_idle_image_names: Vec::new(),
+        }
+    }
+}
+
+impl IdleDetector {
+    pub fn from_config(config: &Config) -> Result<IdleDetector, String> {
+        let mut detector = IdleDetector::default();
+        for (key, value) in config.entries("idle") {
+            match key {
+                "threshold" => {
+                    detector.threshold_in_seconds = value
+                        .parse()
+                        .map_err(|_| format!("[idle] {} = {}: expected seconds", key, value))?
+                }
+                "never" => detector.never_idle_image_names.extend(
+                    config::split_list(value)
+                        .into_iter()
+                        .filter(|t| !t.is_empty()),
+                ),
+                _ => return Err(format!("[idle] {}: unknown setting", key)),
+            }
+        }
+        Ok(detector)
+    }
+
+    // how long the user has been away, once that is past the threshold
+    pub fn idle_time_in_seconds(&self) -> Option<u32> {
+        if self.threshold_in_seconds == 0 {
+            return None;
+        }
+        self.source
+            .idle_time_in_seconds()
+            .filter(|&t| t >= self.threshold_in_seconds)
+    }
+
+    // Cheapest first; the audio sessions are only asked for when nothing else settles it.
+    // The window is the foreground one, before any universal app lookup.
+    pub fn active_without_input(
+        &self,
+        window_handle: Option<win32helper::HWND>,
+        activity: &Activity,
+    ) -> Option<ActiveWithoutInput> {
+        let image_name = activity.image_name();
+        if image_name.is_empty() {
+            return None;
+        }
+        if self
+            .never_idle_image_names
+            .iter()
+            .any(|t| t.eq_ignore_ascii_case(image_name))
+        {
+            return Some(ActiveWithoutInput::NeverIdleApp);
+        }
+        if window_handle.is_some_and(win32helper::is_fullscreen_window) {
+            return Some(ActiveWithoutInput::Fullscreen);
+        }
+        match win32helper::get_audio_playing_image_paths() {
+            Ok(image_paths) => {
+                let is_playing = image_paths.iter().any(|path| {
+                    let name = path.rsplit('\\').next().unwrap_or("");
+                    name.eq_ignore_ascii_case(image_name)
+                });
+                if is_playing {
+                    return Some(ActiveWithoutInput::AudioPlayback);
+                }
+            }
+            Err(e) => println!("{}", e),
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct FixedIdleSource(Option<u32>);
+
+    impl IdleSource for FixedIdleSource {
+        fn idle_time_in_seconds(&self) -> Option<u32> {
+            self.0
+        }
+    }
+
+    fn detector(threshold_in_seconds: u32, idle_time: Option<u32>) -> IdleDetector {
+        IdleDetector {
+            source: Box::new(FixedIdleSource(idle_time)),
+            threshold_in_seconds,
+            never_idle_image_names: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn idle_once_past_the_threshold() {
+        assert_eq!(detector(300, Some(299)).idle_time_in_seconds(), None);
+        assert_eq!(detector(300, Some(300)).idle_time_in_seconds(), Some(300));
+        assert_eq!(detector(300, Some(4000)).idle_time_in_seconds(), Some(4000));
+        assert_eq!(detector(300, None).idle_time_in_seconds(), None);
+    }
+
+    #[test]
+    fn zero_threshold_disables_detection() {
+        assert_eq!(detector(0, Some(4000)).idle_time_in_seconds(), None);
+        assert_eq!(detector(0, Some(0)).idle_time_in_seconds(), None);
+    }
+
+    #[test]
+    fn config_sets_threshold_and_apps() {
+        let config = Config::parse(
+            "[idle]\nthreshold = 60\nnever = vlc.exe | | Teams.exe\nnever = zoom.exe\n",
+        )
+        .unwrap();
+        let detector = IdleDetector::from_config(&config).unwrap();
+        assert_eq!(detector.threshold_in_seconds, 60);
+        assert_eq!(
+            detector.never_idle_image_names,
+            vec!["vlc.exe", "Teams.exe", "zoom.exe"]
+        );
+
+        let activity = Activity {
+            image_path: "C:\\Program Files\\VideoLAN\\VLC.EXE".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(
+            detector.active_without_input(None, &activity),
+            Some(ActiveWithoutInput::NeverIdleApp)
+        );
+        assert_eq!(
+            detector.active_without_input(None, &Activity::default()),
+            None
+        );
+    }
+
+    #[test]
+    fn config_defaults_and_errors() {
+        let detector = IdleDetector::from_config(&Config::default()).unwrap();
+        assert_eq!(detector.threshold_in_seconds, 300);
+        assert!(detector.never_idle_image_names.is_empty());
+
+        let bad = Config::parse("[idle]\nthreshold = 5m\n").unwrap();
+        assert_eq!(
+            IdleDetector::from_config(&bad).err().unwrap(),
+            "[idle] threshold = 5m: expected seconds"
+        );
+        let unknown = Config::parse("[idle]\nafter = 5\n").unwrap();
+        assert_eq!(
+            IdleDetector::from_config(&unknown).err().unwrap(),
+            "[idle] after: unknown setting"
+        );
+    }
+}
@@ -0,0 +1,299 @@
+//
+// The control channel of the running tracker: a named pipe that takes one request per
+// connection and answers it. Each message is a JSON object preceded by its length in
+// bytes, as a little-endian u32.
+//
+//   -> {"command": "status"}
+//   <- {"ok": true, "paused": false, "idle": false, "application": "...", "today_in_seconds": 9000}
+//   <- {"ok": false, "error": "..."}
+//
+// The framing and the dispatch work on any Read + Write, the pipe is only the transport.
+//
+
+use json::{self, Value};
+use win32helper;
+
+use std::env;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::mem;
+use std::os::windows::io::{FromRawHandle, RawHandle};
+use std::sync::Arc;
+use std::thread;
+
+extern crate winapi;
+use self::winapi::shared::winerror;
+
+// a request is a few bytes, this is only there to refuse garbage
+const MAX_MESSAGE_LENGTH: u32 = 1024 * 1024;
+const PIPE_BUSY_TIMEOUT_IN_MILLISECONDS: u32 = 5000;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Command {
+    Status,
+    Pause,
+    Resume,
+    Flush,
+    Reload,
+    Stop,
+}
+
+impl Command {
+    pub fn parse(name: &str) -> Option<Command> {
+        match name {
+            "status" => Some(Command::Status),
+            "pause" => Some(Command::Pause),
+            "resume" => Some(Command::Resume),
+            "flush" => Some(Command::Flush),
+            "reload" => Some(Command::Reload),
+            "stop" => Some(Command::Stop),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Command::Status => "status",
+            Command::Pause => "pause",
+            Command::Resume => "resume",
+            Command::Flush => "flush",
+            Command::Reload => "reload",
+            Command::Stop => "stop",
+        }
+    }
+}
+
+// Pipe names are visible to the whole machine, the user name keeps sessions of different
+// users apart. The pipe gets the default security, so nobody else can write to it.
+pub fn pipe_name() -> String {
+    format!(
+        "\\\\.\\pipe\\record-usage-{}",
+        env::var("USERNAME").unwrap_or_default()
+    )
+}
+
+pub fn ok(members: Vec<(&str, Value)>) -> Value {
+    let mut response = vec![("ok", Value::Bool(true))];
+    response.extend(members);
+    json::object(response)
+}
+
+pub fn error(message: &str) -> Value {
+    json::object(vec![("ok", Value::Bool(false)), ("error", message.into())])
+}
+
+pub fn write_message<W: Write>(writer: &mut W, message: &Value) -> io::Result<()> {
+    let text = message.to_string();
+    let length = text.len() as u32;
+    let header = [
+        length as u8,
+        (length >> 8) as u8,
+        (length >> 16) as u8,
+        (length >> 24) as u8,
+    ];
+    writer.write_all(&header)?;
+    writer.write_all(text.as_bytes())?;
+    writer.flush()
+}
+
+pub fn read_message<R: Read>(reader: &mut R) -> io::Result<Value> {
+    let mut header = [0u8; 4];
+    reader.read_exact(&mut header)?;
+    let length = header[0] as u32
+        | (header[1] as u32) << 8
+        | (header[2] as u32) << 16
+        | (header[3] as u32) << 24;
+    if length > MAX_MESSAGE_LENGTH {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("message of {} bytes", length),
+        ));
+    }
+    let mut buffer = vec![0u8; length as usize];
+    reader.read_exact(&mut buffer)?;
+    let text =
+        String::from_utf8(buffer).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    json::parse(&text).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+// one request and its answer; a request that is not understood is answered, not dropped
+pub fn handle_connection<S: Read + Write>(
+    stream: &mut S,
+    handler: &dyn Fn(Command) -> Value,
+) -> io::Result<()> {
+    let request = read_message(stream)?;
+    let name = request
+        .get("command")
+        .and_then(|t| t.as_str())
+        .unwrap_or("");
+    let response = match Command::parse(name) {
+        Some(command) => handler(command),
+        None => error(&format!("unknown command: {}", name)),
+    };
+    write_message(stream, &response)
+}
+
+// The next instance of the pipe is created before a client is served, so that there is
+// always one to connect to while we are running. Each client is served on a thread of its
+// own, where the handler runs, so that one that never sends its request holds up nobody
+// else.
+pub fn start_server<H>(handler: H) -> win32helper::Win32Result<()>
+where
+    H: Fn(Command) -> Value + Send + Sync + 'static,
+{
+    let name = pipe_name();
+    // fails when somebody else already has the name
+    let mut next = win32helper::create_named_pipe(&name, true)?;
+    let handler = Arc::new(handler);
+    thread::spawn(move || loop {
+        if let Err(e) = win32helper::connect_named_pipe(&next) {
+            println!("{}", e);
+            return;
+        }
+        let pipe = match win32helper::create_named_pipe(&name, false) {
+            Ok(t) => mem::replace(&mut next, t),
+            Err(e) => {
+                println!("{}", e);
+                return;
+            }
+        };
+        let mut stream = unsafe { File::from_raw_handle(pipe.into_raw() as RawHandle) };
+        let handler = handler.clone();
+        thread::spawn(move || {
+            if let Err(e) = handle_connection(&mut stream, &*handler) {
+                println!("ipc: {}", e);
+            }
+            // the client must have read everything before our end goes away
+            stream.sync_all().ok();
+        });
+    });
+    Ok(())
+}
+
+// The answer of the running instance, None when there is none. An answer with "ok": false
+// comes back as the error it carries.
+pub fn send_command(command: Command) -> Result<Option<Value>, String> {
+    let name = pipe_name();
+    let pipe = loop {
+        match win32helper::open_named_pipe(&name) {
+            Ok(t) => break t,
+            Err(ref e)
+                if e.code == win32helper::ErrorCode::LastError(winerror::ERROR_FILE_NOT_FOUND) =>
+            {
+                return Ok(None)
+            }
+            // every instance is serving somebody else
+            Err(ref e)
+                if e.code == win32helper::ErrorCode::LastError(winerror::ERROR_PIPE_BUSY) =>
+            {
+                win32helper::wait_named_pipe(&name, PIPE_BUSY_TIMEOUT_IN_MILLISECONDS)
+                    .map_err(|e| e.to_string())?;
+            }
+            Err(e) => return Err(e.to_string()),
+        }
+    };
+    let mut stream = unsafe { File::from_raw_handle(pipe.into_raw() as RawHandle) };
+
+    let request = json::object(vec![("command", command.name().into())]);
+    let response = write_message(&mut stream, &request)
+        .and_then(|_| read_message(&mut stream))
+        .map_err(|e| format!("{}: {}", name, e))?;
+    if response.get("ok").and_then(|t| t.as_bool()) != Some(true) {
+        let message = response.get("error").and_then(|t| t.as_str());
+        return Err(message.unwrap_or("no answer").to_string());
+    }
+    Ok(Some(response))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    // a connection whose request is read from one buffer and whose answer goes to another
+    struct Connection {
+        request: Cursor<Vec<u8>>,
+        response: Vec<u8>,
+    }
+
+    impl Read for Connection {
+        fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+            self.request.read(buffer)
+        }
+    }
+
+    impl Write for Connection {
+        fn write(&mut self, buffer: &[u8]) -> io::Result<usize> {
+            self.response.write(buffer)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn request(command: &str) -> Connection {
+        let mut request = Vec::new();
+        write_message(
+            &mut request,
+            &json::object(vec![("command", command.into())]),
+        )
+        .unwrap();
+        Connection {
+            request: Cursor::new(request),
+            response: Vec::new(),
+        }
+    }
+
+    fn answer(command: Command) -> Value {
+        ok(vec![("command", command.name().into())])
+    }
+
+    #[test]
+    fn message_round_trip() {
+        let message = json::object(vec![("command", "status".into()), ("n", 3u64.into())]);
+        let mut buffer = Cursor::new(Vec::new());
+        write_message(&mut buffer, &message).unwrap();
+        assert_eq!(buffer.get_ref().len(), 4 + message.to_string().len());
+        buffer.set_position(0);
+        assert_eq!(read_message(&mut buffer).unwrap(), message);
+    }
+
+    #[test]
+    fn oversized_message_is_refused() {
+        let length = MAX_MESSAGE_LENGTH + 1;
+        let mut data = vec![
+            length as u8,
+            (length >> 8) as u8,
+            (length >> 16) as u8,
+            (length >> 24) as u8,
+        ];
+        data.extend_from_slice(b"{}");
+        let error = read_message(&mut Cursor::new(data)).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn known_command_is_handled() {
+        let mut connection = request("pause");
+        handle_connection(&mut connection, &answer).unwrap();
+        let response = read_message(&mut Cursor::new(connection.response)).unwrap();
+        assert_eq!(response.get("ok").and_then(|t| t.as_bool()), Some(true));
+        assert_eq!(
+            response.get("command").and_then(|t| t.as_str()),
+            Some("pause")
+        );
+    }
+
+    #[test]
+    fn unknown_command_is_answered() {
+        let mut connection = request("dance");
+        handle_connection(&mut connection, &|_| panic!("not a command")).unwrap();
+        let response = read_message(&mut Cursor::new(connection.response)).unwrap();
+        assert_eq!(response.get("ok").and_then(|t| t.as_bool()), Some(false));
+        assert_eq!(
+            response.get("error").and_then(|t| t.as_str()),
+            Some("unknown command: dance")
+        );
+    }
+}
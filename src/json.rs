@@ -0,0 +1,387 @@
+use std::fmt;
+
+// Just enough JSON for the control channel and the web server; numbers are kept as f64,
+// which holds any count of seconds this program will ever see.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Value>),
+    // in the order written, keys are few enough that a search does
+    Object(Vec<(String, Value)>),
+}
+
+impl Value {
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match *self {
+            Value::Object(ref members) => members.iter().find(|t| t.0 == key).map(|t| &t.1),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match *self {
+            Value::String(ref t) => Some(t),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match *self {
+            Value::Bool(t) => Some(t),
+            _ => None,
+        }
+    }
+
+    pub fn as_u64(&self) -> Option<u64> {
+        match *self {
+            Value::Number(t) if t >= 0.0 && t.fract() == 0.0 => Some(t as u64),
+            _ => None,
+        }
+    }
+}
+
+impl From<bool> for Value {
+    fn from(value: bool) -> Value {
+        Value::Bool(value)
+    }
+}
+
+impl From<u64> for Value {
+    fn from(value: u64) -> Value {
+        Value::Number(value as f64)
+    }
+}
+
+impl<'a> From<&'a str> for Value {
+    fn from(value: &'a str) -> Value {
+        Value::String(value.to_string())
+    }
+}
+
+impl From<String> for Value {
+    fn from(value: String) -> Value {
+        Value::String(value)
+    }
+}
+
+// {"key": value, ...} from pairs
+pub fn object(members: Vec<(&str, Value)>) -> Value {
+    Value::Object(
+        members
+            .into_iter()
+            .map(|(key, value)| (key.to_string(), value))
+            .collect(),
+    )
+}
+
+fn write_string(f: &mut fmt::Formatter, text: &str) -> fmt::Result {
+    write!(f, "\"")?;
+    for c in text.chars() {
+        match c {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\n' => write!(f, "\\n")?,
+            '\r' => write!(f, "\\r")?,
+            '\t' => write!(f, "\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{}", c)?,
+        }
+    }
+    write!(f, "\"")
+}
+
+// compact, without any white space
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Value::Null => write!(f, "null"),
+            Value::Bool(t) => write!(f, "{}", t),
+            // JSON has neither NaN nor infinity
+            Value::Number(t) if !t.is_finite() => write!(f, "null"),
+            Value::Number(t) if t.fract() == 0.0 && t.abs() < 1e15 => write!(f, "{}", t as i64),
+            Value::Number(t) => write!(f, "{}", t),
+            Value::String(ref t) => write_string(f, t),
+            Value::Array(ref values) => {
+                write!(f, "[")?;
+                for (index, value) in values.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}", value)?;
+                }
+                write!(f, "]")
+            }
+            Value::Object(ref members) => {
+                write!(f, "{{")?;
+                for (index, (key, value)) in members.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, ",")?;
+                    }
+                    write_string(f, key)?;
+                    write!(f, ":{}", value)?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+// deep enough for anything we read, shallow enough for the stack of any thread
+const MAX_DEPTH: usize = 64;
+
+struct Parser<'a> {
+    text: &'a str,
+    position: usize,
+    // arrays and objects the parser is in
+    depth: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn error<T>(&self, message: &str) -> Result<T, String> {
+        Err(format!("{} at {}", message, self.position))
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.text[self.position..].chars().next()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.position += c.len_utf8();
+        Some(c)
+    }
+
+    fn skip_white_space(&mut self) {
+        while let Some(' ') | Some('\t') | Some('\r') | Some('\n') = self.peek() {
+            self.position += 1;
+        }
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), String> {
+        self.skip_white_space();
+        if self.next() != Some(c) {
+            return self.error(&format!("expected '{}'", c));
+        }
+        Ok(())
+    }
+
+    fn keyword(&mut self, keyword: &str, value: Value) -> Result<Value, String> {
+        if !self.text[self.position..].starts_with(keyword) {
+            return self.error("unexpected character");
+        }
+        self.position += keyword.len();
+        Ok(value)
+    }
+
+    fn value(&mut self) -> Result<Value, String> {
+        self.skip_white_space();
+        match self.peek() {
+            Some('n') => self.keyword("null", Value::Null),
+            Some('t') => self.keyword("true", Value::Bool(true)),
+            Some('f') => self.keyword("false", Value::Bool(false)),
+            Some('"') => self.string().map(Value::String),
+            Some('[') => self.array(),
+            Some('{') => self.object(),
+            Some(c) if c == '-' || c.is_ascii_digit() => self.number(),
+            Some(_) => self.error("unexpected character"),
+            None => self.error("unexpected end"),
+        }
+    }
+
+    fn number(&mut self) -> Result<Value, String> {
+        let start = self.position;
+        while let Some(c) = self.peek() {
+            if !(c.is_ascii_digit() || c == '-' || c == '+' || c == '.' || c == 'e' || c == 'E') {
+                break;
+            }
+            self.position += 1;
+        }
+        match self.text[start..self.position].parse() {
+            Ok(t) => Ok(Value::Number(t)),
+            Err(_) => self.error("invalid number"),
+        }
+    }
+
+    // from_str_radix alone would take a sign as well
+    fn hex4(&mut self) -> Result<u16, String> {
+        let digits = self
+            .text
+            .get(self.position..self.position + 4)
+            .unwrap_or("");
+        if digits.len() != 4 || !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+            return self.error("invalid \\u escape");
+        }
+        self.position += 4;
+        Ok(u16::from_str_radix(digits, 16).unwrap())
+    }
+
+    // a character outside the BMP comes as a surrogate pair, and only as one
+    fn unicode_escape(&mut self) -> Result<char, String> {
+        let high = self.hex4()? as u32;
+        if (0xdc00..0xe000).contains(&high) {
+            return self.error("unpaired surrogate");
+        }
+        if !(0xd800..0xdc00).contains(&high) {
+            return Ok(std::char::from_u32(high).unwrap());
+        }
+        if !self.text[self.position..].starts_with("\\u") {
+            return self.error("unpaired surrogate");
+        }
+        self.position += 2;
+        let low = self.hex4()? as u32;
+        if !(0xdc00..0xe000).contains(&low) {
+            return self.error("unpaired surrogate");
+        }
+        let code = 0x10000 + ((high - 0xd800) << 10) + (low - 0xdc00);
+        Ok(std::char::from_u32(code).unwrap())
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.expect('"')?;
+        let mut text = String::new();
+        loop {
+            match self.next() {
+                Some('"') => return Ok(text),
+                Some('\\') => {
+                    let c = match self.next() {
+                        Some('"') => '"',
+                        Some('\\') => '\\',
+                        Some('/') => '/',
+                        Some('b') => '\u{8}',
+                        Some('f') => '\u{c}',
+                        Some('n') => '\n',
+                        Some('r') => '\r',
+                        Some('t') => '\t',
+                        Some('u') => self.unicode_escape()?,
+                        _ => return self.error("invalid escape"),
+                    };
+                    text.push(c);
+                }
+                Some(c) => text.push(c),
+                None => return self.error("unterminated string"),
+            }
+        }
+    }
+
+    fn enter(&mut self) -> Result<(), String> {
+        if self.depth == MAX_DEPTH {
+            return self.error("nested too deeply");
+        }
+        self.depth += 1;
+        Ok(())
+    }
+
+    fn array(&mut self) -> Result<Value, String> {
+        self.enter()?;
+        let value = self.array_values();
+        self.depth -= 1;
+        value
+    }
+
+    fn array_values(&mut self) -> Result<Value, String> {
+        self.expect('[')?;
+        let mut values = Vec::new();
+        self.skip_white_space();
+        if self.peek() == Some(']') {
+            self.position += 1;
+            return Ok(Value::Array(values));
+        }
+        loop {
+            values.push(self.value()?);
+            self.skip_white_space();
+            match self.next() {
+                Some(',') => {}
+                Some(']') => return Ok(Value::Array(values)),
+                _ => return self.error("expected ',' or ']'"),
+            }
+        }
+    }
+
+    fn object(&mut self) -> Result<Value, String> {
+        self.enter()?;
+        let value = self.object_members();
+        self.depth -= 1;
+        value
+    }
+
+    fn object_members(&mut self) -> Result<Value, String> {
+        self.expect('{')?;
+        let mut members = Vec::new();
+        self.skip_white_space();
+        if self.peek() == Some('}') {
+            self.position += 1;
+            return Ok(Value::Object(members));
+        }
+        loop {
+            self.skip_white_space();
+            let key = self.string()?;
+            self.expect(':')?;
+            members.push((key, self.value()?));
+            self.skip_white_space();
+            match self.next() {
+                Some(',') => {}
+                Some('}') => return Ok(Value::Object(members)),
+                _ => return self.error("expected ',' or '}'"),
+            }
+        }
+    }
+}
+
+pub fn parse(text: &str) -> Result<Value, String> {
+    let mut parser = Parser {
+        text,
+        position: 0,
+        depth: 0,
+    };
+    let value = parser.value()?;
+    parser.skip_white_space();
+    if parser.position < text.len() {
+        return parser.error("trailing characters");
+    }
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escapes() {
+        let value = parse(r#""a\"\\\/\b\f\n\r\t\u00e9""#).unwrap();
+        assert_eq!(value.as_str(), Some("a\"\\/\u{8}\u{c}\n\r\t\u{e9}"));
+        assert!(parse(r#""\x""#).is_err());
+        assert!(parse(r#""\u12""#).is_err());
+        assert!(parse(r#""\u+0e9""#).is_err());
+        assert_eq!(parse(r#""\u00E9""#).unwrap().as_str(), Some("\u{e9}"));
+    }
+
+    #[test]
+    fn surrogate_pairs() {
+        let value = parse(r#""\ud83d\ude00""#).unwrap();
+        assert_eq!(value.as_str(), Some("\u{1f600}"));
+        // a lone surrogate is no character
+        assert!(parse(r#""\ud83d""#).is_err());
+        assert!(parse(r#""\ud83d\u0041""#).is_err());
+        assert!(parse(r#""\ude00\ud83d""#).is_err());
+        assert!(parse(r#""\ud83dx""#).is_err());
+    }
+
+    #[test]
+    fn trailing_garbage() {
+        assert!(parse("{\"a\": 1} ").is_ok());
+        assert!(parse("{\"a\": 1} x").is_err());
+        assert!(parse("[1, 2]]").is_err());
+    }
+
+    #[test]
+    fn depth_is_limited() {
+        let nested = |depth: usize| "[".repeat(depth) + &"]".repeat(depth);
+        assert!(parse(&nested(MAX_DEPTH)).is_ok());
+        assert!(parse(&nested(MAX_DEPTH + 1)).is_err());
+        assert!(parse(&"[".repeat(1000000)).is_err());
+        assert!(parse(&"{\"a\":".repeat(1000000)).is_err());
+    }
+}
@@ -1,18 +1,109 @@
+#[derive(Clone, Default, PartialEq)]
+pub struct Activity {
+    pub window_title: String,
+    pub command_line: String,
+    pub image_path: String,
+    pub product_name: String,
+    pub company_name: String,
+    pub working_directory: String,
+    pub parent_process_id: u32,
+    pub project: String,
+    pub page_title: String,
+    pub site: String,
+    pub package_family_name: String,
+    pub app_user_model_id: String,
+    pub process_access: win32helper::ProcessAccess,
+    pub not_responding: bool,
+    // the allowlisted variables of [environment] that the process has set
+    pub environment: Vec<(String, String)>,
+    // nobody was there; everything else is left empty
+    pub idle: bool,
+}
+
+impl Activity {
+    pub fn image_name(&self) -> &str {
+        match self.image_path.rfind('\\') {
+            Some(t) => &self.image_path[t + 1..],
+            None => &self.image_path,
+        }
+    }
+
+    // Why the process could not be read in full: limited access almost always means an
+    // elevated process, while only protected ones are out of reach of that as well.
+    pub fn access_note(&self) -> Option<&'static str> {
+        match self.process_access {
+            win32helper::ProcessAccess::Limited => Some("elevated app"),
+            win32helper::ProcessAccess::Snapshot => Some("protected app"),
+            _ => None,
+        }
+    }
+
+    // what reports group by when asked for applications
+    pub fn application_name(&self) -> &str {
+        if !self.product_name.is_empty() {
+            &self.product_name
+        } else if !self.image_path.is_empty() {
+            self.image_name()
+        } else {
+            &self.command_line
+        }
+    }
+
+    // "NAME=value" lines, which is how the log stores them
+    pub fn environment_text(&self) -> String {
+        self.environment
+            .iter()
+            .map(|(name, value)| format!("{}={}", name, value))
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+
+    pub fn parse_environment(text: &str) -> Vec<(String, String)> {
+        text.lines()
+            .filter_map(|line| {
+                line.find('=')
+                    .map(|t| (line[..t].to_string(), line[t + 1..].to_string()))
+            })
+            .collect()
+    }
+}
+
 pub trait Log {
-    fn log(&self, window_title: String, command_line: String);
-    fn get_last_entry(&self) -> (String, String);
+    fn log(&self, activity: Activity);
+    fn log_idle(&self, idle_in_seconds: u32);
+    fn get_last_entry(&self) -> Activity;
+    // tracked time since midnight, not counting idle time
+    fn get_today_in_seconds(&self) -> u32;
+    fn flush(&self);
 }
 
-pub fn log(window_title: String, command_line: String) {
+pub fn log(activity: Activity) {
     let logger = unsafe { &*LOGGER };
-    logger.log(window_title, command_line);
+    logger.log(activity);
 }
 
-pub fn get_last_entry() -> (String, String) {
+pub fn log_idle(idle_in_seconds: u32) {
+    let logger = unsafe { &*LOGGER };
+    logger.log_idle(idle_in_seconds);
+}
+
+// The logger is not thread safe; the sampler thread logs, and shares what the others need
+// through Sampler::status.
+pub fn get_last_entry() -> Activity {
     let logger = unsafe { &*LOGGER };
     logger.get_last_entry()
 }
 
+pub fn get_today_in_seconds() -> u32 {
+    let logger = unsafe { &*LOGGER };
+    logger.get_today_in_seconds()
+}
+
+pub fn flush() {
+    let logger = unsafe { &*LOGGER };
+    logger.flush();
+}
+
 pub fn set_logger<M>(make_logger: M)
 where
     M: FnOnce() -> Box<Log>,
@@ -26,10 +117,15 @@ static mut LOGGER: *const Log = &NopLogger;
 
 struct NopLogger;
 impl Log for NopLogger {
-    fn log(&self, _: String, _: String) {}
-    fn get_last_entry(&self) -> (String, String) {
-        ("".to_string(), "".to_string())
+    fn log(&self, _: Activity) {}
+    fn log_idle(&self, _: u32) {}
+    fn get_last_entry(&self) -> Activity {
+        Activity::default()
     }
+    fn get_today_in_seconds(&self) -> u32 {
+        0
+    }
+    fn flush(&self) {}
 }
 
 ////////////////////////////////////////////////////////////////////////////////////////
@@ -38,15 +134,154 @@ extern crate winapi;
 
 use self::winapi::{um::minwinbase, um::winnt};
 
+use super::csv;
+use super::metrics;
 use super::win32helper;
-use std::fs::File;
+use std::borrow::Cow;
+use std::cmp;
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, Write};
 use std::mem;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+pub fn log_file_name() -> String {
+    env::var("LOCALAPPDATA").unwrap() + "\\record-usage.csv"
+}
+
+// "2020-5-17 9:03:41", as Logger::flush() writes it
+pub fn parse_timestamp(text: &str) -> Option<minwinbase::SYSTEMTIME> {
+    let mut timestamp: minwinbase::SYSTEMTIME = unsafe { mem::zeroed() };
+    {
+        let mut parts = text.split(['-', ' ', ':']).map(|t| t.parse::<u16>());
+        let mut fields = [
+            &mut timestamp.wYear,
+            &mut timestamp.wMonth,
+            &mut timestamp.wDay,
+            &mut timestamp.wHour,
+            &mut timestamp.wMinute,
+            &mut timestamp.wSecond,
+        ];
+        for field in fields.iter_mut() {
+            match parts.next() {
+                Some(Ok(t)) => **field = t,
+                _ => return None,
+            }
+        }
+    }
+    Some(timestamp)
+}
+
+pub struct Entry {
+    pub timestamp: minwinbase::SYSTEMTIME,
+    pub duration_in_seconds: u32,
+    pub activity: Activity,
+}
+
+// The column counts the log has had since its fields are quoted, oldest first.
+const QUOTED_COLUMN_COUNTS: [usize; 9] = [7, 9, 10, 12, 14, 15, 16, 17, 18];
+
+fn is_flag(text: &str) -> bool {
+    text == "0" || text == "1"
+}
+
+// The four-column log wrote the command line and the title as they were, so a comma in
+// either spilled the rest into the columns that came later. A record is taken for one of
+// the quoted formats only when it has as many columns as one of them and the columns with
+// a fixed form have it. Before the process access column, which brought image names
+// without a path, the image path was a full path or nothing.
+fn is_legacy_record(record: &[String]) -> bool {
+    if record.len() <= 4 {
+        return false;
+    }
+    let count = record.len();
+    let column = |index: usize| record[index].as_str();
+    let is_quoted = QUOTED_COLUMN_COUNTS.contains(&count)
+        && (count < 9 || column(8).parse::<u32>().is_ok())
+        && (count >= 15
+            || column(4).is_empty()
+            || column(4).contains(":\\")
+            || column(4).starts_with("\\\\"))
+        && (count < 15 || win32helper::ProcessAccess::parse(column(14)).as_str() == column(14))
+        && (count < 16 || is_flag(column(15)))
+        && (count < 18 || is_flag(column(17)));
+    !is_quoted
+}
+
+impl Entry {
+    // the reverse of write_entry(); columns added later are optional, so that
+    // files written by older versions can still be read
+    pub fn from_record(record: &[String]) -> Option<Entry> {
+        if record.len() < 4 {
+            return None;
+        }
+        let record: Cow<[String]> = if is_legacy_record(record) {
+            let mut joined = record[..3].to_vec();
+            joined.push(record[3..].join(", "));
+            Cow::Owned(joined)
+        } else {
+            Cow::Borrowed(record)
+        };
+        let column = |index: usize| record.get(index).cloned().unwrap_or_default();
+
+        let timestamp = parse_timestamp(&record[0])?;
 
-struct Entry {
-    timestamp: minwinbase::SYSTEMTIME,
-    duration_in_seconds: u32,
-    window_title: String,
-    command_line: String,
+        Some(Entry {
+            timestamp,
+            duration_in_seconds: record[1].parse().ok()?,
+            activity: Activity {
+                command_line: column(2),
+                window_title: column(3),
+                image_path: column(4),
+                product_name: column(5),
+                company_name: column(6),
+                working_directory: column(7),
+                parent_process_id: column(8).parse().unwrap_or(0),
+                project: column(9),
+                page_title: column(10),
+                site: column(11),
+                package_family_name: column(12),
+                app_user_model_id: column(13),
+                process_access: win32helper::ProcessAccess::parse(&column(14)),
+                not_responding: column(15) == "1",
+                environment: Activity::parse_environment(&column(16)),
+                idle: column(17) == "1",
+            },
+        })
+    }
+}
+
+// one line of the log, the reverse of Entry::from_record()
+pub fn write_entry<W: Write>(writer: &mut W, entry: &Entry) -> io::Result<()> {
+    let now = entry.timestamp;
+    let activity = &entry.activity;
+    writeln!(
+        writer,
+        "{}-{}-{} {}:{}:{}, {}, {}, {}, {}, {}, {}, {}, {}, {}, {}, {}, {}, {}, {}, {}, {}, {}",
+        now.wYear,
+        now.wMonth,
+        now.wDay,
+        now.wHour,
+        now.wMinute,
+        now.wSecond,
+        entry.duration_in_seconds,
+        csv::field(&activity.command_line),
+        csv::field(&activity.window_title),
+        csv::field(&activity.image_path),
+        csv::field(&activity.product_name),
+        csv::field(&activity.company_name),
+        csv::field(&activity.working_directory),
+        activity.parent_process_id,
+        csv::field(&activity.project),
+        csv::field(&activity.page_title),
+        csv::field(&activity.site),
+        csv::field(&activity.package_family_name),
+        csv::field(&activity.app_user_model_id),
+        activity.process_access.as_str(),
+        activity.not_responding as u8,
+        csv::field(&activity.environment_text()),
+        activity.idle as u8
+    )
 }
 
 pub struct Logger {
@@ -56,37 +291,80 @@ pub struct Logger {
     count: u32,
     last_entry: Entry,
     entries: Vec<Entry>,
+    today: (u16, u16, u16),
+    // read from other threads, e.g. by the notification area icon
+    today_in_seconds: AtomicU32,
 }
 
+fn day_of(time: &minwinbase::SYSTEMTIME) -> (u16, u16, u16) {
+    (time.wYear, time.wMonth, time.wDay)
+}
+
+// what was logged earlier today, by a previous run
+fn read_today_in_seconds(file_name: &str, today: (u16, u16, u16)) -> u32 {
+    let text = match fs::read_to_string(file_name) {
+        Ok(t) => t,
+        Err(_) => return 0,
+    };
+    csv::parse_records(&text)
+        .iter()
+        .filter_map(|t| Entry::from_record(t))
+        .filter(|t| !t.activity.idle && day_of(&t.timestamp) == today)
+        .map(|t| t.duration_in_seconds)
+        .sum()
+}
+
+// the Log methods mutate the static logger through &self
+#[allow(clippy::transmute_ptr_to_ref)]
 impl Log for Logger {
-    fn log(&self, window_title: String, command_line: String) {
+    fn log(&self, activity: Activity) {
+        unsafe {
+            let logger: &mut Logger = mem::transmute(self as *const Logger);
+            logger.add_entry(activity);
+            logger.count_buffered_entries();
+        }
+    }
+
+    fn log_idle(&self, idle_in_seconds: u32) {
         unsafe {
             let logger: &mut Logger = mem::transmute(self as *const Logger);
-            logger.add_entry(window_title, command_line);
+            logger.add_idle_entry(idle_in_seconds);
+            logger.count_buffered_entries();
         }
     }
 
-    fn get_last_entry(&self) -> (String, String) {
+    fn get_last_entry(&self) -> Activity {
         unsafe {
             let logger: &mut Logger = mem::transmute(self as *const Logger);
             logger.get_last_entry()
         }
     }
+
+    fn get_today_in_seconds(&self) -> u32 {
+        self.today_in_seconds.load(Ordering::Relaxed)
+    }
+
+    fn flush(&self) {
+        unsafe {
+            let logger: &mut Logger = mem::transmute(self as *const Logger);
+            logger.flush();
+        }
+    }
 }
 
 impl Logger {
     pub fn new(interval_in_seconds: u32, flush_interval_in_minutes: u32) -> Logger {
-        use std::env;
         use std::fs::OpenOptions;
         use std::os::windows::fs::OpenOptionsExt;
 
-        let file_name = env::var("LOCALAPPDATA").unwrap() + "\\record-usage.csv";
+        let file_name = log_file_name();
+        let now = win32helper::get_local_time();
+        let today_in_seconds = read_today_in_seconds(&file_name, day_of(&now));
 
         let last_entry = Entry {
-            timestamp: win32helper::get_local_time(),
+            timestamp: now,
             duration_in_seconds: 0,
-            window_title: String::new(),
-            command_line: String::new(),
+            activity: Activity::default(),
         };
 
         let max_entries_before_flush = flush_interval_in_minutes * 60 / interval_in_seconds;
@@ -103,10 +381,12 @@ impl Logger {
             count: 0,
             last_entry: last_entry,
             entries: Vec::<Entry>::with_capacity(max_entries_before_flush as usize),
+            today: day_of(&now),
+            today_in_seconds: AtomicU32::new(today_in_seconds),
         }
     }
 
-    pub fn add_entry(&mut self, window_title: String, command_line: String) {
+    pub fn add_entry(&mut self, activity: Activity) {
         self.count += 1;
         if self.count >= self.max_entries_before_flush {
             self.flush();
@@ -114,59 +394,79 @@ impl Logger {
         let entry = Entry {
             timestamp: win32helper::get_local_time(),
             duration_in_seconds: self.interval_in_seconds,
-            window_title: window_title.to_owned(),
-            command_line: command_line.to_owned(),
+            activity,
         };
+        if day_of(&entry.timestamp) != self.today {
+            self.today = day_of(&entry.timestamp);
+            self.today_in_seconds.store(0, Ordering::Relaxed);
+        }
+        if !entry.activity.idle {
+            self.today_in_seconds
+                .fetch_add(self.interval_in_seconds, Ordering::Relaxed);
+        }
         if self.last_entry.duration_in_seconds == 0 {
             self.last_entry = entry;
             return;
         }
-        if self.last_entry.window_title == window_title
-            && self.last_entry.command_line == command_line
-        {
+        if self.last_entry.activity == entry.activity {
             self.last_entry.duration_in_seconds += self.interval_in_seconds;
             return;
         }
         self.entries.push(mem::replace(&mut self.last_entry, entry));
     }
 
-    pub fn get_last_entry(&self) -> (String, String) {
-        (
-            self.last_entry.window_title.clone(),
-            self.last_entry.command_line.clone(),
-        )
+    // The samples taken after the user left went to whatever was active at the time; take
+    // them off that entry and start the idle entry when the user actually left.
+    pub fn add_idle_entry(&mut self, idle_in_seconds: u32) {
+        let activity = Activity {
+            idle: true,
+            ..Activity::default()
+        };
+        if self.last_entry.activity.idle || self.last_entry.duration_in_seconds == 0 {
+            self.add_entry(activity);
+            return;
+        }
+
+        let idle_in_seconds = idle_in_seconds / self.interval_in_seconds * self.interval_in_seconds;
+        let trimmed = cmp::min(idle_in_seconds, self.last_entry.duration_in_seconds);
+        self.last_entry.duration_in_seconds -= trimmed;
+        let today_in_seconds = self.today_in_seconds.load(Ordering::Relaxed);
+        self.today_in_seconds
+            .store(today_in_seconds.saturating_sub(trimmed), Ordering::Relaxed);
+        // an entry trimmed down to nothing is replaced rather than logged
+        self.add_entry(activity);
+        self.last_entry.timestamp =
+            win32helper::subtract_seconds(&self.last_entry.timestamp, trimmed);
+        self.last_entry.duration_in_seconds += trimmed;
     }
 
-    fn flush(&mut self) {
-        use std::io::Write;
+    pub fn get_last_entry(&self) -> Activity {
+        self.last_entry.activity.clone()
+    }
 
+    fn flush(&mut self) {
         let entry = Entry {
             timestamp: win32helper::get_local_time(),
             duration_in_seconds: 0,
-            window_title: String::new(),
-            command_line: String::new(),
+            activity: Activity::default(),
         };
         self.entries.push(mem::replace(&mut self.last_entry, entry));
 
         for entry in &self.entries {
-            let now = entry.timestamp;
-            writeln!(
-                self.file,
-                "{}-{}-{} {}:{}:{}, {}, {}, {}",
-                now.wYear,
-                now.wMonth,
-                now.wDay,
-                now.wHour,
-                now.wMinute,
-                now.wSecond,
-                entry.duration_in_seconds,
-                entry.command_line,
-                entry.window_title
-            ).unwrap();
+            write_entry(&mut self.file, entry).unwrap();
         }
 
         self.entries.clear();
         self.count = 0;
+        metrics::count_flush();
+        self.count_buffered_entries();
+    }
+
+    // entries not written yet, for /metrics
+    fn count_buffered_entries(&self) {
+        metrics::set_buffered_entries(
+            self.entries.len() + (self.last_entry.duration_in_seconds > 0) as usize,
+        );
     }
 }
 
@@ -175,3 +475,68 @@ impl Drop for Logger {
         self.flush();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(line: &str) -> Vec<String> {
+        csv::parse_records(line).remove(0)
+    }
+
+    #[test]
+    fn legacy_title_with_commas_is_joined() {
+        let entry = Entry::from_record(&record(
+            "2020-5-17 9:03:41, 10, notepad.exe, Hello, world, again - Notepad\n",
+        ))
+        .unwrap();
+        assert_eq!(entry.activity.window_title, "Hello, world, again - Notepad");
+        assert_eq!(entry.activity.image_path, "");
+    }
+
+    #[test]
+    fn current_record_is_not_taken_for_legacy() {
+        let entry = Entry::from_record(&record(
+            "2020-5-17 9:03:41, 10, code, \"a, b\", C:\\Code.exe, Code, Microsoft\n",
+        ))
+        .unwrap();
+        assert_eq!(entry.activity.window_title, "a, b");
+        assert_eq!(entry.activity.image_path, "C:\\Code.exe");
+        assert_eq!(entry.activity.company_name, "Microsoft");
+    }
+
+    #[test]
+    fn snapshot_record_is_not_taken_for_legacy() {
+        let entry = Entry::from_record(&record(
+            "2026-10-19 9:03:41, 10, , , System, , , , 4, , , , , , snapshot, 0, , 0\n",
+        ))
+        .unwrap();
+        assert_eq!(entry.activity.image_path, "System");
+        assert_eq!(
+            entry.activity.process_access,
+            win32helper::ProcessAccess::Snapshot
+        );
+        assert_eq!(entry.activity.parent_process_id, 4);
+    }
+
+    #[test]
+    fn legacy_command_line_with_commas_is_legacy() {
+        // five columns, which no quoted format had
+        let entry = Entry::from_record(&record(
+            "2020-5-17 9:03:41, 10, C:\\Tools\\sort.exe /k, 2, data.txt - Sort\n",
+        ))
+        .unwrap();
+        assert_eq!(entry.duration_in_seconds, 10);
+        assert_eq!(entry.activity.command_line, "C:\\Tools\\sort.exe /k");
+        assert_eq!(entry.activity.window_title, "2, data.txt - Sort");
+        assert_eq!(entry.activity.image_path, "");
+
+        // as many columns as the first quoted format, but no path where the image path goes
+        let entry = Entry::from_record(&record(
+            "2020-5-17 9:03:41, 10, tool.exe a, b, c, d, Title\n",
+        ))
+        .unwrap();
+        assert_eq!(entry.activity.window_title, "b, c, d, Title");
+        assert_eq!(entry.activity.image_path, "");
+    }
+}
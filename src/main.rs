@@ -2,95 +2,263 @@
 
 mod win32helper;
 
+mod browser;
+mod cli;
+mod collector;
+mod config;
+mod csv;
+mod export;
+mod focus;
+mod goals;
+mod http;
+mod idle;
+mod ipc;
+mod json;
 mod logger;
 mod mainframe;
+mod metrics;
+mod project;
+mod report;
+mod rules;
+mod sampler;
+mod session;
+mod sha256;
+mod snapshot;
+mod upload;
+mod webhook;
+use self::cli::CliError;
+use self::goals::{GoalKind, GoalTracker};
 use self::logger::*;
 use self::mainframe::*;
+use self::sampler::*;
+use self::session::SessionSettings;
 
+use std::env;
 use std::io::prelude::*;
 use std::net;
+use std::process;
+use std::sync::{Arc, Mutex};
 use std::thread;
 
 pub const CHECK_INTERNVAL_IN_SECONDS: u32 = 10;
 pub const FLUSH_INTERVAL_IN_MINUTES: u32 = 15;
 const LISTENING_PORT: u16 = 50080;
+// held by the running tracker for as long as it lives
+pub const INSTANCE_MUTEX_NAME: &str = "Local\\{AB2F0A5E-FAA2-4664-B3C2-25D3984F0A20}";
+
+// where start_web_server() can be reached
+pub fn dashboard_url() -> String {
+    format!("http://{}:{}/", win32helper::get_local_ip(), LISTENING_PORT)
+}
 
 fn main() {
-    if win32helper::is_app_already_runniing("Local\\{AB2F0A5E-FAA2-4664-B3C2-25D3984F0A20}") {
-        return;
+    let args: Vec<String> = env::args().collect();
+    process::exit(cli::run(&args[1..]));
+}
+
+// --console opens a console of its own for what the tracker prints along the way
+pub fn run_tracker(console: bool) -> Result<(), CliError> {
+    if win32helper::is_app_already_runniing(INSTANCE_MUTEX_NAME) {
+        let message = match ipc::send_command(ipc::Command::Status) {
+            Ok(Some(status)) => format!(
+                "record-usage is already running: {}",
+                cli::format_status(&status)
+            ),
+            _ => "record-usage is already running".to_string(),
+        };
+        return Err(CliError::new(cli::EXIT_ALREADY_RUNNING, &message));
     }
 
-    // let console_result = win32helper::alloc_console();
-    // println!("alloc_console: {:?}", console_result);
+    if console {
+        win32helper::alloc_console()?;
+    }
 
     let logger = Logger::new(CHECK_INTERNVAL_IN_SECONDS, FLUSH_INTERVAL_IN_MINUTES);
     set_logger(|| Box::new(logger));
 
-    start_web_server();
-
-    MainFrame::run();
-}
-
-pub fn get_foreground_app() {
-    let mut window_handle = win32helper::get_foreground_window();
-    let window_text = win32helper::get_window_text(window_handle);
-    let mut process_id = win32helper::get_window_process_id(window_handle);
-    let mut process_handle = win32helper::open_process(process_id);
-
-    if win32helper::is_immersive_process(process_handle) {
-        win32helper::close_handle(process_handle);
-        win32helper::get_universal_app(&mut window_handle, &mut process_id);
-        process_handle = win32helper::open_process(process_id);
-
-        // Note: I'm not refreshing get_window_text() for universal app here, because if we do so,
-        // the result seems always to be a static string like "Microsoft Edge", which is not useful.
-        // Besides, previous result from the parent app already reads as "CreateFileW - Google Search
-        // and 2 more pages ‎- Microsoft Edge", and I'd rather keep that.
-    }
-
-    let command_line = win32helper::get_process_command_line(process_handle);
-
-    win32helper::output_timestamp();
-    println!();
-    println!("hwnd  : {:?}", window_handle);
-    println!("pid   : {:?}", process_id);
-    println!("text  : {}", window_text);
-    println!("cmdln : {}", command_line);
-    println!();
+    let sampler = Sampler::start();
+    start_web_server(
+        sampler.goal_tracker(),
+        sampler.session_settings(),
+        sampler.status(),
+    );
 
-    log(window_text, command_line);
+    MainFrame::run(sampler);
+    Ok(())
 }
 
-fn start_web_server() {
-    thread::spawn(|| {
+fn start_web_server(
+    goal_tracker: Arc<Mutex<GoalTracker>>,
+    session_settings: Arc<Mutex<SessionSettings>>,
+    status: Arc<Mutex<Status>>,
+) {
+    thread::spawn(move || {
         let ip_address = win32helper::get_local_ip();
         println!("Listen on {}:{}", &ip_address, LISTENING_PORT);
         let listener = net::TcpListener::bind((ip_address.as_str(), LISTENING_PORT)).unwrap();
         for stream in listener.incoming() {
             match stream {
-                Ok(t) => handle_connection(t),
+                Ok(t) => handle_connection(t, &goal_tracker, &session_settings, &status),
                 Err(_) => return,
             }
         }
     });
 }
 
-fn handle_connection(mut stream: net::TcpStream) {
+// the path of a GET request, e.g. "/sessions"
+fn request_path(request: &[u8]) -> Option<String> {
+    let line = request.split(|&t| t == b'\r').next()?;
+    let line = String::from_utf8_lossy(line);
+    let mut parts = line.split(' ');
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some("GET"), Some(path), Some("HTTP/1.1")) => Some(path.to_string()),
+        _ => None,
+    }
+}
+
+fn html_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn handle_connection(
+    mut stream: net::TcpStream,
+    goal_tracker: &Mutex<GoalTracker>,
+    session_settings: &Mutex<SessionSettings>,
+    status: &Mutex<Status>,
+) {
+    // as of the last reload of the rules
+    let settings = session_settings.lock().unwrap().clone();
     let mut buffer = [0; 512];
     stream.read(&mut buffer).unwrap();
-    let get = b"GET / HTTP/1.1\r\n";
-    if !buffer.starts_with(get) {
-        return;
-    }
+    let response = match request_path(&buffer).as_deref() {
+        Some("/") => status_page(goal_tracker, status),
+        Some("/sessions") => sessions_page(&settings),
+        Some("/metrics") => format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\n\r\n{}",
+            metrics::format_metrics()
+        ),
+        Some(t) if t == "/api/summary" || t.starts_with("/api/summary?") => {
+            summary_api(t, &settings)
+        }
+        Some(_) => "HTTP/1.1 404 Not Found\r\n\r\n".to_string(),
+        None => return,
+    };
 
+    stream.write_all(response.as_bytes()).unwrap();
+    stream.flush().unwrap();
+}
+
+fn status_page(goal_tracker: &Mutex<GoalTracker>, status: &Mutex<Status>) -> String {
     let http_header = "HTTP/1.1 200 OK\r\n\r\n";
     let html_head = "<head><title>Status</title><style>body{font-size:50px}</style></head>";
-    let (window_title, command_line) = get_last_entry();
-    let response = format!(
-        "{}{}<p/>{}<p/>{}",
-        http_header, html_head, window_title, command_line
-    );
+    let activity = status.lock().unwrap().activity.clone();
 
-    stream.write(response.as_bytes()).unwrap();
-    stream.flush().unwrap();
+    // e.g. "Coding: 1:12:00 of at least 3:00:00 (40%)"
+    let mut goals = String::new();
+    for progress in goal_tracker.lock().unwrap().progress() {
+        let goal = &progress.goal;
+        goals += &format!(
+            "<p/>{}: {} of {} {} ({}%){}",
+            html_escape(&goal.name),
+            report::format_duration(progress.today_in_seconds as u64),
+            match goal.kind {
+                GoalKind::AtMost => "at most",
+                GoalKind::AtLeast => "at least",
+            },
+            report::format_duration(goal.target_in_seconds as u64),
+            progress.percent(),
+            if progress.crossed && goal.kind == GoalKind::AtMost {
+                " - over"
+            } else {
+                ""
+            }
+        );
+    }
+    format!(
+        "{}{}<p/>{}<p/>{}<p/>{}{}",
+        http_header,
+        html_head,
+        html_escape(&activity.window_title),
+        html_escape(&activity.product_name),
+        html_escape(&activity.command_line),
+        goals
+    )
+}
+
+// the entries of one day from the log, which lacks whatever is still waiting for a flush
+fn read_day_entries(date: (u16, u16, u16)) -> Vec<Entry> {
+    report::read_entries(&log_file_name())
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|t| (t.timestamp.wYear, t.timestamp.wMonth, t.timestamp.wDay) == date)
+        .collect()
+}
+
+fn today() -> (u16, u16, u16) {
+    let now = win32helper::get_local_time();
+    (now.wYear, now.wMonth, now.wDay)
+}
+
+// GET /api/summary[?date=yyyy-mm-dd], today by default, see report::day_summary
+fn summary_api(path: &str, settings: &SessionSettings) -> String {
+    let query = path.split_once('?').map_or("", |t| t.1);
+    let date = query
+        .split('&')
+        .filter_map(|t| {
+            let mut pair = t.splitn(2, '=');
+            match (pair.next(), pair.next()) {
+                (Some("date"), Some(value)) => Some(cli::parse_date(value)),
+                _ => None,
+            }
+        })
+        .next()
+        .unwrap_or(Some(today()));
+    let date = match date {
+        Some(t) => t,
+        None => return "HTTP/1.1 400 Bad Request\r\n\r\n".to_string(),
+    };
+
+    let entries = read_day_entries(date);
+    let summary = report::day_summary(date, &entries, settings.tolerance_in_seconds);
+    format!(
+        "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\n\r\n{}",
+        summary
+    )
+}
+
+// Today's sessions, both raw and smoothed, as far as the log has been written; the last
+// quarter of an hour may still be waiting for the next flush.
+fn sessions_page(settings: &SessionSettings) -> String {
+    let http_header = "HTTP/1.1 200 OK\r\nContent-Type: text/html; charset=utf-8\r\n\r\n";
+    let html_head = "<head><title>Sessions</title></head>";
+    let entries = read_day_entries(today());
+    let raw = session::sessions(&entries, report::Grouping::Application);
+    let smoothed = session::smooth(raw.clone(), settings.tolerance_in_seconds);
+
+    let mut body = String::new();
+    let views = [
+        (
+            format!(
+                "Smoothed, interruptions under {} seconds",
+                settings.tolerance_in_seconds
+            ),
+            &smoothed,
+        ),
+        ("Raw".to_string(), &raw),
+    ];
+    for &(ref title, sessions) in views.iter() {
+        body += &format!("<h2>{}</h2><pre>", title);
+        for session in sessions.iter() {
+            body += &html_escape(&report::format_session(session));
+            body += "\n";
+        }
+        body += "</pre>";
+    }
+    format!(
+        "{}<html>{}<body>{}</body></html>",
+        http_header, html_head, body
+    )
 }
@@ -1,13 +1,35 @@
 extern crate winapi;
 
-use get_foreground_app;
-use CHECK_INTERNVAL_IN_SECONDS;
+use ipc;
+use json;
+use logger;
+use metrics;
+use report;
+use sampler::Sampler;
+use {dashboard_url, CHECK_INTERNVAL_IN_SECONDS};
 
 use self::winapi::{ctypes, shared::minwindef, shared::windef, um::winnt, um::winuser};
 
 use super::win32helper;
 use std::mem;
-use std::ptr;
+use std::path::Path;
+
+// mouse events on the notification area icon
+const WM_APP_NOTIFY_ICON: minwindef::UINT = winuser::WM_APP + 1;
+
+// sent by the control channel thread, lparam points to a ControlMessage
+const WM_APP_CONTROL: minwindef::UINT = winuser::WM_APP + 2;
+
+struct ControlMessage {
+    command: ipc::Command,
+    response: json::Value,
+}
+
+const ID_PAUSE: minwindef::UINT = 1;
+const ID_OPEN_DASHBOARD: minwindef::UINT = 2;
+const ID_OPEN_LOG_FOLDER: minwindef::UINT = 3;
+const ID_FLUSH: minwindef::UINT = 4;
+const ID_EXIT: minwindef::UINT = 5;
 
 struct MyTimer {
     hwnd: Option<windef::HWND>,
@@ -18,12 +40,19 @@ struct MyTimer {
 
 impl MyTimer {
     pub fn start(&mut self) {
-        win32helper::set_timer(self.hwnd.unwrap(), self.id, self.period_in_second * 1000);
+        if let Err(e) =
+            win32helper::set_timer(self.hwnd.unwrap(), self.id, self.period_in_second * 1000)
+        {
+            println!("{}", e);
+            return;
+        }
         self.running = true;
     }
 
     pub fn stop(&mut self) {
-        win32helper::kill_timer(self.hwnd.unwrap(), self.id);
+        if let Err(e) = win32helper::kill_timer(self.hwnd.unwrap(), self.id) {
+            println!("{}", e);
+        }
         self.running = false;
     }
 
@@ -35,6 +64,11 @@ impl MyTimer {
 pub struct MainFrame {
     hwnd: Option<windef::HWND>,
     timer: MyTimer,
+    sampler: Sampler,
+    // by the user; power and session events leave the timer alone while paused
+    paused: bool,
+    // sent to every top-level window when explorer restarts and the icon is gone
+    taskbar_created: minwindef::UINT,
 }
 
 impl win32helper::WindowTrait for MainFrame {
@@ -47,8 +81,31 @@ impl win32helper::WindowTrait for MainFrame {
     ) -> minwindef::LRESULT {
         match msg {
             winuser::WM_TIMER => {
-                get_foreground_app();
+                self.sampler.request_sample();
+                self.update_notify_icon(hwnd);
+                // from earlier samples, this one is still being taken
+                for notification in self.sampler.take_notifications() {
+                    let result = win32helper::show_notify_balloon(
+                        hwnd,
+                        &notification.title,
+                        &notification.text,
+                    );
+                    if let Err(e) = result {
+                        println!("{}", e);
+                    }
+                }
+            }
+            WM_APP_CONTROL => {
+                // sent, not posted, so the message outlives the call
+                let message = unsafe { &mut *(lparam as *mut ControlMessage) };
+                message.response = self.control(hwnd, message.command);
+                return 0;
             }
+            WM_APP_NOTIFY_ICON => match lparam as minwindef::UINT {
+                winuser::WM_RBUTTONUP | winuser::WM_CONTEXTMENU => self.show_menu(hwnd),
+                winuser::WM_LBUTTONDBLCLK => self.open_dashboard(),
+                _ => {}
+            },
             winuser::WM_POWERBROADCAST => {
                 if wparam == win32helper::PBT_POWERSETTINGCHANGE && lparam != 0 {
                     let setting: &win32helper::POWERBROADCAST_SETTING =
@@ -60,8 +117,10 @@ impl win32helper::WindowTrait for MainFrame {
                 self.logon_event(wparam);
             }
             winuser::WM_DESTROY => {
+                win32helper::delete_notify_icon(hwnd).ok();
                 win32helper::post_quit_message(0);
             }
+            _ if msg == self.taskbar_created && msg != 0 => self.add_notify_icon(hwnd),
             _ => {}
         }
         win32helper::def_window_proc(hwnd, msg, wparam, lparam)
@@ -69,7 +128,7 @@ impl win32helper::WindowTrait for MainFrame {
 }
 
 impl MainFrame {
-    pub fn run() {
+    pub fn run(sampler: Sampler) {
         let timer = MyTimer {
             hwnd: None,
             running: false,
@@ -80,33 +139,47 @@ impl MainFrame {
         let mut frame = MainFrame {
             hwnd: None,
             timer: timer,
+            sampler,
+            paused: false,
+            taskbar_created: win32helper::register_window_message("TaskbarCreated").unwrap_or(0),
         };
 
-        let hwnd = frame.create_window();
+        let hwnd = match frame.create_window() {
+            Ok(t) => t,
+            Err(e) => {
+                println!("{}", e);
+                return;
+            }
+        };
         frame.hwnd = Some(hwnd);
         frame.timer.hwnd = Some(hwnd);
 
         frame.register_notification(hwnd);
+        frame.add_notify_icon(hwnd);
+        frame.start_control_server(hwnd);
         frame.timer.start();
 
         win32helper::message_loop();
+
+        // the window is gone, and with it the last pointer to the frame
+        let MainFrame { sampler, .. } = frame;
+        sampler.stop();
     }
 
-    pub fn create_window(&mut self) -> windef::HWND {
+    pub fn create_window(&mut self) -> win32helper::Win32Result<windef::HWND> {
         let instance_handle = win32helper::get_current_instance();
         let class_name = "{8677407E-01E9-4D3E-8BF5-F9082CE08AEB}";
         let window_name = "Monitor";
         let wnd_extra: ctypes::c_int = 0;
 
-        let hwnd = win32helper::create_window::<MainFrame>(
+        win32helper::create_window::<MainFrame>(
             self,
             class_name,
             window_name,
             winuser::WS_OVERLAPPEDWINDOW, // | winuser::WS_VISIBLE,
             instance_handle,
             wnd_extra,
-        );
-        hwnd
+        )
     }
 
     pub fn register_notification(&mut self, hwnd: windef::HWND) {
@@ -115,28 +188,21 @@ impl MainFrame {
             win32helper::GUID_SESSION_DISPLAY_STATUS,
         ];
         for setting in power_settings.iter() {
-            if win32helper::register_power_setting_notification(
+            if let Err(e) = win32helper::register_power_setting_notification(
                 hwnd as winnt::HANDLE,
                 setting,
                 win32helper::DEVICE_NOTIFY_WINDOW_HANDLE,
-            ) == ptr::null_mut()
-            {
-                println!(
-                    "register_power_setting_notification failed with {:?}",
-                    win32helper::get_last_error()
-                );
+            ) {
+                println!("{}", e);
                 return;
             }
         }
 
-        if !win32helper::wts_register_session_notification(
+        if let Err(e) = win32helper::wts_register_session_notification(
             hwnd,
             win32helper::NOTIFY_FOR_THIS_SESSION,
         ) {
-            println!(
-                "wts_register_session_notification failed with {:?}",
-                win32helper::get_last_error()
-            );
+            println!("{}", e);
             return;
         }
     }
@@ -152,7 +218,7 @@ impl MainFrame {
             let data = setting.data;
             if self.timer.is_running() && data == power_user_inactive {
                 self.timer.stop();
-            } else if !self.timer.is_running() && data == power_user_present {
+            } else if !self.timer.is_running() && !self.paused && data == power_user_present {
                 self.timer.start();
             }
         } else if win32helper::is_equal_guid(
@@ -165,17 +231,189 @@ impl MainFrame {
             let data = setting.data;
             if self.timer.is_running() && data == display_off {
                 self.timer.stop();
-            } else if !self.timer.is_running() && data == display_on {
+            } else if !self.timer.is_running() && !self.paused && data == display_on {
                 self.timer.start();
             }
         }
     }
 
     fn logon_event(&mut self, data: minwindef::WPARAM) {
+        if data == win32helper::WTS_SESSION_LOCK || data == win32helper::WTS_SESSION_UNLOCK {
+            metrics::set_locked(data == win32helper::WTS_SESSION_LOCK);
+            self.sampler
+                .report_lock(data == win32helper::WTS_SESSION_LOCK);
+        }
         if self.timer.is_running() && data == win32helper::WTS_SESSION_LOCK {
             self.timer.stop();
-        } else if !self.timer.is_running() && data == win32helper::WTS_SESSION_UNLOCK {
+        } else if !self.timer.is_running()
+            && !self.paused
+            && data == win32helper::WTS_SESSION_UNLOCK
+        {
             self.timer.start();
         }
     }
+
+    // e.g. "Visual Studio Code" and "2:41:07 today"
+    fn status(&self) -> (String, String) {
+        let status = self.sampler.status().lock().unwrap().clone();
+        let activity = &status.activity;
+        let current = if self.paused {
+            "Paused".to_string()
+        } else if activity.idle {
+            "Idle".to_string()
+        } else if activity.application_name().is_empty() {
+            "Running".to_string()
+        } else {
+            activity.application_name().to_string()
+        };
+        let today = report::format_duration(status.today_in_seconds as u64);
+        (current, format!("{} today", today))
+    }
+
+    fn add_notify_icon(&mut self, hwnd: windef::HWND) {
+        let (current, today) = self.status();
+        let tip = format!("{}\n{}", current, today);
+        if let Err(e) = win32helper::add_notify_icon(hwnd, WM_APP_NOTIFY_ICON, &tip) {
+            println!("{}", e);
+        }
+    }
+
+    fn update_notify_icon(&mut self, hwnd: windef::HWND) {
+        let (current, today) = self.status();
+        let tip = format!("{}\n{}", current, today);
+        if let Err(e) = win32helper::set_notify_icon_tip(hwnd, &tip) {
+            println!("{}", e);
+        }
+    }
+
+    fn show_menu(&mut self, hwnd: windef::HWND) {
+        let (current, today) = self.status();
+        let status = format!("{} - {}", current, today);
+        let items = [
+            win32helper::MenuItem::Disabled(&status),
+            win32helper::MenuItem::Separator,
+            win32helper::MenuItem::Command(ID_PAUSE, if self.paused { "Resume" } else { "Pause" }),
+            win32helper::MenuItem::Command(ID_OPEN_DASHBOARD, "Open dashboard"),
+            win32helper::MenuItem::Command(ID_OPEN_LOG_FOLDER, "Open log folder"),
+            win32helper::MenuItem::Command(ID_FLUSH, "Flush now"),
+            win32helper::MenuItem::Separator,
+            win32helper::MenuItem::Command(ID_EXIT, "Exit"),
+        ];
+        let id = match win32helper::show_popup_menu(hwnd, &items) {
+            Ok(Some(t)) => t,
+            Ok(None) => return,
+            Err(e) => {
+                println!("{}", e);
+                return;
+            }
+        };
+        match id {
+            ID_PAUSE => self.set_paused(hwnd, !self.paused),
+            ID_OPEN_DASHBOARD => self.open_dashboard(),
+            ID_OPEN_LOG_FOLDER => {
+                let file_name = logger::log_file_name();
+                if let Some(folder) = Path::new(&file_name).parent() {
+                    if let Err(e) = win32helper::shell_execute(&folder.to_string_lossy()) {
+                        println!("{}", e);
+                    }
+                }
+            }
+            ID_FLUSH => self.sampler.request_flush(),
+            ID_EXIT => {
+                if let Err(e) = win32helper::destroy_window(hwnd) {
+                    println!("{}", e);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn control(&mut self, hwnd: windef::HWND, command: ipc::Command) -> json::Value {
+        match command {
+            ipc::Command::Status => {
+                let status = self.sampler.status().lock().unwrap().clone();
+                let activity = &status.activity;
+                ipc::ok(vec![
+                    ("paused", self.paused.into()),
+                    ("idle", activity.idle.into()),
+                    ("application", activity.application_name().into()),
+                    ("today_in_seconds", (status.today_in_seconds as u64).into()),
+                    (
+                        "config_error",
+                        self.sampler
+                            .config_error()
+                            .map_or(json::Value::Null, |t| t.into()),
+                    ),
+                ])
+            }
+            ipc::Command::Pause => {
+                self.set_paused(hwnd, true);
+                ipc::ok(vec![])
+            }
+            ipc::Command::Resume => {
+                self.set_paused(hwnd, false);
+                ipc::ok(vec![])
+            }
+            // answered by the control channel thread, see start_control_server()
+            ipc::Command::Flush | ipc::Command::Reload => {
+                ipc::error(&format!("{} is not for the window", command.name()))
+            }
+            // after the answer has gone out
+            ipc::Command::Stop => match win32helper::post_message(hwnd, winuser::WM_CLOSE, 0, 0) {
+                Ok(()) => ipc::ok(vec![]),
+                Err(e) => ipc::error(&e.to_string()),
+            },
+        }
+    }
+
+    fn start_control_server(&self, hwnd: windef::HWND) {
+        // a window handle is not Send, but the window outlives the server thread
+        let hwnd = hwnd as usize;
+        // a reload or a flush waits for the sample being taken, which the window must not
+        let controller = self.sampler.controller();
+        let result = ipc::start_server(move |command| {
+            let result = match command {
+                ipc::Command::Reload => Some(controller.reload()),
+                ipc::Command::Flush => Some(controller.flush()),
+                _ => None,
+            };
+            if let Some(result) = result {
+                return match result {
+                    Ok(()) => ipc::ok(vec![]),
+                    Err(e) => ipc::error(&e),
+                };
+            }
+            let mut message = ControlMessage {
+                command,
+                response: json::Value::Null,
+            };
+            win32helper::send_message(
+                hwnd as windef::HWND,
+                WM_APP_CONTROL,
+                0,
+                &mut message as *mut ControlMessage as minwindef::LPARAM,
+            );
+            message.response
+        });
+        if let Err(e) = result {
+            println!("{}", e);
+        }
+    }
+
+    fn set_paused(&mut self, hwnd: windef::HWND, paused: bool) {
+        self.paused = paused;
+        metrics::set_paused(paused);
+        if paused && self.timer.is_running() {
+            self.timer.stop();
+        } else if !paused && !self.timer.is_running() {
+            self.timer.start();
+        }
+        self.update_notify_icon(hwnd);
+    }
+
+    fn open_dashboard(&self) {
+        if let Err(e) = win32helper::shell_execute(&dashboard_url()) {
+            println!("{}", e);
+        }
+    }
 }
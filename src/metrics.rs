@@ -0,0 +1,280 @@
+//
+// GET /metrics, for Prometheus:
+//
+// record_usage_samples_total                               samples taken
+// record_usage_sample_failures_total{reason}               what a sample could not find out,
+//                                                          e.g. "open_process" for a failed
+//                                                          OpenProcess or "empty_command_line"
+// record_usage_application_seconds_total{application}      time per application
+// record_usage_project_seconds_total{project}              time per project
+// record_usage_idle_seconds_total                          time nobody was there
+// record_usage_flushes_total                               writes of the log
+// record_usage_buffered_entries                            entries waiting for the next one
+// record_usage_idle, record_usage_locked, record_usage_paused
+//                                                          1 or 0
+//
+// The counters are kept per sample and start from zero with every run. Time the log later
+// hands from an application to idle is not taken back.
+//
+
+use logger::Activity;
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Mutex;
+
+static SAMPLES: AtomicU64 = AtomicU64::new(0);
+static IDLE_SECONDS: AtomicU64 = AtomicU64::new(0);
+static FLUSHES: AtomicU64 = AtomicU64::new(0);
+static BUFFERED_ENTRIES: AtomicU64 = AtomicU64::new(0);
+static IDLE: AtomicBool = AtomicBool::new(false);
+static LOCKED: AtomicBool = AtomicBool::new(false);
+static PAUSED: AtomicBool = AtomicBool::new(false);
+static SAMPLE_FAILURES: Mutex<BTreeMap<String, u64>> = Mutex::new(BTreeMap::new());
+static APPLICATION_SECONDS: Mutex<BTreeMap<String, u64>> = Mutex::new(BTreeMap::new());
+static PROJECT_SECONDS: Mutex<BTreeMap<String, u64>> = Mutex::new(BTreeMap::new());
+
+fn add(counters: &Mutex<BTreeMap<String, u64>>, key: &str, value: u64) {
+    *counters.lock().unwrap().entry(key.to_string()).or_insert(0) += value;
+}
+
+// the activity is None when nobody was there
+pub fn count_sample(activity: Option<&Activity>, seconds: u32) {
+    SAMPLES.fetch_add(1, Ordering::Relaxed);
+    IDLE.store(activity.is_none(), Ordering::Relaxed);
+    match activity {
+        Some(activity) => {
+            let application = match activity.application_name() {
+                "" => "(none)",
+                t => t,
+            };
+            add(&APPLICATION_SECONDS, application, seconds as u64);
+            if !activity.project.is_empty() {
+                add(&PROJECT_SECONDS, &activity.project, seconds as u64);
+            }
+        }
+        None => {
+            IDLE_SECONDS.fetch_add(seconds as u64, Ordering::Relaxed);
+        }
+    }
+}
+
+pub fn count_sample_failure(reason: &str) {
+    add(&SAMPLE_FAILURES, reason, 1);
+}
+
+// "OpenProcess" is "open_process", "GetWindowTextW" is "get_window_text"
+pub fn failure_reason(function: &str) -> String {
+    let function = if function.ends_with('W') && function.len() > 1 {
+        &function[..function.len() - 1]
+    } else {
+        function
+    };
+    let mut reason = String::new();
+    for (index, c) in function.chars().enumerate() {
+        if c.is_ascii_uppercase() && index > 0 {
+            reason.push('_');
+        }
+        reason.push(c.to_ascii_lowercase());
+    }
+    reason
+}
+
+pub fn count_flush() {
+    FLUSHES.fetch_add(1, Ordering::Relaxed);
+}
+
+pub fn set_buffered_entries(buffered_entries: usize) {
+    BUFFERED_ENTRIES.store(buffered_entries as u64, Ordering::Relaxed);
+}
+
+pub fn set_locked(locked: bool) {
+    LOCKED.store(locked, Ordering::Relaxed);
+}
+
+pub fn set_paused(paused: bool) {
+    PAUSED.store(paused, Ordering::Relaxed);
+}
+
+// backslashes, quotes and line feeds are escaped in label values
+fn label_value(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+fn write_header(text: &mut String, name: &str, kind: &str, help: &str) {
+    writeln!(text, "# HELP {} {}", name, help).unwrap();
+    writeln!(text, "# TYPE {} {}", name, kind).unwrap();
+}
+
+fn write_labeled(
+    text: &mut String,
+    name: &str,
+    help: &str,
+    label: &str,
+    counters: &Mutex<BTreeMap<String, u64>>,
+) {
+    write_header(text, name, "counter", help);
+    for (key, value) in counters.lock().unwrap().iter() {
+        writeln!(
+            text,
+            "{}{{{}=\"{}\"}} {}",
+            name,
+            label,
+            label_value(key),
+            value
+        )
+        .unwrap();
+    }
+}
+
+fn write_value(text: &mut String, name: &str, kind: &str, help: &str, value: u64) {
+    write_header(text, name, kind, help);
+    writeln!(text, "{} {}", name, value).unwrap();
+}
+
+// the text format of Prometheus, version 0.0.4
+pub fn format_metrics() -> String {
+    let mut text = String::new();
+    write_value(
+        &mut text,
+        "record_usage_samples_total",
+        "counter",
+        "Samples taken.",
+        SAMPLES.load(Ordering::Relaxed),
+    );
+    write_labeled(
+        &mut text,
+        "record_usage_sample_failures_total",
+        "What samples could not find out.",
+        "reason",
+        &SAMPLE_FAILURES,
+    );
+    write_labeled(
+        &mut text,
+        "record_usage_application_seconds_total",
+        "Seconds spent in each application.",
+        "application",
+        &APPLICATION_SECONDS,
+    );
+    write_labeled(
+        &mut text,
+        "record_usage_project_seconds_total",
+        "Seconds spent in each project.",
+        "project",
+        &PROJECT_SECONDS,
+    );
+    write_value(
+        &mut text,
+        "record_usage_idle_seconds_total",
+        "counter",
+        "Seconds nobody was there.",
+        IDLE_SECONDS.load(Ordering::Relaxed),
+    );
+    write_value(
+        &mut text,
+        "record_usage_flushes_total",
+        "counter",
+        "Writes of the log.",
+        FLUSHES.load(Ordering::Relaxed),
+    );
+    write_value(
+        &mut text,
+        "record_usage_buffered_entries",
+        "gauge",
+        "Entries waiting to be written.",
+        BUFFERED_ENTRIES.load(Ordering::Relaxed),
+    );
+    let flags = [
+        ("record_usage_idle", "Whether nobody is there.", &IDLE),
+        (
+            "record_usage_locked",
+            "Whether the session is locked.",
+            &LOCKED,
+        ),
+        (
+            "record_usage_paused",
+            "Whether recording is paused.",
+            &PAUSED,
+        ),
+    ];
+    for &(name, help, flag) in flags.iter() {
+        write_value(
+            &mut text,
+            name,
+            "gauge",
+            help,
+            flag.load(Ordering::Relaxed) as u64,
+        );
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reasons_are_snake_case_without_the_wide_suffix() {
+        assert_eq!(failure_reason("OpenProcess"), "open_process");
+        assert_eq!(failure_reason("GetWindowTextW"), "get_window_text");
+        assert_eq!(
+            failure_reason("CreateToolhelp32Snapshot"),
+            "create_toolhelp32_snapshot"
+        );
+        assert_eq!(failure_reason("W"), "w");
+        assert_eq!(failure_reason(""), "");
+    }
+
+    // the counters are shared by all tests, so each one uses names of its own
+    #[test]
+    fn failures_are_counted_by_reason() {
+        count_sample_failure("test_reason");
+        count_sample_failure("test_reason");
+        let text = format_metrics();
+        assert!(text.contains(
+            "# HELP record_usage_sample_failures_total What samples could not find out.\n\
+             # TYPE record_usage_sample_failures_total counter\n"
+        ));
+        assert!(text.contains("record_usage_sample_failures_total{reason=\"test_reason\"} 2\n"));
+    }
+
+    #[test]
+    fn label_values_are_escaped() {
+        let activity = Activity {
+            product_name: "Test \"Quoted\" C:\\App".to_string(),
+            project: "test\nproject".to_string(),
+            ..Default::default()
+        };
+        count_sample(Some(&activity), 10);
+        let text = format_metrics();
+        assert!(text.contains(
+            "record_usage_application_seconds_total{application=\"Test \\\"Quoted\\\" C:\\\\App\"} 10\n"
+        ));
+        assert!(
+            text.contains("record_usage_project_seconds_total{project=\"test\\nproject\"} 10\n")
+        );
+    }
+
+    #[test]
+    fn every_metric_has_a_type() {
+        let text = format_metrics();
+        for (name, kind) in [
+            ("record_usage_samples_total", "counter"),
+            ("record_usage_idle_seconds_total", "counter"),
+            ("record_usage_flushes_total", "counter"),
+            ("record_usage_buffered_entries", "gauge"),
+            ("record_usage_idle", "gauge"),
+            ("record_usage_locked", "gauge"),
+            ("record_usage_paused", "gauge"),
+        ]
+        .iter()
+        {
+            assert!(text.contains(&format!("# TYPE {} {}\n", name, kind)));
+            assert!(text.lines().any(|t| t.starts_with(&format!("{} ", name))));
+        }
+    }
+}
@@ -0,0 +1,254 @@
+//
+// [project]
+// ; title     = <image name> | <segment index, negative counts from the end> | <separator>
+// ; directory = <image name> | [<root folder>]
+// ; keyword   = <image name> | <keyword in title> | <project>
+// title     = Code.exe | -2 | " - "
+// directory = pwsh.exe | C:\src
+// keyword   = * | record-usage | monitor
+//
+// The image name "*" matches any process. Extractors are tried in order and the first
+// non-empty result wins. Without a [project] section the built-in defaults apply.
+//
+
+use config::{self, Config};
+use logger::Activity;
+
+use std::path::Path;
+
+pub enum Extractor {
+    // "file.rs - myrepo - Visual Studio Code" split on " - ", segment -2 => "myrepo"
+    TitleSegment {
+        image_name: String,
+        index: i32,
+        separator: String,
+    },
+    // the first folder below root, or the enclosing git repository when root is empty
+    WorkingDirectory {
+        image_name: String,
+        root: String,
+    },
+    TitleKeyword {
+        image_name: String,
+        keyword: String,
+        project: String,
+    },
+}
+
+impl Extractor {
+    fn image_name(&self) -> &str {
+        match *self {
+            Extractor::TitleSegment { ref image_name, .. } => image_name,
+            Extractor::WorkingDirectory { ref image_name, .. } => image_name,
+            Extractor::TitleKeyword { ref image_name, .. } => image_name,
+        }
+    }
+
+    fn extract(&self, activity: &Activity) -> Option<String> {
+        match *self {
+            Extractor::TitleSegment {
+                index,
+                ref separator,
+                ..
+            } => {
+                let segments: Vec<&str> = activity.window_title.split(separator.as_str()).collect();
+                let position = if index < 0 {
+                    segments.len() as i32 + index
+                } else {
+                    index
+                };
+                // a single segment means the separator was not found at all
+                if segments.len() < 2 || position < 0 || position as usize >= segments.len() {
+                    return None;
+                }
+                Some(segments[position as usize].trim().to_string())
+            }
+            Extractor::WorkingDirectory { ref root, .. } => {
+                let directory = Path::new(&activity.working_directory);
+                if root.is_empty() {
+                    return directory
+                        .ancestors()
+                        .find(|t| t.join(".git").exists())
+                        .and_then(|t| t.file_name())
+                        .map(|t| t.to_string_lossy().into_owned());
+                }
+                directory
+                    .strip_prefix(root)
+                    .ok()
+                    .and_then(|t| t.components().next())
+                    .map(|t| t.as_os_str().to_string_lossy().into_owned())
+            }
+            Extractor::TitleKeyword {
+                ref keyword,
+                ref project,
+                ..
+            } => {
+                if activity.window_title.contains(keyword.as_str()) {
+                    Some(project.clone())
+                } else {
+                    None
+                }
+            }
+        }
+    }
+}
+
+pub struct ProjectDetector {
+    extractors: Vec<Extractor>,
+}
+
+impl Default for ProjectDetector {
+    fn default() -> ProjectDetector {
+        let title = |image_name: &str, index: i32| Extractor::TitleSegment {
+            image_name: image_name.to_string(),
+            index,
+            separator: " - ".to_string(),
+        };
+        let directory = |image_name: &str| Extractor::WorkingDirectory {
+            image_name: image_name.to_string(),
+            root: String::new(),
+        };
+        ProjectDetector {
+            extractors: vec![
+                title("Code.exe", -2),
+                title("devenv.exe", -2),
+                directory("cmd.exe"),
+                directory("powershell.exe"),
+                directory("pwsh.exe"),
+                directory("bash.exe"),
+            ],
+        }
+    }
+}
+
+impl ProjectDetector {
+    pub fn from_config(config: &Config) -> Result<ProjectDetector, String> {
+        if !config.has_section("project") {
+            return Ok(ProjectDetector::default());
+        }
+
+        let mut extractors = Vec::new();
+        for (key, value) in config.entries("project") {
+            let mut arguments = config::split_list(value).into_iter();
+            let image_name = arguments.next().unwrap_or_default();
+            let arguments: Vec<String> = arguments.collect();
+            let extractor = match (key, arguments.len()) {
+                ("title", 2) => Extractor::TitleSegment {
+                    image_name,
+                    index: arguments[0]
+                        .parse()
+                        .map_err(|_| format!("[project] {} = {}: bad segment index", key, value))?,
+                    separator: arguments[1].clone(),
+                },
+                ("directory", 0) | ("directory", 1) => Extractor::WorkingDirectory {
+                    image_name,
+                    root: arguments.first().cloned().unwrap_or_default(),
+                },
+                ("keyword", 2) => Extractor::TitleKeyword {
+                    image_name,
+                    keyword: arguments[0].clone(),
+                    project: arguments[1].clone(),
+                },
+                _ => return Err(format!("[project] {} = {}: unknown extractor", key, value)),
+            };
+            extractors.push(extractor);
+        }
+        Ok(ProjectDetector { extractors })
+    }
+
+    pub fn detect(&self, activity: &Activity) -> String {
+        let image_name = activity.image_name();
+        self.extractors
+            .iter()
+            .filter(|t| t.image_name() == "*" || t.image_name().eq_ignore_ascii_case(image_name))
+            .filter_map(|t| t.extract(activity))
+            .find(|t| !t.is_empty())
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn activity(image_path: &str, window_title: &str) -> Activity {
+        Activity {
+            image_path: image_path.to_string(),
+            window_title: window_title.to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn title(index: i32) -> Extractor {
+        Extractor::TitleSegment {
+            image_name: "Code.exe".to_string(),
+            index,
+            separator: " - ".to_string(),
+        }
+    }
+
+    #[test]
+    fn negative_index_counts_from_the_end() {
+        let activity = activity("C:\\Code.exe", "main.rs - myrepo - Visual Studio Code");
+        assert_eq!(title(-2).extract(&activity), Some("myrepo".to_string()));
+        assert_eq!(title(-3).extract(&activity), Some("main.rs".to_string()));
+        assert_eq!(title(0).extract(&activity), Some("main.rs".to_string()));
+        assert_eq!(title(-4).extract(&activity), None);
+        assert_eq!(title(3).extract(&activity), None);
+    }
+
+    #[test]
+    fn single_segment_is_no_project() {
+        let activity = activity("C:\\Code.exe", "Visual Studio Code");
+        assert_eq!(title(-1).extract(&activity), None);
+        assert_eq!(title(0).extract(&activity), None);
+    }
+
+    #[test]
+    fn keyword_matches_anywhere_in_the_title() {
+        let extractor = Extractor::TitleKeyword {
+            image_name: "*".to_string(),
+            keyword: "record-usage".to_string(),
+            project: "monitor".to_string(),
+        };
+        let found = activity("C:\\x.exe", "issue #4 - record-usage - Firefox");
+        assert_eq!(extractor.extract(&found), Some("monitor".to_string()));
+        assert_eq!(extractor.extract(&activity("C:\\x.exe", "Firefox")), None);
+    }
+
+    #[test]
+    fn config_replaces_the_defaults() {
+        let config = Config::parse(
+            "[project]\ntitle = code.exe | -2 | \" - \"\nkeyword = * | record-usage | monitor\n",
+        )
+        .unwrap();
+        let detector = ProjectDetector::from_config(&config).unwrap();
+        assert_eq!(detector.extractors.len(), 2);
+        let code = activity("C:\\Code.exe", "main.rs - myrepo - Visual Studio Code");
+        assert_eq!(detector.detect(&code), "myrepo");
+        let other = activity("C:\\notepad.exe", "record-usage.txt - Notepad");
+        assert_eq!(detector.detect(&other), "monitor");
+        assert_eq!(detector.detect(&activity("C:\\notepad.exe", "Notepad")), "");
+    }
+
+    #[test]
+    fn config_without_section_keeps_the_defaults() {
+        let detector = ProjectDetector::from_config(&Config::default()).unwrap();
+        let code = activity("C:\\Code.exe", "main.rs - myrepo - Visual Studio Code");
+        assert_eq!(detector.detect(&code), "myrepo");
+    }
+
+    #[test]
+    fn config_errors_name_the_entry() {
+        let bad_index = Config::parse("[project]\ntitle = Code.exe | two | \" - \"\n").unwrap();
+        assert_eq!(
+            ProjectDetector::from_config(&bad_index).err().unwrap(),
+            "[project] title = Code.exe | two | \" - \": bad segment index"
+        );
+        let unknown = Config::parse("[project]\nkeyword = Code.exe | only\n").unwrap();
+        assert_eq!(
+            ProjectDetector::from_config(&unknown).err().unwrap(),
+            "[project] keyword = Code.exe | only: unknown extractor"
+        );
+    }
+}
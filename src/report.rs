@@ -0,0 +1,297 @@
+use cli::{self, CliError};
+use csv;
+use focus::{self, FocusMetrics};
+use json;
+use logger::{self, Entry};
+use session::{self, Session, SessionSettings};
+use snapshot::{self, ScreenRecord};
+
+use std::collections::HashMap;
+use std::fs;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Grouping {
+    Application,
+    Project,
+    Site,
+}
+
+impl Grouping {
+    pub fn parse(name: &str) -> Option<Grouping> {
+        match name {
+            "app" | "application" => Some(Grouping::Application),
+            "project" => Some(Grouping::Project),
+            "site" => Some(Grouping::Site),
+            _ => None,
+        }
+    }
+
+    pub fn key(&self, entry: &Entry) -> String {
+        if entry.activity.idle {
+            return "(idle)".to_string();
+        }
+        if *self == Grouping::Application {
+            if let Some(note) = entry.activity.access_note() {
+                let name = entry.activity.application_name();
+                return if name.is_empty() {
+                    format!("({})", note)
+                } else {
+                    format!("{} ({})", name, note)
+                };
+            }
+        }
+
+        let key = match *self {
+            Grouping::Application => entry.activity.application_name(),
+            Grouping::Project => entry.activity.project.as_str(),
+            Grouping::Site => entry.activity.site.as_str(),
+        };
+        if key.is_empty() {
+            "(none)".to_string()
+        } else {
+            key.to_string()
+        }
+    }
+}
+
+pub fn read_screen_entries(file_name: &str) -> Result<Vec<Entry>, String> {
+    let text = fs::read_to_string(file_name).map_err(|e| format!("{}: {}", file_name, e))?;
+    Ok(csv::parse_records(&text)
+        .iter()
+        .filter_map(|t| ScreenRecord::from_record(t))
+        .map(|t| t.into_entry())
+        .filter(|t| t.duration_in_seconds > 0)
+        .collect())
+}
+
+pub fn read_entries(file_name: &str) -> Result<Vec<Entry>, String> {
+    let text = fs::read_to_string(file_name).map_err(|e| format!("{}: {}", file_name, e))?;
+    Ok(csv::parse_records(&text)
+        .iter()
+        .filter_map(|t| Entry::from_record(t))
+        .filter(|t| t.duration_in_seconds > 0)
+        .collect())
+}
+
+// total seconds per group, longest first
+pub fn summarize(entries: &[Entry], grouping: Grouping) -> Vec<(String, u64)> {
+    let mut totals: HashMap<String, u64> = HashMap::new();
+    for entry in entries {
+        *totals.entry(grouping.key(entry)).or_insert(0) += entry.duration_in_seconds as u64;
+    }
+    let mut result: Vec<(String, u64)> = totals.into_iter().collect();
+    result.sort_by(|x, y| y.1.cmp(&x.1).then_with(|| x.0.cmp(&y.0)));
+    result
+}
+
+pub fn format_duration(seconds: u64) -> String {
+    format!(
+        "{}:{:02}:{:02}",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+// runs of entries from the same day, for entries in the order they were logged
+pub fn split_by_day(entries: &[Entry]) -> Vec<&[Entry]> {
+    let day = |t: &Entry| (t.timestamp.wYear, t.timestamp.wMonth, t.timestamp.wDay);
+    let mut days = Vec::new();
+    let mut first = 0;
+    for index in 1..entries.len() {
+        if day(&entries[index]) != day(&entries[index - 1]) {
+            days.push(&entries[first..index]);
+            first = index;
+        }
+    }
+    if first < entries.len() {
+        days.push(&entries[first..]);
+    }
+    days
+}
+
+// e.g. "active 6:12:00  switches 41 (6.6/h)  longest 1:25:00  median 0:12:30  return 0:02:10  score 54"
+pub fn format_focus_metrics(metrics: &FocusMetrics) -> String {
+    format!(
+        "active {}  switches {} ({:.1}/h)  longest {}  median {}  return {}  score {}",
+        format_duration(metrics.active_in_seconds),
+        metrics.switches,
+        metrics.switches_per_hour(),
+        format_duration(metrics.longest_block_in_seconds as u64),
+        format_duration(metrics.median_session_in_seconds as u64),
+        metrics
+            .median_time_to_return_in_seconds
+            .map_or("-".to_string(), |t| format_duration(t as u64)),
+        metrics.focus_score
+    )
+}
+
+// The entries of one day as /api/summary and the summary webhook have them:
+//
+// {"date": "2026-10-19", "applications": [{"name": "...", "seconds": 5400}, ...],
+//  "focus": {"active_seconds": ..., "switches": ..., "switches_per_hour": ...,
+//            "longest_block_seconds": ..., "median_session_seconds": ...,
+//            "median_time_to_return_seconds": ... or null, "score": ...}}
+pub fn day_summary(
+    date: (u16, u16, u16),
+    entries: &[Entry],
+    tolerance_in_seconds: u32,
+) -> json::Value {
+    let metrics = focus::focus_metrics(entries, tolerance_in_seconds);
+    let applications = summarize(entries, Grouping::Application)
+        .into_iter()
+        .map(|(name, seconds)| {
+            json::object(vec![("name", name.into()), ("seconds", seconds.into())])
+        })
+        .collect();
+    json::object(vec![
+        (
+            "date",
+            format!("{}-{:02}-{:02}", date.0, date.1, date.2).into(),
+        ),
+        ("applications", json::Value::Array(applications)),
+        (
+            "focus",
+            json::object(vec![
+                ("active_seconds", metrics.active_in_seconds.into()),
+                ("switches", (metrics.switches as u64).into()),
+                (
+                    "switches_per_hour",
+                    json::Value::Number(metrics.switches_per_hour()),
+                ),
+                (
+                    "longest_block_seconds",
+                    (metrics.longest_block_in_seconds as u64).into(),
+                ),
+                (
+                    "median_session_seconds",
+                    (metrics.median_session_in_seconds as u64).into(),
+                ),
+                (
+                    "median_time_to_return_seconds",
+                    metrics
+                        .median_time_to_return_in_seconds
+                        .map_or(json::Value::Null, |t| (t as u64).into()),
+                ),
+                ("score", (metrics.focus_score as u64).into()),
+            ]),
+        ),
+    ])
+}
+
+// e.g. "2026-10-19 09:30:00  1:25:00  Visual Studio Code (3 interruptions)"
+pub fn format_session(session: &Session) -> String {
+    let start = session.start;
+    let mut text = format!(
+        "{}-{:02}-{:02} {:02}:{:02}:{:02}  {:>8}  {}",
+        start.wYear,
+        start.wMonth,
+        start.wDay,
+        start.wHour,
+        start.wMinute,
+        start.wSecond,
+        format_duration(session.length_in_seconds() as u64),
+        session.key
+    );
+    match session.interruptions {
+        0 => {}
+        1 => text += " (1 interruption)",
+        n => text += &format!(" ({} interruptions)", n),
+    }
+    text
+}
+
+// record-usage report [--by app|project|site] [--on-screen] [--file <log file>]
+//                     [--sessions raw|smoothed] [--focus] [--tolerance <seconds>]
+// --on-screen sums up the visible window snapshots instead of the focused windows
+// --focus shows the focus metrics of each day
+// --sessions lists the sessions instead, smoothed with the tolerance of [sessions] unless
+// one is given
+pub fn run(args: &[String]) -> Result<(), CliError> {
+    let mut grouping = Grouping::Application;
+    let mut on_screen = false;
+    let mut file_name = None;
+    let mut sessions = None;
+    let mut focus = false;
+    let mut tolerance_in_seconds = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--by" => {
+                let name = args.next().map(|t| t.as_str()).unwrap_or("");
+                grouping = Grouping::parse(name)
+                    .ok_or(CliError::usage(&format!("unknown grouping: {}", name)))?;
+            }
+            "--on-screen" => on_screen = true,
+            "--sessions" => {
+                let view = args.next().map(|t| t.as_str()).unwrap_or("");
+                sessions = match view {
+                    "raw" => Some(false),
+                    "smoothed" => Some(true),
+                    _ => return Err(CliError::usage(&format!("unknown sessions view: {}", view))),
+                };
+            }
+            "--focus" => focus = true,
+            "--tolerance" => {
+                tolerance_in_seconds = Some(cli::seconds_argument(arg, args.next())?);
+            }
+            "--file" => {
+                file_name = Some(
+                    args.next()
+                        .ok_or(CliError::usage("--file needs a file name"))?
+                        .clone(),
+                );
+            }
+            _ => return Err(CliError::usage(&format!("unknown argument: {}", arg))),
+        }
+    }
+
+    if on_screen && (sessions.is_some() || focus) {
+        return Err(CliError::usage(
+            "--on-screen: windows on screen overlap, they make no sessions",
+        ));
+    }
+    if sessions.is_some() && focus {
+        return Err(CliError::usage("--sessions and --focus: one at a time"));
+    }
+
+    let entries = if on_screen {
+        read_screen_entries(&file_name.unwrap_or_else(snapshot::screen_log_file_name))?
+    } else {
+        read_entries(&file_name.unwrap_or_else(logger::log_file_name))?
+    };
+    let tolerance_in_seconds = match tolerance_in_seconds {
+        Some(t) => t,
+        None if sessions == Some(true) || focus => SessionSettings::load()?.tolerance_in_seconds,
+        None => 0,
+    };
+    if focus {
+        for day in split_by_day(&entries) {
+            let date = day[0].timestamp;
+            let metrics = focus::focus_metrics(day, tolerance_in_seconds);
+            println!(
+                "{}-{:02}-{:02}  {}",
+                date.wYear,
+                date.wMonth,
+                date.wDay,
+                format_focus_metrics(&metrics)
+            );
+        }
+        return Ok(());
+    }
+    if let Some(smoothed) = sessions {
+        let mut sessions = session::sessions(&entries, grouping);
+        if smoothed {
+            sessions = session::smooth(sessions, tolerance_in_seconds);
+        }
+        for session in &sessions {
+            println!("{}", format_session(session));
+        }
+        return Ok(());
+    }
+    for (key, seconds) in summarize(&entries, grouping) {
+        println!("{:>10}  {}", format_duration(seconds), key);
+    }
+    Ok(())
+}
@@ -0,0 +1,167 @@
+use browser::{self, Browser, SiteResolver};
+use config::{self, Config};
+use goals::{self, Goal};
+use idle::IdleDetector;
+use logger::Activity;
+use project::ProjectDetector;
+use session::SessionSettings;
+use snapshot::SnapshotSettings;
+use upload::UploadSettings;
+use webhook::WebhookSettings;
+
+use std::fs;
+use std::time::SystemTime;
+
+//
+// [limits]
+// ; the most characters stored per field, 0 for no limit
+// title = 1024
+// command_line = 8192
+//
+pub struct Limits {
+    pub max_title_length: usize,
+    pub max_command_line_length: usize,
+}
+
+pub const TRUNCATION_MARKER: &str = "\u{2026}[truncated]";
+
+impl Default for Limits {
+    fn default() -> Limits {
+        Limits {
+            max_title_length: 1024,
+            max_command_line_length: 8192,
+        }
+    }
+}
+
+impl Limits {
+    pub fn from_config(config: &Config) -> Result<Limits, String> {
+        let mut limits = Limits::default();
+        for (key, value) in config.entries("limits") {
+            let length = value
+                .parse::<usize>()
+                .map_err(|_| format!("[limits] {} = {}: expected a number", key, value))?;
+            match key {
+                "title" => limits.max_title_length = length,
+                "command_line" => limits.max_command_line_length = length,
+                _ => return Err(format!("[limits] {}: unknown limit", key)),
+            }
+        }
+        Ok(limits)
+    }
+}
+
+fn truncate(text: &mut String, max_length: usize) {
+    if max_length == 0 {
+        return;
+    }
+    if let Some((position, _)) = text.char_indices().nth(max_length) {
+        text.truncate(position);
+        text.push_str(TRUNCATION_MARKER);
+    }
+}
+
+//
+// [environment]
+// ; capture = <variable name> | <variable name> | ...
+// capture = PROJECT_ROOT | VIRTUAL_ENV
+//
+// Only these variables are read from a process, and only with full access to it.
+//
+fn environment_from_config(config: &Config) -> Result<Vec<String>, String> {
+    let mut variables = Vec::new();
+    for (key, value) in config.entries("environment") {
+        if key != "capture" {
+            return Err(format!(
+                "[environment] {}: expected capture = <variable>",
+                key
+            ));
+        }
+        variables.extend(
+            config::split_list(value)
+                .into_iter()
+                .filter(|t| !t.is_empty()),
+        );
+    }
+    Ok(variables)
+}
+
+// everything configured in record-usage.ini that is applied to each sample
+#[derive(Default)]
+pub struct Rules {
+    pub project_detector: ProjectDetector,
+    pub site_resolver: SiteResolver,
+    pub limits: Limits,
+    pub environment_variables: Vec<String>,
+    pub idle_detector: IdleDetector,
+    pub snapshot: SnapshotSettings,
+    pub goals: Vec<Goal>,
+    pub webhook: WebhookSettings,
+    pub upload: UploadSettings,
+    pub sessions: SessionSettings,
+}
+
+impl Rules {
+    pub fn from_config(config: &Config) -> Result<Rules, String> {
+        Ok(Rules {
+            project_detector: ProjectDetector::from_config(config)?,
+            site_resolver: SiteResolver::from_config(config)?,
+            limits: Limits::from_config(config)?,
+            environment_variables: environment_from_config(config)?,
+            idle_detector: IdleDetector::from_config(config)?,
+            snapshot: SnapshotSettings::from_config(config)?,
+            goals: goals::goals_from_config(config)?,
+            webhook: WebhookSettings::from_config(config)?,
+            upload: UploadSettings::from_config(config)?,
+            sessions: SessionSettings::from_config(config)?,
+        })
+    }
+
+    pub fn apply(&self, activity: &mut Activity) {
+        if let Some(browser) = Browser::from_image_name(activity.image_name()) {
+            activity.page_title = browser::page_title(browser, &activity.window_title);
+            activity.site = self.site_resolver.resolve(&activity.page_title);
+        }
+        activity.project = self.project_detector.detect(activity);
+
+        // only after everything above has seen the full text
+        truncate(&mut activity.window_title, self.limits.max_title_length);
+        truncate(&mut activity.page_title, self.limits.max_title_length);
+        truncate(
+            &mut activity.command_line,
+            self.limits.max_command_line_length,
+        );
+    }
+}
+
+// Tells when the configuration file has changed, by its modification time. A file that
+// goes away counts as a change as well, back to the defaults.
+pub struct RulesWatcher {
+    file_name: String,
+    modified: Option<SystemTime>,
+}
+
+impl RulesWatcher {
+    pub fn new(file_name: String) -> RulesWatcher {
+        RulesWatcher {
+            file_name,
+            modified: None,
+        }
+    }
+
+    fn modified_time(&self) -> Option<SystemTime> {
+        fs::metadata(&self.file_name)
+            .and_then(|t| t.modified())
+            .ok()
+    }
+
+    pub fn is_changed(&self) -> bool {
+        self.modified_time() != self.modified
+    }
+
+    // an invalid file is not looked at again until it changes
+    pub fn load(&mut self) -> Result<Rules, String> {
+        self.modified = self.modified_time();
+        Config::load(&self.file_name).and_then(|t| Rules::from_config(&t))
+    }
+}
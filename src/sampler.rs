@@ -0,0 +1,573 @@
+use config;
+use goals::{GoalTracker, Notification};
+use json;
+use logger::{self, log, log_idle, Activity};
+use metrics;
+use report;
+use rules::{Rules, RulesWatcher};
+use session::SessionSettings;
+use snapshot::{self, ScreenLogger};
+use upload::Uploader;
+use webhook::{self, EventKind, Outbox};
+use win32helper;
+use {CHECK_INTERNVAL_IN_SECONDS, FLUSH_INTERVAL_IN_MINUTES};
+
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+
+extern crate winapi;
+use self::winapi::shared::winerror;
+
+const WINDOW_TEXT_TIMEOUT_IN_MILLISECONDS: u32 = 500;
+
+enum Request {
+    Sample,
+    // there is something on the control channel
+    Control,
+    // a Controller may still hold a sender, so the channel alone does not tell
+    Stop,
+}
+
+// Nothing that sends these may have to wait for a sample, so they go through a channel of
+// their own that is drained between two requests.
+enum Control {
+    // answers whether the configuration could be loaded
+    Reload(mpsc::Sender<Result<(), String>>),
+    // answers once everything has been written, if anybody waits for that
+    Flush(Option<mpsc::Sender<()>>),
+    // the session was locked, or unlocked
+    Lock(bool),
+}
+
+// what the window and the status page show, as of the last sample
+#[derive(Clone, Default)]
+pub struct Status {
+    pub activity: Activity,
+    // tracked time since midnight, not counting idle time
+    pub today_in_seconds: u32,
+}
+
+// what the webhook events are made of, as of the last sample
+struct EventState {
+    // application, project and site
+    activity: Option<(String, String, String)>,
+    idle: bool,
+    day: (u16, u16, u16),
+}
+
+// Samples are taken on a thread of their own, so that a hung target process can at worst
+// hold up the next sample, never the message loop that drives the timer. Writing the logs
+// happens there as well, so that nothing else touches them while a sample is taken.
+// The rules live there too and are only ever swapped between two samples.
+pub struct Sampler {
+    requests: mpsc::SyncSender<Request>,
+    controls: mpsc::Sender<Control>,
+    thread: thread::JoinHandle<()>,
+    // why the configuration in use is not the one in the file
+    config_error: Arc<Mutex<Option<String>>>,
+    goal_tracker: Arc<Mutex<GoalTracker>>,
+    // those of the rules in use, for the pages that show sessions
+    session_settings: Arc<Mutex<SessionSettings>>,
+    // the logger belongs to the sampler thread, everybody else reads this copy
+    status: Arc<Mutex<Status>>,
+    // goals crossed since last asked
+    notifications: mpsc::Receiver<Notification>,
+}
+
+impl Sampler {
+    pub fn start() -> Sampler {
+        // room for one pending request besides the one being worked on
+        let (sender, receiver) = mpsc::sync_channel::<Request>(1);
+        let (control_sender, control_receiver) = mpsc::channel::<Control>();
+        let (notification_sender, notifications) = mpsc::channel();
+        let config_error = Arc::new(Mutex::new(None));
+        let goal_tracker = Arc::new(Mutex::new(GoalTracker::new()));
+        let thread_config_error = config_error.clone();
+        let session_settings = Arc::new(Mutex::new(SessionSettings::default()));
+        let status = Arc::new(Mutex::new(Status::default()));
+        let thread_goal_tracker = goal_tracker.clone();
+        let thread_session_settings = session_settings.clone();
+        let thread_status = status.clone();
+        let thread = thread::spawn(move || {
+            let config_error = thread_config_error;
+            let goal_tracker = thread_goal_tracker;
+            let session_settings = thread_session_settings;
+            let status = thread_status;
+            let mut watcher = RulesWatcher::new(config::config_file_name());
+            let mut rules = Rules::default();
+            reload_rules(&mut watcher, &mut rules, &config_error).ok();
+            // for the audio sessions; without COM only that hint is lost
+            if let Err(e) = win32helper::co_initialize_multithreaded() {
+                println!("{}", e);
+            }
+            let mut screen_logger = None;
+            let uploader = Uploader::start(logger::log_file_name());
+            apply_rules(
+                &rules,
+                &mut screen_logger,
+                &goal_tracker,
+                &session_settings,
+                &uploader,
+            );
+            let outbox = Outbox::start(webhook::outbox_file_name());
+            let mut events = EventState {
+                activity: None,
+                idle: false,
+                day: today(),
+            };
+            for request in receiver.iter() {
+                for control in control_receiver.try_iter() {
+                    match control {
+                        Control::Reload(reply) => {
+                            let result = reload_rules(&mut watcher, &mut rules, &config_error);
+                            apply_rules(
+                                &rules,
+                                &mut screen_logger,
+                                &goal_tracker,
+                                &session_settings,
+                                &uploader,
+                            );
+                            reply.send(result).ok();
+                        }
+                        Control::Flush(reply) => {
+                            logger::flush();
+                            update_status(&status);
+                            if let Some(ref mut screen_logger) = screen_logger {
+                                screen_logger.flush();
+                            }
+                            uploader.request_upload();
+                            if let Some(reply) = reply {
+                                reply.send(()).ok();
+                            }
+                        }
+                        Control::Lock(locked) => {
+                            let name = if locked { "lock" } else { "unlock" };
+                            let event = webhook::event(name, json::object(vec![]));
+                            outbox.post(&rules.webhook, EventKind::Lock, event);
+                        }
+                    }
+                }
+                match request {
+                    Request::Sample => {
+                        if watcher.is_changed() {
+                            reload_rules(&mut watcher, &mut rules, &config_error).ok();
+                            apply_rules(
+                                &rules,
+                                &mut screen_logger,
+                                &goal_tracker,
+                                &session_settings,
+                                &uploader,
+                            );
+                        }
+                        let activity = get_foreground_app(&rules);
+                        update_status(&status);
+                        metrics::count_sample(activity.as_ref(), CHECK_INTERNVAL_IN_SECONDS);
+                        post_sample_events(&rules, &outbox, &mut events, activity.as_ref());
+                        if let Some(ref activity) = activity {
+                            let crossed = goal_tracker
+                                .lock()
+                                .unwrap()
+                                .add_sample(activity, CHECK_INTERNVAL_IN_SECONDS);
+                            for notification in crossed {
+                                notify(&rules, &outbox, notification, &notification_sender);
+                            }
+                        }
+                        if let Some(ref mut screen_logger) = screen_logger {
+                            let snapshot = if activity.is_some() {
+                                snapshot::take_snapshot(&rules)
+                            } else {
+                                Vec::new()
+                            };
+                            screen_logger.add_snapshot(snapshot);
+                        }
+                    }
+                    Request::Control => {}
+                    Request::Stop => break,
+                }
+            }
+            // stopped; dropping the screen logger writes out what it still has
+            logger::flush();
+        });
+        Sampler {
+            requests: sender,
+            controls: control_sender,
+            thread,
+            config_error,
+            goal_tracker,
+            session_settings,
+            status,
+            notifications,
+        }
+    }
+
+    // never blocks; a tick that finds the sampler still busy is dropped
+    pub fn request_sample(&self) {
+        match self.requests.try_send(Request::Sample) {
+            Ok(()) => {}
+            Err(mpsc::TrySendError::Full(_)) => println!("Sampler is busy, skipping a sample"),
+            Err(mpsc::TrySendError::Disconnected(_)) => println!("Sampler has stopped"),
+        }
+    }
+
+    // never blocks; see Controller for a flush that can be waited for
+    pub fn request_flush(&self) {
+        send_control(&self.requests, &self.controls, Control::Flush(None));
+    }
+
+    // for threads that may wait for the answer, see Controller
+    pub fn controller(&self) -> Controller {
+        Controller {
+            requests: self.requests.clone(),
+            controls: self.controls.clone(),
+        }
+    }
+
+    // never blocks
+    pub fn report_lock(&self, locked: bool) {
+        send_control(&self.requests, &self.controls, Control::Lock(locked));
+    }
+
+    pub fn config_error(&self) -> Option<String> {
+        self.config_error.lock().unwrap().clone()
+    }
+
+    // for the status page, which lives on a thread of its own
+    pub fn goal_tracker(&self) -> Arc<Mutex<GoalTracker>> {
+        self.goal_tracker.clone()
+    }
+
+    // for the web server, like the goal tracker
+    pub fn session_settings(&self) -> Arc<Mutex<SessionSettings>> {
+        self.session_settings.clone()
+    }
+
+    // for the window and the status page, like the goal tracker
+    pub fn status(&self) -> Arc<Mutex<Status>> {
+        self.status.clone()
+    }
+
+    pub fn take_notifications(&self) -> Vec<Notification> {
+        self.notifications.try_iter().collect()
+    }
+
+    // returns once everything has been written
+    pub fn stop(self) {
+        if self.requests.send(Request::Stop).is_err() {
+            println!("Sampler has stopped");
+        }
+        if self.thread.join().is_err() {
+            println!("Sampler has panicked");
+        }
+    }
+}
+
+// only ever called on the sampler thread, which is the one that logs
+fn update_status(status: &Mutex<Status>) {
+    *status.lock().unwrap() = Status {
+        activity: logger::get_last_entry(),
+        today_in_seconds: logger::get_today_in_seconds(),
+    };
+}
+
+// A full request channel means the thread is busy and will look at the controls before the
+// pending request anyway.
+fn send_control(
+    requests: &mpsc::SyncSender<Request>,
+    controls: &mpsc::Sender<Control>,
+    control: Control,
+) {
+    if controls.send(control).is_err() {
+        println!("Sampler has stopped");
+        return;
+    }
+    requests.try_send(Request::Control).ok();
+}
+
+// What the control channel asks for and waits to be done. The answers have to wait for the
+// sample being taken, so this is for the control channel threads, not the window.
+#[derive(Clone)]
+pub struct Controller {
+    requests: mpsc::SyncSender<Request>,
+    controls: mpsc::Sender<Control>,
+}
+
+impl Controller {
+    // reads the configuration file now instead of when it changes
+    pub fn reload(&self) -> Result<(), String> {
+        let (sender, receiver) = mpsc::channel();
+        send_control(&self.requests, &self.controls, Control::Reload(sender));
+        receiver
+            .recv()
+            .unwrap_or(Err("Sampler has stopped".to_string()))
+    }
+
+    pub fn flush(&self) -> Result<(), String> {
+        let (sender, receiver) = mpsc::channel();
+        send_control(&self.requests, &self.controls, Control::Flush(Some(sender)));
+        receiver
+            .recv()
+            .map_err(|_| "Sampler has stopped".to_string())
+    }
+}
+
+// the previous rules stay when the new ones cannot be loaded
+fn reload_rules(
+    watcher: &mut RulesWatcher,
+    rules: &mut Rules,
+    config_error: &Mutex<Option<String>>,
+) -> Result<(), String> {
+    let result = watcher.load().map(|t| *rules = t);
+    if let Err(ref e) = result {
+        println!("{}", e);
+    }
+    *config_error.lock().unwrap() = result.clone().err();
+    result
+}
+
+fn apply_rules(
+    rules: &Rules,
+    screen_logger: &mut Option<ScreenLogger>,
+    goal_tracker: &Mutex<GoalTracker>,
+    session_settings: &Mutex<SessionSettings>,
+    uploader: &Uploader,
+) {
+    update_screen_logger(screen_logger, rules);
+    goal_tracker.lock().unwrap().set_goals(&rules.goals);
+    *session_settings.lock().unwrap() = rules.sessions.clone();
+    uploader.set_settings(&rules.upload);
+}
+
+// the notification area is the main window's, so it gets the notification to show
+fn notify(
+    rules: &Rules,
+    outbox: &Outbox,
+    notification: Notification,
+    sender: &mpsc::Sender<Notification>,
+) {
+    println!("goal  : {}: {}", notification.title, notification.text);
+    let event = webhook::event("goal", notification.to_json());
+    outbox.post(&rules.webhook, EventKind::Goal, event);
+    sender.send(notification).ok();
+}
+
+fn today() -> (u16, u16, u16) {
+    let now = win32helper::get_local_time();
+    (now.wYear, now.wMonth, now.wDay)
+}
+
+// compares the sample with the one before; the activity is None when idle
+fn post_sample_events(
+    rules: &Rules,
+    outbox: &Outbox,
+    events: &mut EventState,
+    activity: Option<&Activity>,
+) {
+    let settings = &rules.webhook;
+    let idle = activity.is_none();
+    if idle != events.idle {
+        let name = if idle { "idle_start" } else { "idle_end" };
+        outbox.post(
+            settings,
+            EventKind::Idle,
+            webhook::event(name, json::object(vec![])),
+        );
+        events.idle = idle;
+    }
+
+    let key = activity.map(|t| {
+        (
+            t.application_name().to_string(),
+            t.project.clone(),
+            t.site.clone(),
+        )
+    });
+    if let Some(activity) = activity {
+        if key != events.activity {
+            let event = webhook::event(
+                "activity",
+                json::object(vec![
+                    ("application", activity.application_name().into()),
+                    ("image_name", activity.image_name().into()),
+                    ("window_title", activity.window_title.as_str().into()),
+                    ("project", activity.project.as_str().into()),
+                    ("site", activity.site.as_str().into()),
+                ]),
+            );
+            outbox.post(settings, EventKind::Activity, event);
+        }
+    }
+    events.activity = key;
+
+    // the day before is complete in the log once it is flushed
+    let day = today();
+    if day != events.day {
+        if settings.wants(EventKind::Summary) {
+            logger::flush();
+            let entries: Vec<_> = report::read_entries(&logger::log_file_name())
+                .unwrap_or_default()
+                .into_iter()
+                .filter(|t| (t.timestamp.wYear, t.timestamp.wMonth, t.timestamp.wDay) == events.day)
+                .collect();
+            let summary =
+                report::day_summary(events.day, &entries, rules.sessions.tolerance_in_seconds);
+            outbox.post(
+                settings,
+                EventKind::Summary,
+                webhook::event("summary", summary),
+            );
+        }
+        events.day = day;
+    }
+}
+
+// the screen log is opened and closed as the snapshot setting comes and goes
+fn update_screen_logger(screen_logger: &mut Option<ScreenLogger>, rules: &Rules) {
+    if !rules.snapshot.visible_windows {
+        *screen_logger = None;
+    } else if screen_logger.is_none() {
+        *screen_logger = Some(ScreenLogger::new(
+            CHECK_INTERNVAL_IN_SECONDS,
+            FLUSH_INTERVAL_IN_MINUTES,
+        ));
+    }
+}
+
+// what was logged, None when nobody is there
+fn get_foreground_app(rules: &Rules) -> Option<Activity> {
+    let mut activity = Activity::default();
+
+    win32helper::output_timestamp();
+    println!();
+    let foreground_window = win32helper::get_foreground_window();
+    if let Some(window_handle) = foreground_window {
+        // whatever could be found out before a failure is still worth logging
+        if let Err(e) =
+            get_window_activity(window_handle, &rules.environment_variables, &mut activity)
+        {
+            println!("{}", e);
+            metrics::count_sample_failure(&metrics::failure_reason(e.function));
+        } else if activity.command_line.is_empty() {
+            metrics::count_sample_failure("empty_command_line");
+        }
+    }
+
+    // no input alone does not make it idle, the foreground app may be watched or listened to
+    if let Some(idle_in_seconds) = rules.idle_detector.idle_time_in_seconds() {
+        match rules
+            .idle_detector
+            .active_without_input(foreground_window, &activity)
+        {
+            Some(reason) => println!("idle  : {} seconds, but {:?}", idle_in_seconds, reason),
+            None => {
+                println!("idle  : {} seconds", idle_in_seconds);
+                println!();
+                log_idle(idle_in_seconds);
+                return None;
+            }
+        }
+    }
+
+    rules.apply(&mut activity);
+    println!("text  : {}", activity.window_title);
+    println!("cmdln : {}", activity.command_line);
+    println!("image : {}", activity.image_path);
+    println!("cwd   : {}", activity.working_directory);
+    println!("pkg   : {}", activity.package_family_name);
+    println!("aumid : {}", activity.app_user_model_id);
+    println!("ppid  : {}", activity.parent_process_id);
+    println!("access: {}", activity.process_access.as_str());
+    println!(
+        "prod  : {} ({})",
+        activity.product_name, activity.company_name
+    );
+    println!("proj  : {}", activity.project);
+    println!("site  : {}", activity.site);
+    println!("hung  : {}", activity.not_responding);
+    println!(
+        "env   : {}",
+        activity.environment_text().replace('\n', "; ")
+    );
+    println!();
+
+    log(activity.clone());
+    Some(activity)
+}
+
+fn get_window_activity(
+    window_handle: win32helper::HWND,
+    environment_variables: &[String],
+    activity: &mut Activity,
+) -> win32helper::Win32Result<()> {
+    // GetWindowTextW does not send WM_GETTEXT to windows of other processes, so it is the
+    // safe choice for a window already known to be hung; otherwise ask with a timeout, and a
+    // window that lets it pass is not responding either
+    activity.not_responding = win32helper::is_hung_app_window(window_handle);
+    let title = if activity.not_responding {
+        None
+    } else {
+        match win32helper::get_window_text_timeout(
+            window_handle,
+            WINDOW_TEXT_TIMEOUT_IN_MILLISECONDS,
+        ) {
+            Ok(t) => Some(t),
+            Err(e) => {
+                println!("{}", e);
+                activity.not_responding =
+                    e.code == win32helper::ErrorCode::LastError(winerror::ERROR_TIMEOUT);
+                None
+            }
+        }
+    };
+    activity.window_title = match title {
+        Some(t) => t,
+        None => win32helper::get_window_text(window_handle).unwrap_or_default(),
+    };
+    let mut window_handle = window_handle;
+    let mut process_id = win32helper::get_window_process_id(window_handle)?;
+
+    // Only ApplicationFrameHost, which runs unelevated, stands in for another process; any
+    // other window that cannot even be opened with limited access is left to
+    // get_process_information(), which knows how to make do with less.
+    let is_immersive = win32helper::open_process_limited(process_id)
+        .map(|t| win32helper::is_immersive_process(&t))
+        .unwrap_or(false);
+    if is_immersive {
+        match win32helper::get_universal_app(window_handle, process_id) {
+            Some(app) => {
+                window_handle = app.window_handle;
+                process_id = app.process_id;
+            }
+            None => println!(
+                "No universal app found for host window {:?}",
+                activity.window_title
+            ),
+        }
+
+        // Note: I'm not refreshing get_window_text() for universal app here, because if we do so,
+        // the result seems always to be a static string like "Microsoft Edge", which is not useful.
+        // Besides, previous result from the parent app already reads as "CreateFileW - Google Search
+        // and 2 more pages ‎- Microsoft Edge", and I'd rather keep that.
+    }
+    println!("hwnd  : {:?}", window_handle);
+    println!("pid   : {:?}", process_id);
+
+    let information = win32helper::get_process_information(process_id, environment_variables);
+    for e in &information.failures {
+        metrics::count_sample_failure(&metrics::failure_reason(e.function));
+    }
+    activity.process_access = information.access;
+    activity.image_path = information.image_path;
+    activity.command_line = information.command_line;
+    activity.working_directory = information.current_directory;
+    activity.parent_process_id = information.parent_process_id;
+    activity.package_family_name = information.package_family_name;
+    activity.app_user_model_id = information.app_user_model_id;
+    activity.environment = information.environment;
+
+    // plenty of executables come without a version resource
+    if let Ok(version_info) = win32helper::get_file_version_strings(&activity.image_path) {
+        activity.product_name = version_info.product_name;
+        activity.company_name = version_info.company_name;
+    }
+    Ok(())
+}
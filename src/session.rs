@@ -0,0 +1,280 @@
+//
+// Consecutive entries that group the same way make one session, e.g. the two hours spent in
+// one project, however often the window title changed meanwhile.
+//
+// [sessions]
+// ; seconds spent elsewhere that do not end a session, e.g. a glance at the chat
+// tolerance = 30
+//
+// Those are the raw sessions and the smoothed ones. Smoothing hands the time of short
+// interruptions to the session around them.
+//
+
+use config::{self, Config};
+use logger::{Activity, Entry};
+use report::Grouping;
+use win32helper;
+use CHECK_INTERNVAL_IN_SECONDS;
+
+extern crate winapi;
+use self::winapi::um::minwinbase;
+
+#[derive(Clone)]
+pub struct SessionSettings {
+    pub tolerance_in_seconds: u32,
+}
+
+impl Default for SessionSettings {
+    fn default() -> SessionSettings {
+        SessionSettings {
+            tolerance_in_seconds: 30,
+        }
+    }
+}
+
+impl SessionSettings {
+    pub fn load() -> Result<SessionSettings, String> {
+        Config::load(&config::config_file_name()).and_then(|t| SessionSettings::from_config(&t))
+    }
+
+    pub fn from_config(config: &Config) -> Result<SessionSettings, String> {
+        let mut settings = SessionSettings::default();
+        for (key, value) in config.entries("sessions") {
+            match key {
+                "tolerance" => {
+                    settings.tolerance_in_seconds = value
+                        .parse()
+                        .map_err(|_| format!("[sessions] {} = {}: expected seconds", key, value))?
+                }
+                _ => return Err(format!("[sessions] {}: unknown setting", key)),
+            }
+        }
+        Ok(settings)
+    }
+}
+
+#[derive(Clone)]
+pub struct Session {
+    pub start: minwinbase::SYSTEMTIME,
+    pub end: minwinbase::SYSTEMTIME,
+    // the time the entries account for
+    pub duration_in_seconds: u32,
+    // what it was grouped by, as the report shows it
+    pub key: String,
+    // of the first entry
+    pub activity: Activity,
+    // the shorter sessions that smoothing took in
+    pub interruptions: u32,
+}
+
+impl Session {
+    fn new(entry: &Entry, key: String) -> Session {
+        Session {
+            start: entry.timestamp,
+            end: entry.timestamp,
+            duration_in_seconds: 0,
+            key,
+            activity: entry.activity.clone(),
+            interruptions: 0,
+        }
+    }
+
+    // from start to end, gaps the session spans included
+    pub fn length_in_seconds(&self) -> u32 {
+        let start = win32helper::system_time_to_seconds(&self.start);
+        let end = win32helper::system_time_to_seconds(&self.end);
+        match (start, end) {
+            (Some(start), Some(end)) if end >= start => (end - start) as u32,
+            _ => self.duration_in_seconds,
+        }
+    }
+
+    // everything up to the end of the other one becomes part of this one
+    fn absorb(&mut self, other: Session) {
+        let end = win32helper::system_time_to_seconds(&self.end);
+        let other_end = win32helper::system_time_to_seconds(&other.end);
+        if other_end > end {
+            self.end = other.end;
+        }
+        self.duration_in_seconds += other.duration_in_seconds;
+        self.interruptions += other.interruptions;
+        if other.key != self.key {
+            self.interruptions += 1;
+        }
+    }
+
+    fn extend(&mut self, start: u64, duration_in_seconds: u32) {
+        self.end = win32helper::seconds_to_system_time(start + duration_in_seconds as u64)
+            .unwrap_or(self.end);
+        self.duration_in_seconds += duration_in_seconds;
+    }
+}
+
+// The raw sessions. Entries must be in the order they were logged. A gap of more than one
+// sample, e.g. while the tracker was not running, ends a session, and so does idle time,
+// which is never part of one.
+pub fn sessions(entries: &[Entry], grouping: Grouping) -> Vec<Session> {
+    let slack = CHECK_INTERNVAL_IN_SECONDS as u64;
+    let mut sessions: Vec<Session> = Vec::new();
+    // where the last session ends, in seconds since 1601
+    let mut last_end: Option<u64> = None;
+    for entry in entries {
+        let start = match win32helper::system_time_to_seconds(&entry.timestamp) {
+            Some(t) => t,
+            None => continue,
+        };
+        if entry.activity.idle {
+            last_end = None;
+            continue;
+        }
+
+        let key = grouping.key(entry);
+        let is_adjacent = last_end.is_some_and(|t| start + slack >= t && start <= t + slack);
+        if !is_adjacent || sessions.last().is_none_or(|t| t.key != key) {
+            sessions.push(Session::new(entry, key));
+        }
+        if let Some(session) = sessions.last_mut() {
+            session.extend(start, entry.duration_in_seconds);
+        }
+        last_end = Some(start + entry.duration_in_seconds as u64);
+    }
+    sessions
+}
+
+// A session that resumes within the tolerance takes in whatever came between, time spent
+// elsewhere or in no session at all, and continues.
+pub fn smooth(sessions: Vec<Session>, tolerance_in_seconds: u32) -> Vec<Session> {
+    let mut smoothed: Vec<Session> = Vec::new();
+    for session in sessions {
+        let start = win32helper::system_time_to_seconds(&session.start).unwrap_or(0);
+        let resumed = smoothed
+            .iter()
+            .rposition(|t| t.key == session.key)
+            .filter(|&index| {
+                let end = win32helper::system_time_to_seconds(&smoothed[index].end).unwrap_or(0);
+                start >= end && start - end <= tolerance_in_seconds as u64
+            });
+        match resumed {
+            Some(index) => {
+                let interruptions = smoothed.split_off(index + 1);
+                let resumed = &mut smoothed[index];
+                for interruption in interruptions {
+                    resumed.absorb(interruption);
+                }
+                resumed.absorb(session);
+            }
+            None => smoothed.push(session),
+        }
+    }
+    smoothed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use logger::parse_timestamp;
+
+    fn entry(time: &str, duration_in_seconds: u32, application: &str) -> Entry {
+        Entry {
+            timestamp: parse_timestamp(&format!("2026-10-19 {}", time)).unwrap(),
+            duration_in_seconds,
+            activity: Activity {
+                product_name: application.to_string(),
+                idle: application.is_empty(),
+                ..Default::default()
+            },
+        }
+    }
+
+    fn summary(sessions: &[Session]) -> Vec<(&str, u32, u32, u32)> {
+        sessions
+            .iter()
+            .map(|t| {
+                (
+                    t.key.as_str(),
+                    t.duration_in_seconds,
+                    t.length_in_seconds(),
+                    t.interruptions,
+                )
+            })
+            .collect()
+    }
+
+    // Code, a while in the chat, and Code again
+    fn interrupted(chat_in_seconds: u32) -> Vec<Entry> {
+        vec![
+            entry("9:00:00", 60, "Code"),
+            entry("9:01:00", 60, "Code"),
+            entry("9:02:00", chat_in_seconds, "Chat"),
+            entry(&format!("9:02:{:02}", chat_in_seconds), 60, "Code"),
+        ]
+    }
+
+    #[test]
+    fn adjacent_entries_make_one_session() {
+        let raw = sessions(&interrupted(30), Grouping::Application);
+        assert_eq!(
+            summary(&raw),
+            vec![
+                ("Code", 120, 120, 0),
+                ("Chat", 30, 30, 0),
+                ("Code", 60, 60, 0)
+            ]
+        );
+    }
+
+    #[test]
+    fn gap_ends_a_session() {
+        let entries = vec![entry("9:00:00", 60, "Code"), entry("9:05:00", 60, "Code")];
+        let raw = sessions(&entries, Grouping::Application);
+        assert_eq!(
+            summary(&raw),
+            vec![("Code", 60, 60, 0), ("Code", 60, 60, 0)]
+        );
+    }
+
+    #[test]
+    fn short_interruption_is_taken_in() {
+        let smoothed = smooth(sessions(&interrupted(20), Grouping::Application), 30);
+        assert_eq!(summary(&smoothed), vec![("Code", 200, 200, 1)]);
+    }
+
+    #[test]
+    fn tolerance_is_inclusive() {
+        let raw = sessions(&interrupted(30), Grouping::Application);
+        assert_eq!(
+            summary(&smooth(raw.clone(), 30)),
+            vec![("Code", 210, 210, 1)]
+        );
+        assert_eq!(summary(&smooth(raw, 29)).len(), 3);
+    }
+
+    #[test]
+    fn idle_time_is_in_no_session() {
+        let entries = vec![
+            entry("9:00:00", 60, "Code"),
+            entry("9:01:00", 20, ""),
+            entry("9:01:20", 60, "Code"),
+        ];
+        let raw = sessions(&entries, Grouping::Application);
+        assert_eq!(
+            summary(&raw),
+            vec![("Code", 60, 60, 0), ("Code", 60, 60, 0)]
+        );
+        // smoothing bridges it, but the idle time is not counted as active
+        assert_eq!(
+            summary(&smooth(raw.clone(), 30)),
+            vec![("Code", 120, 140, 0)]
+        );
+        assert_eq!(summary(&smooth(raw, 10)).len(), 2);
+    }
+
+    #[test]
+    fn settings_from_config() {
+        let config = Config::parse("[sessions]\ntolerance = 90\n").unwrap();
+        let settings = SessionSettings::from_config(&config).unwrap();
+        assert_eq!(settings.tolerance_in_seconds, 90);
+        let bad = Config::parse("[sessions]\ntolerance = -1\n").unwrap();
+        assert!(SessionSettings::from_config(&bad).is_err());
+    }
+}
@@ -0,0 +1,269 @@
+//
+// [snapshot]
+// ; besides the focused window, record every visible window on every monitor
+// visible_windows = yes
+//
+// These go to record-usage-screen.csv next to the log, one record per window and monitor
+// it shows on, so that reports can tell time on screen from time in focus.
+//
+
+use config::{self, Config};
+use csv;
+use logger::{self, Activity, Entry};
+use rules::Rules;
+use win32helper;
+
+use std::env;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::mem;
+use std::os::windows::fs::OpenOptionsExt;
+
+extern crate winapi;
+use self::winapi::{shared::windef, um::minwinbase, um::winnt};
+
+#[derive(Default)]
+pub struct SnapshotSettings {
+    pub visible_windows: bool,
+}
+
+impl SnapshotSettings {
+    pub fn from_config(config: &Config) -> Result<SnapshotSettings, String> {
+        let mut settings = SnapshotSettings::default();
+        for (key, value) in config.entries("snapshot") {
+            let flag = config::parse_bool(value).ok_or(format!(
+                "[snapshot] {} = {}: expected yes or no",
+                key, value
+            ));
+            match key {
+                "visible_windows" => settings.visible_windows = flag?,
+                _ => return Err(format!("[snapshot] {}: unknown setting", key)),
+            }
+        }
+        Ok(settings)
+    }
+}
+
+pub fn screen_log_file_name() -> String {
+    env::var("LOCALAPPDATA").unwrap() + "\\record-usage-screen.csv"
+}
+
+pub struct ScreenRecord {
+    pub timestamp: minwinbase::SYSTEMTIME,
+    pub duration_in_seconds: u32,
+    pub monitor: String,
+    // 0 is the topmost window; the best position seen while the record was open
+    pub z_order: u32,
+    // how much of the monitor the window covers; the most seen while the record was open
+    pub area_percent: u32,
+    pub activity: Activity,
+}
+
+impl ScreenRecord {
+    // the reverse of ScreenLogger::flush()
+    pub fn from_record(record: &[String]) -> Option<ScreenRecord> {
+        if record.len() < 7 {
+            return None;
+        }
+        let column = |index: usize| record.get(index).cloned().unwrap_or_default();
+        Some(ScreenRecord {
+            timestamp: logger::parse_timestamp(&record[0])?,
+            duration_in_seconds: record[1].parse().ok()?,
+            monitor: column(2),
+            z_order: column(3).parse().unwrap_or(0),
+            area_percent: column(4).parse().unwrap_or(0),
+            activity: Activity {
+                image_path: column(5),
+                window_title: column(6),
+                project: column(7),
+                page_title: column(8),
+                site: column(9),
+                ..Activity::default()
+            },
+        })
+    }
+
+    pub fn into_entry(self) -> Entry {
+        Entry {
+            timestamp: self.timestamp,
+            duration_in_seconds: self.duration_in_seconds,
+            activity: self.activity,
+        }
+    }
+}
+
+fn area(rect: &windef::RECT) -> i64 {
+    if rect.right <= rect.left || rect.bottom <= rect.top {
+        return 0;
+    }
+    (rect.right - rect.left) as i64 * (rect.bottom - rect.top) as i64
+}
+
+fn intersect(x: &windef::RECT, y: &windef::RECT) -> windef::RECT {
+    windef::RECT {
+        left: x.left.max(y.left),
+        top: x.top.max(y.top),
+        right: x.right.min(y.right),
+        bottom: x.bottom.min(y.bottom),
+    }
+}
+
+// Only the image path is looked up, and only once per process; reading the PEB of every
+// window on screen every few seconds is more than this is worth.
+pub fn take_snapshot(rules: &Rules) -> Vec<ScreenRecord> {
+    let now = win32helper::get_local_time();
+    let monitors = win32helper::get_monitors();
+    let mut image_paths: Vec<(u32, String)> = Vec::new();
+    let mut z_orders: Vec<u32> = vec![0; monitors.len()];
+    let mut records = Vec::new();
+
+    // the processes known to be immersive or not, so that each is opened once
+    let mut immersive: Vec<(u32, bool)> = Vec::new();
+
+    for window in win32helper::get_visible_windows() {
+        let mut process_id = window.process_id;
+        let is_immersive = match immersive.iter().find(|t| t.0 == process_id) {
+            Some(t) => t.1,
+            None => {
+                let is_immersive = win32helper::open_process_limited(process_id)
+                    .map(|t| win32helper::is_immersive_process(&t))
+                    .unwrap_or(false);
+                immersive.push((process_id, is_immersive));
+                is_immersive
+            }
+        };
+        // a frame whose app cannot be found stands for it
+        if is_immersive {
+            if let Some(app) = win32helper::get_universal_app(window.window_handle, process_id) {
+                process_id = app.process_id;
+            }
+        }
+        let image_path = match image_paths.iter().find(|t| t.0 == process_id) {
+            Some(t) => t.1.clone(),
+            None => {
+                let image_path = win32helper::open_process_limited(process_id)
+                    .and_then(|t| win32helper::query_full_process_image_name(&t))
+                    .unwrap_or_default();
+                image_paths.push((process_id, image_path.clone()));
+                image_path
+            }
+        };
+
+        let mut activity = Activity {
+            window_title: window.title,
+            image_path,
+            ..Activity::default()
+        };
+        rules.apply(&mut activity);
+
+        for (index, monitor) in monitors.iter().enumerate() {
+            let monitor_area = area(&monitor.rect);
+            let window_area = area(&intersect(&window.rect, &monitor.rect));
+            if monitor_area == 0 || window_area == 0 {
+                continue;
+            }
+            records.push(ScreenRecord {
+                timestamp: now,
+                duration_in_seconds: 0,
+                monitor: monitor.device_name.clone(),
+                z_order: z_orders[index],
+                area_percent: (window_area * 100 / monitor_area) as u32,
+                activity: activity.clone(),
+            });
+            z_orders[index] += 1;
+        }
+    }
+    records
+}
+
+// Like Logger, a window that stays on the same monitor keeps one record for as long as it
+// does, and everything is written out in batches.
+pub struct ScreenLogger {
+    file: File,
+    interval_in_seconds: u32,
+    max_snapshots_before_flush: u32,
+    count: u32,
+    open_records: Vec<ScreenRecord>,
+    closed_records: Vec<ScreenRecord>,
+}
+
+impl ScreenLogger {
+    pub fn new(interval_in_seconds: u32, flush_interval_in_minutes: u32) -> ScreenLogger {
+        ScreenLogger {
+            file: OpenOptions::new()
+                .append(true)
+                .create(true)
+                .share_mode(winnt::FILE_SHARE_READ)
+                .open(screen_log_file_name())
+                .unwrap(),
+            interval_in_seconds,
+            max_snapshots_before_flush: flush_interval_in_minutes * 60 / interval_in_seconds,
+            count: 0,
+            open_records: Vec::new(),
+            closed_records: Vec::new(),
+        }
+    }
+
+    // an empty snapshot closes everything, e.g. while nobody is there
+    pub fn add_snapshot(&mut self, snapshot: Vec<ScreenRecord>) {
+        let mut open_records = Vec::with_capacity(snapshot.len());
+        for mut record in snapshot {
+            let position = self
+                .open_records
+                .iter()
+                .position(|t| t.monitor == record.monitor && t.activity == record.activity);
+            if let Some(position) = position {
+                let mut open_record = self.open_records.swap_remove(position);
+                open_record.z_order = open_record.z_order.min(record.z_order);
+                open_record.area_percent = open_record.area_percent.max(record.area_percent);
+                record = open_record;
+            }
+            record.duration_in_seconds += self.interval_in_seconds;
+            open_records.push(record);
+        }
+        let closed = mem::replace(&mut self.open_records, open_records);
+        self.closed_records.extend(closed);
+
+        self.count += 1;
+        if self.count >= self.max_snapshots_before_flush {
+            self.flush();
+        }
+    }
+
+    // open records are written as they are and start over with the next snapshot
+    pub fn flush(&mut self) {
+        self.closed_records.append(&mut self.open_records);
+        for record in &self.closed_records {
+            let time = record.timestamp;
+            let activity = &record.activity;
+            writeln!(
+                self.file,
+                "{}-{}-{} {}:{}:{}, {}, {}, {}, {}, {}, {}, {}, {}, {}",
+                time.wYear,
+                time.wMonth,
+                time.wDay,
+                time.wHour,
+                time.wMinute,
+                time.wSecond,
+                record.duration_in_seconds,
+                csv::field(&record.monitor),
+                record.z_order,
+                record.area_percent,
+                csv::field(&activity.image_path),
+                csv::field(&activity.window_title),
+                csv::field(&activity.project),
+                csv::field(&activity.page_title),
+                csv::field(&activity.site)
+            )
+            .unwrap();
+        }
+        self.closed_records.clear();
+        self.count = 0;
+    }
+}
+
+impl Drop for ScreenLogger {
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
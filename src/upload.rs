@@ -0,0 +1,256 @@
+//
+// [upload]
+// ; ships the log to a collector, see record-usage collector
+// url = http://collector.example:9090/
+// token = the token the collector knows this client by
+//
+// The log is shipped as it is, a piece at a time, from where the collector says its copy
+// ends. A piece that fails is sent again from there, so nothing is lost or sent twice, and
+// an upload that was cut short resumes where it stopped. This happens after every flush,
+// and record-usage upload does it right away.
+//
+
+use config::Config;
+use csv;
+use http::{self, Url};
+use json;
+use FLUSH_INTERVAL_IN_MINUTES;
+
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+const PIECE_SIZE: usize = 256 * 1024;
+
+#[derive(Clone, Default, PartialEq)]
+pub struct UploadSettings {
+    pub url: Option<Url>,
+    pub token: String,
+}
+
+impl UploadSettings {
+    pub fn from_config(config: &Config) -> Result<UploadSettings, String> {
+        let mut settings = UploadSettings::default();
+        for (key, value) in config.entries("upload") {
+            match key {
+                "url" => {
+                    settings.url = Some(Url::parse(value).map_err(|e| format!("[upload] {}", e))?)
+                }
+                "token" => settings.token = value.to_string(),
+                _ => return Err(format!("[upload] {}: unknown setting", key)),
+            }
+        }
+        if settings.url.is_some() && settings.token.is_empty() {
+            return Err("[upload] url needs a token".to_string());
+        }
+        Ok(settings)
+    }
+}
+
+// the offset a collector answers with, {"offset": 123456}
+fn offset_of(url: &Url, response: &http::Response) -> Result<u64, String> {
+    json::parse(&response.body)
+        .ok()
+        .and_then(|t| t.get("offset").and_then(|t| t.as_u64()))
+        .ok_or(format!(
+            "{}: answered {} without an offset",
+            url, response.status
+        ))
+}
+
+// The log from the offset on, up to the last complete record of at most a piece, or the
+// one record it starts with when that is longer. A partial record at the end is still being
+// written.
+fn read_piece(file_name: &str, offset: u64) -> Result<Vec<u8>, String> {
+    let mut file = File::open(file_name).map_err(|e| format!("{}: {}", file_name, e))?;
+    let length = file
+        .metadata()
+        .map_err(|e| format!("{}: {}", file_name, e))?
+        .len();
+    if offset > length {
+        return Err(format!(
+            "{}: the collector has {} bytes of it, but there are only {}",
+            file_name, offset, length
+        ));
+    }
+    file.seek(SeekFrom::Start(offset))
+        .map_err(|e| format!("{}: {}", file_name, e))?;
+    read_records(BufReader::new(file)).map_err(|e| format!("{}: {}", file_name, e))
+}
+
+// A record may span lines, the environment is a quoted field with a line per variable, so
+// only a csv::RecordScanner tells where the piece can end.
+fn read_records<R: BufRead>(mut reader: R) -> io::Result<Vec<u8>> {
+    let mut piece = Vec::new();
+    (&mut reader)
+        .take(PIECE_SIZE as u64)
+        .read_to_end(&mut piece)?;
+    let mut scanner = csv::RecordScanner::default();
+    let mut end = scanner.scan(&piece);
+    while end.is_none() && piece.len() >= PIECE_SIZE {
+        let start = piece.len();
+        if reader.read_until(b'\n', &mut piece)? == 0 {
+            break;
+        }
+        end = scanner.scan(&piece[start..]).map(|t| start + t);
+    }
+    piece.truncate(end.unwrap_or(0));
+    Ok(piece)
+}
+
+// Ships what the collector does not have yet and returns the offset it has now. Without a
+// known offset, the collector is asked first.
+pub fn upload(
+    settings: &UploadSettings,
+    file_name: &str,
+    offset: Option<u64>,
+) -> Result<u64, String> {
+    let url = match settings.url {
+        Some(ref t) => t,
+        None => return Err("no [upload] url".to_string()),
+    };
+    let authorization = format!("Bearer {}", settings.token);
+    let headers = [("Authorization", authorization.as_str())];
+
+    let mut offset = match offset {
+        Some(t) => t,
+        None => {
+            let url = url.join("/api/offset");
+            let response = http::request("GET", &url, &headers, "", &[])?;
+            if !response.is_success() {
+                return Err(format!("{}: answered {}", url, response.status));
+            }
+            offset_of(&url, &response)?
+        }
+    };
+    loop {
+        let piece = read_piece(file_name, offset)?;
+        if piece.is_empty() {
+            return Ok(offset);
+        }
+        let url = url.join(&format!("/api/upload?offset={}", offset));
+        let response = http::request("POST", &url, &headers, "text/csv", &piece)?;
+        match response.status {
+            // taken, or not where the collector is; either way it says where to go on
+            200 | 409 => offset = offset_of(&url, &response)?,
+            t => return Err(format!("{}: answered {}", url, t)),
+        }
+    }
+}
+
+enum Request {
+    Settings(UploadSettings),
+    Upload,
+}
+
+// Uploads happen on a thread of their own, a slow collector must not hold up the samples.
+pub struct Uploader {
+    requests: mpsc::Sender<Request>,
+}
+
+impl Uploader {
+    pub fn start(file_name: String) -> Uploader {
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let interval = Duration::from_secs(FLUSH_INTERVAL_IN_MINUTES as u64 * 60);
+            let mut settings = UploadSettings::default();
+            // where the collector's copy ends, as far as we know
+            let mut offset = None;
+            loop {
+                match receiver.recv_timeout(interval) {
+                    Ok(Request::Settings(t)) => {
+                        if t == settings {
+                            continue;
+                        }
+                        settings = t;
+                        offset = None;
+                    }
+                    Ok(Request::Upload) | Err(mpsc::RecvTimeoutError::Timeout) => {}
+                    Err(mpsc::RecvTimeoutError::Disconnected) => return,
+                }
+                if settings.url.is_none() {
+                    continue;
+                }
+                match upload(&settings, &file_name, offset) {
+                    Ok(t) => offset = Some(t),
+                    Err(e) => {
+                        println!("upload: {}", e);
+                        offset = None;
+                    }
+                }
+            }
+        });
+        Uploader { requests: sender }
+    }
+
+    pub fn set_settings(&self, settings: &UploadSettings) {
+        self.requests.send(Request::Settings(settings.clone())).ok();
+    }
+
+    // e.g. after a flush
+    pub fn request_upload(&self) {
+        self.requests.send(Request::Upload).ok();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    // a record of the given length, its last field quoted and spread over lines
+    fn record(length: usize) -> String {
+        let start = "2026-10-19 9:00:00, 10, code, \"";
+        let end = "\", 0\n";
+        let mut environment = String::new();
+        while environment.len() < length - start.len() - end.len() {
+            environment += if environment.len() % 80 == 79 {
+                "\n"
+            } else {
+                "x"
+            };
+        }
+        format!("{}{}{}", start, environment, end)
+    }
+
+    fn pieces(log: &str) -> Vec<String> {
+        let mut pieces = Vec::new();
+        let mut offset = 0;
+        loop {
+            let piece = read_records(Cursor::new(&log.as_bytes()[offset..])).unwrap();
+            if piece.is_empty() {
+                return pieces;
+            }
+            offset += piece.len();
+            pieces.push(String::from_utf8(piece).unwrap());
+        }
+    }
+
+    #[test]
+    fn pieces_end_with_a_record() {
+        let first = record(PIECE_SIZE - 1000);
+        // across the end of the first piece, with newlines on either side of it
+        let second = record(2000);
+        let third = record(100);
+        let log = format!("{}{}{}", first, second, third);
+        assert_eq!(pieces(&log), vec![first, second + &third]);
+    }
+
+    #[test]
+    fn longer_record_is_a_piece_of_its_own() {
+        let first = record(PIECE_SIZE + 5000);
+        let second = record(100);
+        let log = format!("{}{}", first, second);
+        assert_eq!(pieces(&log), vec![first, second]);
+    }
+
+    #[test]
+    fn partial_record_waits() {
+        let first = record(100);
+        let partial = record(PIECE_SIZE * 2);
+        let log = format!("{}{}", first, &partial[..partial.len() - 10]);
+        assert_eq!(pieces(&log), vec![first]);
+    }
+}
@@ -0,0 +1,481 @@
+//
+// [notify]
+// ; events are posted as JSON to every webhook, plain http only
+// webhook = http://192.168.1.10:8080/hooks/usage
+// webhook = http://localhost:8000/
+// ; signs the bodies, see below; none by default
+// secret = a long random string
+// ; all of them by default
+// events = activity | lock | idle | summary | goal
+//
+// Every event is an object that starts with {"event": "...", "time": "2026-10-19T09:30:00"},
+// the time local:
+//
+// activity     the foreground application, project or site changed
+// lock         "lock" and "unlock" of the session
+// idle         "idle_start" and "idle_end"
+// summary      a day as /api/summary has it, at the first sample after midnight
+// goal         a goal was crossed
+//
+// With a secret, X-Record-Usage-Signature is "sha256=" and the hex HMAC-SHA256 of the body.
+// Events wait in an outbox file until their webhook answers 2xx, so they survive restarts and
+// servers that are down. A webhook that fails is retried after 10 seconds, then twice as long
+// each time up to an hour, and holds back only its own events. An event it refuses with a
+// 4xx other than 408 and 429 is dropped. The outbox keeps the latest 10000 of them.
+//
+// record-usage webhook test posts a "test" event to each webhook right away.
+//
+
+use config::{self, Config};
+use http::{self, Url};
+use json;
+use sha256;
+use win32helper;
+
+use std::collections::HashMap;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+const FIRST_RETRY_IN_SECONDS: u64 = 10;
+const LAST_RETRY_IN_SECONDS: u64 = 60 * 60;
+const OUTBOX_SIZE: usize = 10000;
+
+pub fn outbox_file_name() -> String {
+    env::var("LOCALAPPDATA").unwrap() + "\\record-usage-outbox.jsonl"
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EventKind {
+    Activity,
+    Lock,
+    Idle,
+    Summary,
+    Goal,
+}
+
+const EVENT_KINDS: [EventKind; 5] = [
+    EventKind::Activity,
+    EventKind::Lock,
+    EventKind::Idle,
+    EventKind::Summary,
+    EventKind::Goal,
+];
+
+impl EventKind {
+    pub fn parse(name: &str) -> Option<EventKind> {
+        EVENT_KINDS.iter().cloned().find(|t| t.as_str() == name)
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            EventKind::Activity => "activity",
+            EventKind::Lock => "lock",
+            EventKind::Idle => "idle",
+            EventKind::Summary => "summary",
+            EventKind::Goal => "goal",
+        }
+    }
+}
+
+pub struct WebhookSettings {
+    pub urls: Vec<Url>,
+    // empty for unsigned bodies
+    pub secret: String,
+    pub events: Vec<EventKind>,
+}
+
+impl Default for WebhookSettings {
+    fn default() -> WebhookSettings {
+        WebhookSettings {
+            urls: Vec::new(),
+            secret: String::new(),
+            events: EVENT_KINDS.to_vec(),
+        }
+    }
+}
+
+impl WebhookSettings {
+    pub fn from_config(config: &Config) -> Result<WebhookSettings, String> {
+        let mut settings = WebhookSettings::default();
+        for (key, value) in config.entries("notify") {
+            match key {
+                "webhook" => settings
+                    .urls
+                    .push(Url::parse(value).map_err(|e| format!("[notify] {}", e))?),
+                "secret" => settings.secret = value.to_string(),
+                "events" => {
+                    settings.events = config::split_list(value)
+                        .iter()
+                        .map(|t| {
+                            EventKind::parse(t).ok_or(format!(
+                                "[notify] {} = {}: expected activity, lock, idle, summary or goal",
+                                key, value
+                            ))
+                        })
+                        .collect::<Result<_, _>>()?
+                }
+                _ => return Err(format!("[notify] {}: unknown setting", key)),
+            }
+        }
+        Ok(settings)
+    }
+
+    pub fn wants(&self, kind: EventKind) -> bool {
+        !self.urls.is_empty() && self.events.contains(&kind)
+    }
+}
+
+// The name and the local time go first, then the members of the given object.
+pub fn event(name: &str, body: json::Value) -> json::Value {
+    let now = win32helper::get_local_time();
+    let time = format!(
+        "{}-{:02}-{:02}T{:02}:{:02}:{:02}",
+        now.wYear, now.wMonth, now.wDay, now.wHour, now.wMinute, now.wSecond
+    );
+    let mut members = vec![
+        ("event".to_string(), json::Value::from(name)),
+        ("time".to_string(), json::Value::from(time)),
+    ];
+    if let json::Value::Object(t) = body {
+        members.extend(t);
+    }
+    json::Value::Object(members)
+}
+
+// why a webhook did not take an event; status is None when it did not answer at all
+pub struct PostError {
+    pub status: Option<u16>,
+    pub message: String,
+}
+
+impl PostError {
+    // The webhook will refuse the event however often it is sent, e.g. with 400 or 404;
+    // 408 and 429 only ask to come back later.
+    pub fn is_permanent(&self) -> bool {
+        match self.status {
+            Some(t) => (400..500).contains(&t) && t != 408 && t != 429,
+            None => false,
+        }
+    }
+}
+
+impl fmt::Display for PostError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+// One event for one webhook, signed when it was queued.
+pub struct Delivery {
+    url: String,
+    event: String,
+    body: String,
+    signature: String,
+}
+
+impl Delivery {
+    pub fn new(url: &Url, secret: &str, event: &json::Value) -> Delivery {
+        let body = event.to_string();
+        Delivery {
+            url: url.to_string(),
+            event: event
+                .get("event")
+                .and_then(|t| t.as_str())
+                .unwrap_or("")
+                .to_string(),
+            signature: if secret.is_empty() {
+                String::new()
+            } else {
+                format!(
+                    "sha256={}",
+                    sha256::to_hex(&sha256::hmac_sha256(secret.as_bytes(), body.as_bytes()))
+                )
+            },
+            body,
+        }
+    }
+
+    fn to_json(&self) -> json::Value {
+        json::object(vec![
+            ("url", self.url.as_str().into()),
+            ("event", self.event.as_str().into()),
+            ("body", self.body.as_str().into()),
+            ("signature", self.signature.as_str().into()),
+        ])
+    }
+
+    fn from_json(value: &json::Value) -> Option<Delivery> {
+        let member = |key: &str| value.get(key).and_then(|t| t.as_str()).map(String::from);
+        Some(Delivery {
+            url: member("url")?,
+            event: member("event")?,
+            body: member("body")?,
+            signature: member("signature")?,
+        })
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    // anything but a 2xx answer is an error
+    pub fn post(&self) -> Result<(), PostError> {
+        let error = |message| PostError {
+            status: None,
+            message,
+        };
+        let url = Url::parse(&self.url).map_err(error)?;
+        let mut headers = vec![("X-Record-Usage-Event", self.event.as_str())];
+        if !self.signature.is_empty() {
+            headers.push(("X-Record-Usage-Signature", self.signature.as_str()));
+        }
+        let response = http::request(
+            "POST",
+            &url,
+            &headers,
+            "application/json",
+            self.body.as_bytes(),
+        )
+        .map_err(error)?;
+        if !response.is_success() {
+            return Err(PostError {
+                status: Some(response.status),
+                message: format!("{}: answered {}", url.host, response.status),
+            });
+        }
+        Ok(())
+    }
+}
+
+// a line of JSON per delivery, oldest first; lines that do not parse are dropped
+fn load_outbox(file_name: &str) -> Vec<Delivery> {
+    fs::read_to_string(file_name)
+        .unwrap_or_default()
+        .lines()
+        .filter_map(|t| json::parse(t).ok())
+        .filter_map(|t| Delivery::from_json(&t))
+        .collect()
+}
+
+// written aside and renamed, so that a crash leaves either outbox whole
+fn save_outbox(file_name: &str, deliveries: &[Delivery]) {
+    let mut text = String::new();
+    for delivery in deliveries {
+        text += &format!("{}\n", delivery.to_json());
+    }
+    let temporary = format!("{}.tmp", file_name);
+    let result = fs::write(&temporary, text).and_then(|_| fs::rename(&temporary, file_name));
+    if let Err(e) = result {
+        println!("{}: {}", file_name, e);
+    }
+}
+
+struct Backoff {
+    failures: u32,
+    retry_at: Instant,
+}
+
+fn retry_delay(failures: u32) -> Duration {
+    let seconds = FIRST_RETRY_IN_SECONDS << (failures - 1).min(16);
+    Duration::from_secs(seconds.min(LAST_RETRY_IN_SECONDS))
+}
+
+// Posts what is due, oldest first, and waits for the next retry or the next event.
+fn run_outbox(file_name: String, receiver: mpsc::Receiver<Delivery>) {
+    let mut deliveries = load_outbox(&file_name);
+    let mut backoffs: HashMap<String, Backoff> = HashMap::new();
+    loop {
+        let mut changed = false;
+        let mut index = 0;
+        while index < deliveries.len() {
+            let now = Instant::now();
+            if backoffs
+                .get(&deliveries[index].url)
+                .is_some_and(|t| t.retry_at > now)
+            {
+                index += 1;
+                continue;
+            }
+            match deliveries[index].post() {
+                Ok(()) => {
+                    backoffs.remove(&deliveries[index].url);
+                    deliveries.remove(index);
+                    changed = true;
+                }
+                // the server is up, so nothing else of it is held back
+                Err(ref e) if e.is_permanent() => {
+                    println!("{}, dropped", e);
+                    backoffs.remove(&deliveries[index].url);
+                    deliveries.remove(index);
+                    changed = true;
+                }
+                Err(e) => {
+                    let backoff =
+                        backoffs
+                            .entry(deliveries[index].url.clone())
+                            .or_insert(Backoff {
+                                failures: 0,
+                                retry_at: now,
+                            });
+                    backoff.failures += 1;
+                    let delay = retry_delay(backoff.failures);
+                    backoff.retry_at = now + delay;
+                    println!("{}, retried in {} seconds", e, delay.as_secs());
+                    index += 1;
+                }
+            }
+        }
+        if changed {
+            save_outbox(&file_name, &deliveries);
+        }
+
+        // the webhooks that have nothing to post wait for the next event
+        let now = Instant::now();
+        let next_retry = deliveries
+            .iter()
+            .filter_map(|t| backoffs.get(&t.url))
+            .map(|t| t.retry_at.saturating_duration_since(now))
+            .min();
+        let received = match next_retry {
+            Some(t) => receiver.recv_timeout(t),
+            None => receiver
+                .recv()
+                .map_err(|_| mpsc::RecvTimeoutError::Disconnected),
+        };
+        match received {
+            Ok(delivery) => {
+                deliveries.push(delivery);
+                deliveries.extend(receiver.try_iter());
+                if deliveries.len() > OUTBOX_SIZE {
+                    let excess = deliveries.len() - OUTBOX_SIZE;
+                    deliveries.drain(..excess);
+                }
+                // on disk before the first attempt
+                save_outbox(&file_name, &deliveries);
+            }
+            Err(mpsc::RecvTimeoutError::Timeout) => {}
+            Err(mpsc::RecvTimeoutError::Disconnected) => return,
+        }
+    }
+}
+
+// The events go out from a thread of their own, a slow server must not hold up the samples.
+pub struct Outbox {
+    sender: mpsc::Sender<Delivery>,
+}
+
+impl Outbox {
+    pub fn start(file_name: String) -> Outbox {
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || run_outbox(file_name, receiver));
+        Outbox { sender }
+    }
+
+    // queues the event for every webhook, unless the settings leave its kind out
+    pub fn post(&self, settings: &WebhookSettings, kind: EventKind, event: json::Value) {
+        if !settings.wants(kind) {
+            return;
+        }
+        for url in &settings.urls {
+            self.sender
+                .send(Delivery::new(url, &settings.secret, &event))
+                .ok();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    // A webhook on a port of its own that answers one request with the given status, and
+    // hands the request over.
+    fn serve_once(status: &'static str) -> (Url, thread::JoinHandle<http::Request>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = Url::parse(&format!(
+            "http://127.0.0.1:{}/hooks/usage",
+            listener.local_addr().unwrap().port()
+        ))
+        .unwrap();
+        let server = thread::spawn(move || {
+            let mut stream = listener.accept().unwrap().0;
+            let request = http::read_request(&mut stream, 1024 * 1024).unwrap();
+            http::write_response(&mut stream, status, "text/plain", "").unwrap();
+            request
+        });
+        (url, server)
+    }
+
+    fn test_event() -> json::Value {
+        json::object(vec![("event", "test".into()), ("n", 1u64.into())])
+    }
+
+    #[test]
+    fn post_signs_the_body() {
+        let (url, server) = serve_once("204 No Content");
+        let delivery = Delivery::new(&url, "secret", &test_event());
+        assert!(delivery.post().is_ok());
+
+        let request = server.join().unwrap();
+        let body = test_event().to_string();
+        assert_eq!(request.method, "POST");
+        assert_eq!(request.path, "/hooks/usage");
+        assert_eq!(request.body, body.as_bytes());
+        assert_eq!(request.header("X-Record-Usage-Event"), Some("test"));
+        let signature = format!(
+            "sha256={}",
+            sha256::to_hex(&sha256::hmac_sha256(b"secret", body.as_bytes()))
+        );
+        assert_eq!(
+            request.header("X-Record-Usage-Signature"),
+            Some(signature.as_str())
+        );
+    }
+
+    #[test]
+    fn post_without_secret_is_unsigned() {
+        let (url, server) = serve_once("200 OK");
+        assert!(Delivery::new(&url, "", &test_event()).post().is_ok());
+        assert_eq!(
+            server.join().unwrap().header("X-Record-Usage-Signature"),
+            None
+        );
+    }
+
+    #[test]
+    fn non_2xx_answers_are_failures() {
+        let cases = [
+            ("500 Internal Server Error", 500, false),
+            ("429 Too Many Requests", 429, false),
+            ("408 Request Timeout", 408, false),
+            ("404 Not Found", 404, true),
+            ("400 Bad Request", 400, true),
+        ];
+        for &(status, code, permanent) in cases.iter() {
+            let (url, server) = serve_once(status);
+            let error = match Delivery::new(&url, "", &test_event()).post() {
+                Ok(()) => panic!("{} taken for success", status),
+                Err(e) => e,
+            };
+            server.join().unwrap();
+            assert_eq!(error.status, Some(code));
+            assert_eq!(error.is_permanent(), permanent, "{}", status);
+        }
+    }
+
+    #[test]
+    fn retry_delay_doubles_up_to_an_hour() {
+        let delays: Vec<u64> = (1..11).map(|t| retry_delay(t).as_secs()).collect();
+        assert_eq!(
+            delays,
+            vec![10, 20, 40, 80, 160, 320, 640, 1280, 2560, 3600]
+        );
+        assert_eq!(retry_delay(100).as_secs(), 3600);
+        assert_eq!(retry_delay(u32::MAX).as_secs(), 3600);
+    }
+}
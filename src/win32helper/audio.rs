@@ -0,0 +1,200 @@
+#![allow(non_snake_case, non_upper_case_globals)]
+
+extern crate winapi;
+
+use self::winapi::{
+    ctypes, shared::guiddef, shared::minwindef, shared::ntdef, um::audiosessiontypes,
+    um::combaseapi, um::mmdeviceapi, um::unknwnbase, Interface,
+};
+
+use std::ptr;
+
+use super::{Win32Error, Win32Result};
+
+// audiopolicy.h is not covered by winapi. Only the methods called below are typed, the
+// others are placeholders that keep the vtable slots where they belong.
+type VtblSlot = usize;
+
+// {77AA99A0-1BD6-484F-8BC7-2C654C9A9B6F}
+const IID_IAudioSessionManager2: guiddef::GUID = guiddef::GUID {
+    Data1: 0x77aa99a0,
+    Data2: 0x1bd6,
+    Data3: 0x484f,
+    Data4: [0x8b, 0xc7, 0x2c, 0x65, 0x4c, 0x9a, 0x9b, 0x6f],
+};
+
+// {BFB7FF88-7239-4FC9-8FA2-07C950BE9C6D}
+const IID_IAudioSessionControl2: guiddef::GUID = guiddef::GUID {
+    Data1: 0xbfb7ff88,
+    Data2: 0x7239,
+    Data3: 0x4fc9,
+    Data4: [0x8f, 0xa2, 0x07, 0xc9, 0x50, 0xbe, 0x9c, 0x6d],
+};
+
+#[repr(C)]
+pub struct IAudioSessionManager2 {
+    lpVtbl: *const IAudioSessionManager2Vtbl,
+}
+
+#[repr(C)]
+pub struct IAudioSessionManager2Vtbl {
+    parent: unknwnbase::IUnknownVtbl,
+    GetAudioSessionControl: VtblSlot,
+    GetSimpleAudioVolume: VtblSlot,
+    GetSessionEnumerator: unsafe extern "system" fn(
+        This: *mut IAudioSessionManager2,
+        SessionEnum: *mut *mut IAudioSessionEnumerator,
+    ) -> ntdef::HRESULT,
+}
+
+#[repr(C)]
+pub struct IAudioSessionEnumerator {
+    lpVtbl: *const IAudioSessionEnumeratorVtbl,
+}
+
+#[repr(C)]
+pub struct IAudioSessionEnumeratorVtbl {
+    parent: unknwnbase::IUnknownVtbl,
+    GetCount: unsafe extern "system" fn(
+        This: *mut IAudioSessionEnumerator,
+        SessionCount: *mut ctypes::c_int,
+    ) -> ntdef::HRESULT,
+    // the session comes as IAudioSessionControl, ask it for IAudioSessionControl2
+    GetSession: unsafe extern "system" fn(
+        This: *mut IAudioSessionEnumerator,
+        SessionCount: ctypes::c_int,
+        Session: *mut *mut unknwnbase::IUnknown,
+    ) -> ntdef::HRESULT,
+}
+
+#[repr(C)]
+pub struct IAudioSessionControl2 {
+    lpVtbl: *const IAudioSessionControl2Vtbl,
+}
+
+#[repr(C)]
+pub struct IAudioSessionControl2Vtbl {
+    parent: unknwnbase::IUnknownVtbl,
+    GetState: unsafe extern "system" fn(
+        This: *mut IAudioSessionControl2,
+        pRetVal: *mut audiosessiontypes::AudioSessionState,
+    ) -> ntdef::HRESULT,
+    GetDisplayName: VtblSlot,
+    SetDisplayName: VtblSlot,
+    GetIconPath: VtblSlot,
+    SetIconPath: VtblSlot,
+    GetGroupingParam: VtblSlot,
+    SetGroupingParam: VtblSlot,
+    RegisterAudioSessionNotification: VtblSlot,
+    UnregisterAudioSessionNotification: VtblSlot,
+    GetSessionIdentifier: VtblSlot,
+    GetSessionInstanceIdentifier: VtblSlot,
+    GetProcessId: unsafe extern "system" fn(
+        This: *mut IAudioSessionControl2,
+        pRetVal: *mut minwindef::DWORD,
+    ) -> ntdef::HRESULT,
+}
+
+// every COM interface starts with IUnknown, which is all Release needs
+struct ComPtr<T>(*mut T);
+
+impl<T> Drop for ComPtr<T> {
+    fn drop(&mut self) {
+        unsafe {
+            (*(self.0 as *mut unknwnbase::IUnknown)).Release();
+        }
+    }
+}
+
+fn check(function: &'static str, hresult: ntdef::HRESULT) -> Win32Result<()> {
+    if hresult < 0 {
+        return Err(Win32Error::hresult(function, hresult));
+    }
+    Ok(())
+}
+
+// The processes with a stream playing on the default output device. Needs COM to be
+// initialized on the calling thread.
+pub fn get_audio_playing_process_ids() -> Win32Result<Vec<minwindef::DWORD>> {
+    unsafe {
+        let mut enumerator: *mut mmdeviceapi::IMMDeviceEnumerator = ptr::null_mut();
+        check(
+            "CoCreateInstance",
+            combaseapi::CoCreateInstance(
+                &mmdeviceapi::CLSID_MMDeviceEnumerator,
+                ptr::null_mut(),
+                combaseapi::CLSCTX_ALL,
+                &mmdeviceapi::IMMDeviceEnumerator::uuidof(),
+                &mut enumerator as *mut _ as *mut minwindef::LPVOID,
+            ),
+        )?;
+        let enumerator = ComPtr(enumerator);
+
+        let mut device: *mut mmdeviceapi::IMMDevice = ptr::null_mut();
+        check(
+            "IMMDeviceEnumerator::GetDefaultAudioEndpoint",
+            (*enumerator.0).GetDefaultAudioEndpoint(
+                mmdeviceapi::eRender,
+                mmdeviceapi::eMultimedia,
+                &mut device,
+            ),
+        )?;
+        let device = ComPtr(device);
+
+        let mut manager: *mut IAudioSessionManager2 = ptr::null_mut();
+        check(
+            "IMMDevice::Activate",
+            (*device.0).Activate(
+                &IID_IAudioSessionManager2,
+                combaseapi::CLSCTX_ALL,
+                ptr::null_mut(),
+                &mut manager as *mut _ as *mut minwindef::LPVOID,
+            ),
+        )?;
+        let manager = ComPtr(manager);
+
+        let mut sessions: *mut IAudioSessionEnumerator = ptr::null_mut();
+        check(
+            "IAudioSessionManager2::GetSessionEnumerator",
+            ((*(*manager.0).lpVtbl).GetSessionEnumerator)(manager.0, &mut sessions),
+        )?;
+        let sessions = ComPtr(sessions);
+
+        let mut count: ctypes::c_int = 0;
+        check(
+            "IAudioSessionEnumerator::GetCount",
+            ((*(*sessions.0).lpVtbl).GetCount)(sessions.0, &mut count),
+        )?;
+
+        // a session that cannot be asked is one less hint, not a failure
+        let mut process_ids = Vec::new();
+        for index in 0..count {
+            let mut session: *mut unknwnbase::IUnknown = ptr::null_mut();
+            if ((*(*sessions.0).lpVtbl).GetSession)(sessions.0, index, &mut session) < 0 {
+                continue;
+            }
+            let session = ComPtr(session);
+
+            let mut control: *mut IAudioSessionControl2 = ptr::null_mut();
+            if (*session.0).QueryInterface(
+                &IID_IAudioSessionControl2,
+                &mut control as *mut _ as *mut *mut ctypes::c_void,
+            ) < 0
+            {
+                continue;
+            }
+            let control = ComPtr(control);
+
+            let mut state = audiosessiontypes::AudioSessionStateInactive;
+            let mut process_id: minwindef::DWORD = 0;
+            if ((*(*control.0).lpVtbl).GetState)(control.0, &mut state) >= 0
+                && state == audiosessiontypes::AudioSessionStateActive
+                && ((*(*control.0).lpVtbl).GetProcessId)(control.0, &mut process_id) >= 0
+                && process_id != 0
+            {
+                process_ids.push(process_id);
+            }
+        }
+        Ok(process_ids)
+    }
+}
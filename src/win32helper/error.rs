@@ -0,0 +1,104 @@
+extern crate winapi;
+
+use self::winapi::{shared::minwindef, shared::ntdef, um::winbase, um::winnt};
+
+use std::error;
+use std::fmt;
+use std::ptr;
+
+use super::*;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ErrorCode {
+    LastError(minwindef::DWORD),
+    NtStatus(ntdef::NTSTATUS),
+    HResult(ntdef::HRESULT),
+}
+
+// the failed function and why it failed, e.g. "OpenProcess: (5) Access is denied."
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Win32Error {
+    pub function: &'static str,
+    pub code: ErrorCode,
+}
+
+pub type Win32Result<T> = Result<T, Win32Error>;
+
+impl Win32Error {
+    // to be called right after the failing function, before anything can reset it
+    pub fn last_error(function: &'static str) -> Win32Error {
+        Win32Error {
+            function,
+            code: ErrorCode::LastError(get_last_error()),
+        }
+    }
+
+    pub fn nt_status(function: &'static str, status: ntdef::NTSTATUS) -> Win32Error {
+        Win32Error {
+            function,
+            code: ErrorCode::NtStatus(status),
+        }
+    }
+
+    pub fn hresult(function: &'static str, hresult: ntdef::HRESULT) -> Win32Error {
+        Win32Error {
+            function,
+            code: ErrorCode::HResult(hresult),
+        }
+    }
+}
+
+impl fmt::Display for Win32Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.code {
+            ErrorCode::LastError(t) => {
+                let message = format_message(winbase::FORMAT_MESSAGE_FROM_SYSTEM, ptr::null(), t);
+                write!(f, "{}: ({}) {}", self.function, t, message)
+            }
+            ErrorCode::NtStatus(t) => {
+                // NTSTATUS messages live in the message table of ntdll.dll
+                let ntdll = get_module_handle(to_wide_chars("ntdll.dll").as_ptr());
+                let message = format_message(
+                    winbase::FORMAT_MESSAGE_FROM_HMODULE,
+                    ntdll as minwindef::LPCVOID,
+                    t as minwindef::DWORD,
+                );
+                write!(f, "{}: (0x{:08X}) {}", self.function, t, message)
+            }
+            ErrorCode::HResult(t) => {
+                let message = format_message(
+                    winbase::FORMAT_MESSAGE_FROM_SYSTEM,
+                    ptr::null(),
+                    t as minwindef::DWORD,
+                );
+                write!(f, "{}: (0x{:08X}) {}", self.function, t, message)
+            }
+        }
+    }
+}
+
+impl error::Error for Win32Error {}
+
+// pub unsafe extern "system" fn FormatMessageW(dwFlags: DWORD, lpSource: LPCVOID, dwMessageId: DWORD, dwLanguageId: DWORD,
+//                                              lpBuffer: LPWSTR, nSize: DWORD, Arguments: *mut va_list) -> DWORD
+fn format_message(
+    flags: minwindef::DWORD,
+    source: minwindef::LPCVOID,
+    message_id: minwindef::DWORD,
+) -> String {
+    let max_char_count: usize = 512;
+    let mut buffer: Vec<winnt::WCHAR> = vec![0; max_char_count];
+    let char_count = unsafe {
+        winbase::FormatMessageW(
+            flags | winbase::FORMAT_MESSAGE_IGNORE_INSERTS,
+            source,
+            message_id,
+            0,
+            buffer.as_mut_ptr(),
+            max_char_count as minwindef::DWORD,
+            ptr::null_mut(),
+        ) as usize
+    };
+    buffer.truncate(char_count);
+    String::from_utf16_lossy(&buffer).trim_end().to_string()
+}
@@ -1,166 +1,643 @@
 extern crate winapi;
 
 use self::winapi::{
-    ctypes, shared::basetsd, shared::minwindef, shared::windef, um::winnt, um::winuser,
+    ctypes, shared::basetsd, shared::minwindef, shared::windef, shared::winerror, um::minwinbase,
+    um::shellapi, um::winnt, um::winuser,
 };
 
 use std::mem;
 use std::process::Command;
 use std::ptr;
+use std::slice;
 
 use super::ntdll;
 use super::*;
 
-pub fn get_process_peb_address(process_handle: winnt::HANDLE) -> winnt::PVOID {
+pub fn get_process_peb_address(process_handle: &ProcessHandle) -> Win32Result<winnt::PVOID> {
     let mut basic_info: ntdll::PROCESS_BASIC_INFORMATION = unsafe { mem::zeroed() };
-    if ntdll::nt_query_information_process::<ntdll::PROCESS_BASIC_INFORMATION>(
+    ntdll::nt_query_information_process::<ntdll::PROCESS_BASIC_INFORMATION>(
         process_handle,
         ntdll::PROCESSINFOCLASS::ProcessBasicInformation,
         &mut basic_info,
-    ) {
-        basic_info.PebBaseAddress
-    } else {
-        ptr::null_mut()
-    }
+    )?;
+    Ok(basic_info.PebBaseAddress)
+}
+
+pub fn get_parent_process_id(process_handle: &ProcessHandle) -> Win32Result<minwindef::DWORD> {
+    // the native basic information is available for WOW64 processes as well
+    let mut basic_info: ntdll::PROCESS_BASIC_INFORMATION = unsafe { mem::zeroed() };
+    ntdll::nt_query_information_process::<ntdll::PROCESS_BASIC_INFORMATION>(
+        process_handle,
+        ntdll::PROCESSINFOCLASS::ProcessBasicInformation,
+        &mut basic_info,
+    )?;
+    Ok(basic_info.InheritedFromUniqueProcessId as minwindef::DWORD)
 }
 
-pub fn get_process_peb_address_wow32(process_handle: winnt::HANDLE) -> winnt::PVOID {
+pub fn get_process_peb_address_wow32(process_handle: &ProcessHandle) -> Win32Result<winnt::PVOID> {
     let mut peb_address: winnt::PVOID = ptr::null_mut();
-    if ntdll::nt_query_information_process::<winnt::PVOID>(
+    ntdll::nt_query_information_process::<winnt::PVOID>(
         process_handle,
         ntdll::PROCESSINFOCLASS::ProcessWow64Information,
         &mut peb_address,
-    ) {
-        peb_address
-    } else {
-        ptr::null_mut()
-    }
+    )?;
+    Ok(peb_address)
 }
 
-pub fn get_process_command_line(process_handle: winnt::HANDLE) -> String {
-    if is_wow64_process(process_handle) {
-        return get_process_command_line_32(process_handle);
-    }
-    let empty = String::new();
+// the PEB of a 32-bit process is its WOW64 one when we are 64-bit, the only one otherwise
+pub fn get_process_peb_address_32(process_handle: &ProcessHandle) -> Win32Result<u64> {
+    let peb_address = if cfg!(target_pointer_width = "64") {
+        get_process_peb_address_wow32(process_handle)?
+    } else {
+        get_process_peb_address(process_handle)?
+    };
+    Ok(peb_address as usize as u64)
+}
 
-    let peb_address = get_process_peb_address(process_handle);
-    let mut peb: ntdll::PROCESS_ENVIRONMENT_BLOCK = unsafe { mem::zeroed() };
-    if !read_process_memory::<ntdll::PROCESS_ENVIRONMENT_BLOCK>(
+pub fn get_process_peb_address_64(process_handle: &ProcessHandle) -> Win32Result<u64> {
+    let mut basic_info: ntdll::PROCESS_BASIC_INFORMATION_64 = unsafe { mem::zeroed() };
+    ntdll::nt_query_information_process_64::<ntdll::PROCESS_BASIC_INFORMATION_64>(
         process_handle,
-        peb_address,
-        &mut peb,
-    ) {
-        return empty;
+        ntdll::PROCESSINFOCLASS::ProcessBasicInformation,
+        &mut basic_info,
+    )?;
+    Ok(basic_info.PebBaseAddress)
+}
+
+// WOW64 processes are the 32-bit ones on 64-bit Windows, the rest match Windows itself
+pub fn is_64bit_process(process_handle: &ProcessHandle) -> Win32Result<bool> {
+    Ok(!is_wow64_process(process_handle)? && is_64bit_windows())
+}
+
+// the longest command line Windows accepts is 32767 characters
+pub const MAX_REMOTE_STRING_BYTES: usize = 32767 * 2;
+
+#[derive(Default)]
+pub struct ProcessParameters {
+    pub image_path: String,
+    pub command_line: String,
+    pub current_directory: String,
+    // only the variables asked for, in the order asked for
+    pub environment: Vec<(String, String)>,
+}
+
+// variables names are matched case-insensitively, as Windows does; with no names the
+// environment block is not read at all
+pub fn get_process_parameters(
+    process_handle: &ProcessHandle,
+    variables: &[String],
+) -> Win32Result<ProcessParameters> {
+    if is_64bit_process(process_handle)? {
+        let peb_address = get_process_peb_address_64(process_handle)?;
+        read_process_parameters::<u64>(process_handle, peb_address, variables)
+    } else {
+        let peb_address = get_process_peb_address_32(process_handle)?;
+        read_process_parameters::<u32>(process_handle, peb_address, variables)
     }
+}
+
+fn read_process_parameters<P: ntdll::RemotePointer>(
+    process_handle: &ProcessHandle,
+    peb_address: u64,
+    variables: &[String],
+) -> Win32Result<ProcessParameters> {
+    let mut peb: ntdll::PROCESS_ENVIRONMENT_BLOCK<P> = unsafe { mem::zeroed() };
+    read_target_memory::<P, _>(process_handle, peb_address, &mut peb)?;
 
-    let mut process_parameters: ntdll::RTL_USER_PROCESS_PARAMETERS = unsafe { mem::zeroed() };
-    if !read_process_memory::<ntdll::RTL_USER_PROCESS_PARAMETERS>(
+    let mut process_parameters: ntdll::RTL_USER_PROCESS_PARAMETERS<P> = unsafe { mem::zeroed() };
+    read_target_memory::<P, _>(
         process_handle,
-        peb.ProcessParameters,
+        peb.ProcessParameters.into(),
         &mut process_parameters,
-    ) {
-        return empty;
+    )?;
+
+    // the environment is a nice-to-have, the rest of the parameters are not held up by it
+    let environment = if variables.is_empty() {
+        Vec::new()
+    } else {
+        read_process_environment::<P>(process_handle, process_parameters.Environment.into())
+            .map(|block| filter_environment(&block, variables))
+            .unwrap_or_else(|e| {
+                println!("{}", e);
+                Vec::new()
+            })
+    };
+
+    Ok(ProcessParameters {
+        image_path: read_process_unicode_string(process_handle, &process_parameters.ImagePathName)?,
+        command_line: read_process_unicode_string(process_handle, &process_parameters.CommandLine)?,
+        current_directory: read_process_unicode_string(
+            process_handle,
+            &process_parameters.CurrentDirectory.DosPath,
+        )?,
+        environment,
+    })
+}
+
+pub const MAX_REMOTE_ENVIRONMENT_BYTES: usize = 1024 * 1024;
+const PAGE_SIZE: u64 = 4096;
+
+// The block is a run of "NAME=value\0" strings closed by an empty one, and its size is not
+// among what we read. Go page by page until the end shows up, since reading past the end
+// of the block may run into memory that is not there.
+fn read_process_environment<P: ntdll::RemotePointer>(
+    process_handle: &ProcessHandle,
+    address: u64,
+) -> Win32Result<Vec<winnt::WCHAR>> {
+    let invalid_data = Win32Error {
+        function: "read_process_environment",
+        code: ErrorCode::LastError(winerror::ERROR_INVALID_DATA),
+    };
+    if address == 0 || !address.is_multiple_of(2) {
+        return Err(invalid_data);
     }
 
-    let byte_count = process_parameters.CommandLine.Length as usize;
-    let char_count = byte_count / 2;
-    let mut buffer: Vec<winnt::WCHAR> = Vec::with_capacity(char_count);
-    unsafe {
-        buffer.set_len(char_count);
+    let mut block: Vec<winnt::WCHAR> = Vec::new();
+    let mut position = address;
+    loop {
+        let byte_count = (PAGE_SIZE - position % PAGE_SIZE) as usize;
+        if block.len() * 2 + byte_count > MAX_REMOTE_ENVIRONMENT_BYTES {
+            return Err(invalid_data);
+        }
+        let mut chunk: Vec<winnt::WCHAR> = vec![0; byte_count / 2];
+        read_target_memory_raw::<P>(
+            process_handle,
+            position,
+            chunk.as_mut_ptr() as minwindef::LPVOID,
+            byte_count,
+        )?;
+        position += byte_count as u64;
+
+        // the closing pair may straddle the previous chunk
+        let search_from = block.len().saturating_sub(1);
+        block.extend_from_slice(&chunk);
+        if let Some(end) = block[search_from..].windows(2).position(|t| t == [0, 0]) {
+            block.truncate(search_from + end);
+            return Ok(block);
+        }
+    }
+}
+
+// Names are compared before the value is decoded, so nothing outside the allowlist is
+// ever turned into a string. Entries like "=C:=C:\src" are per-drive directories, not
+// variables.
+fn filter_environment(block: &[winnt::WCHAR], variables: &[String]) -> Vec<(String, String)> {
+    let mut found: Vec<(usize, String, String)> = Vec::new();
+    for entry in block.split(|&c| c == 0) {
+        let separator = match entry.iter().skip(1).position(|&c| c == '=' as u16) {
+            Some(t) => t + 1,
+            None => continue,
+        };
+        let name = String::from_utf16_lossy(&entry[..separator]);
+        if let Some(index) = variables.iter().position(|t| t.eq_ignore_ascii_case(&name)) {
+            let value = String::from_utf16_lossy(&entry[separator + 1..]);
+            found.push((index, name, value));
+        }
+    }
+    found.sort_by_key(|&(index, _, _)| index);
+    found
+        .into_iter()
+        .map(|(_, name, value)| (name, value))
+        .collect()
+}
+
+// a 64-bit target may keep its data above anything a 32-bit pointer of ours can address
+fn read_target_memory_raw<P: ntdll::RemotePointer>(
+    process_handle: &ProcessHandle,
+    base_address: u64,
+    buffer: minwindef::LPVOID,
+    size: usize,
+) -> Win32Result<()> {
+    if mem::size_of::<P>() == 8 {
+        ntdll::nt_read_virtual_memory_64(process_handle, base_address, buffer, size)
+    } else {
+        read_process_memory_raw(
+            process_handle,
+            base_address as usize as minwindef::LPCVOID,
+            buffer,
+            size,
+        )
+    }
+}
+
+fn read_target_memory<P: ntdll::RemotePointer, T>(
+    process_handle: &ProcessHandle,
+    base_address: u64,
+    buffer: *mut T,
+) -> Win32Result<()> {
+    read_target_memory_raw::<P>(
+        process_handle,
+        base_address,
+        buffer as minwindef::LPVOID,
+        mem::size_of::<T>(),
+    )
+}
+
+// Length does not include the terminating null. The other process may be in the middle
+// of changing the string, or its PEB may be plain garbage, so check it makes sense before
+// allocating anything.
+fn read_process_unicode_string<P: ntdll::RemotePointer>(
+    process_handle: &ProcessHandle,
+    string: &ntdll::UNICODE_STRING<P>,
+) -> Win32Result<String> {
+    let address: u64 = string.Buffer.into();
+    let byte_count = string.Length as usize;
+    if byte_count == 0 {
+        return Ok(String::new());
+    }
+    if address == 0
+        || !byte_count.is_multiple_of(2)
+        || byte_count > string.MaximumLength as usize
+        || byte_count > MAX_REMOTE_STRING_BYTES
+    {
+        return Err(Win32Error {
+            function: "read_process_unicode_string",
+            code: ErrorCode::LastError(winerror::ERROR_INVALID_DATA),
+        });
     }
-    if !read_process_memory_raw(
+    let char_count = byte_count / 2;
+    let mut buffer: Vec<winnt::WCHAR> = vec![0; char_count];
+    read_target_memory_raw::<P>(
         process_handle,
-        process_parameters.CommandLine.Buffer as minwindef::LPCVOID,
+        address,
         buffer.as_mut_ptr() as minwindef::LPVOID,
         byte_count,
-    ) {
-        return empty;
+    )?;
+    Ok(String::from_utf16_lossy(&buffer))
+}
+
+// how much we were allowed to find out about a process, best first
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum ProcessAccess {
+    #[default]
+    Unknown,
+    // PEB read through PROCESS_QUERY_INFORMATION | PROCESS_VM_READ
+    Full,
+    // image path through PROCESS_QUERY_LIMITED_INFORMATION, typical for elevated processes
+    Limited,
+    // executable name from a process snapshot, for protected processes
+    Snapshot,
+}
+
+impl ProcessAccess {
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            ProcessAccess::Unknown => "",
+            ProcessAccess::Full => "full",
+            ProcessAccess::Limited => "limited",
+            ProcessAccess::Snapshot => "snapshot",
+        }
+    }
+
+    pub fn parse(text: &str) -> ProcessAccess {
+        match text {
+            "full" => ProcessAccess::Full,
+            "limited" => ProcessAccess::Limited,
+            "snapshot" => ProcessAccess::Snapshot,
+            _ => ProcessAccess::Unknown,
+        }
     }
-    String::from_utf16_lossy(&buffer)
 }
 
-pub fn get_process_command_line_32(process_handle: winnt::HANDLE) -> String {
-    let empty = String::new();
+#[derive(Default)]
+pub struct ProcessInformation {
+    pub access: ProcessAccess,
+    pub image_path: String,
+    pub command_line: String,
+    pub current_directory: String,
+    pub parent_process_id: minwindef::DWORD,
+    pub package_family_name: String,
+    pub app_user_model_id: String,
+    pub environment: Vec<(String, String)>,
+    // why the fuller ways did not work out, in the order they were tried
+    pub failures: Vec<Win32Error>,
+}
 
-    let peb_address = get_process_peb_address_wow32(process_handle);
-    let mut peb: ntdll::PROCESS_ENVIRONMENT_BLOCK_32 = unsafe { mem::zeroed() };
-    if !read_process_memory::<ntdll::PROCESS_ENVIRONMENT_BLOCK_32>(
-        process_handle,
-        peb_address,
-        &mut peb,
-    ) {
-        return empty;
+// Falls back from the full PEB read to the image path, and from there to the process
+// snapshot; access tells which of them succeeded.
+// environment only ever comes with full access
+pub fn get_process_information(
+    process_id: minwindef::DWORD,
+    variables: &[String],
+) -> ProcessInformation {
+    let mut result = ProcessInformation::default();
+
+    match open_process(process_id)
+        .and_then(|handle| get_process_parameters(&handle, variables).map(|t| (handle, t)))
+    {
+        Ok((handle, parameters)) => {
+            result.access = ProcessAccess::Full;
+            result.image_path = parameters.image_path;
+            result.command_line = parameters.command_line;
+            result.current_directory = parameters.current_directory;
+            result.environment = parameters.environment;
+            get_process_identity(&handle, &mut result);
+            return result;
+        }
+        Err(e) => {
+            println!("{}", e);
+            result.failures.push(e);
+        }
     }
 
-    let mut process_parameters: ntdll::RTL_USER_PROCESS_PARAMETERS_32 = unsafe { mem::zeroed() };
-    if !read_process_memory::<ntdll::RTL_USER_PROCESS_PARAMETERS_32>(
-        process_handle,
-        peb.ProcessParameters as minwindef::LPCVOID,
-        &mut process_parameters,
-    ) {
-        return empty;
+    match open_process_limited(process_id)
+        .and_then(|handle| query_full_process_image_name(&handle).map(|t| (handle, t)))
+    {
+        Ok((handle, image_path)) => {
+            result.access = ProcessAccess::Limited;
+            result.image_path = image_path;
+            get_process_identity(&handle, &mut result);
+            return result;
+        }
+        Err(e) => {
+            println!("{}", e);
+            result.failures.push(e);
+        }
     }
 
-    let byte_count = process_parameters.CommandLine.Length as usize;
-    let char_count = byte_count / 2;
-    let mut buffer: Vec<winnt::WCHAR> = Vec::with_capacity(char_count);
-    unsafe {
-        buffer.set_len(char_count);
+    match get_process_snapshot() {
+        Ok(entries) => {
+            if let Some(entry) = entries.into_iter().find(|t| t.process_id == process_id) {
+                result.access = ProcessAccess::Snapshot;
+                result.image_path = entry.exe_file;
+                result.parent_process_id = entry.parent_process_id;
+            }
+        }
+        Err(e) => {
+            println!("{}", e);
+            result.failures.push(e);
+        }
     }
-    if !read_process_memory_raw(
-        process_handle,
-        process_parameters.CommandLine.Buffer as minwindef::LPCVOID,
-        buffer.as_mut_ptr() as minwindef::LPVOID,
-        byte_count,
-    ) {
-        return empty;
+    result
+}
+
+// what is available with limited access; none of it is essential
+fn get_process_identity(process_handle: &ProcessHandle, result: &mut ProcessInformation) {
+    match get_parent_process_id(process_handle) {
+        Ok(t) => result.parent_process_id = t,
+        Err(e) => println!("{}", e),
+    }
+    match get_package_family_name(process_handle) {
+        Ok(t) => result.package_family_name = t,
+        Err(e) => println!("{}", e),
+    }
+    match get_application_user_model_id(process_handle) {
+        Ok(t) => result.app_user_model_id = t,
+        Err(e) => println!("{}", e),
     }
-    String::from_utf16_lossy(&buffer)
 }
 
-pub fn get_universal_app(window_handle: &mut windef::HWND, process_id: &mut minwindef::DWORD) {
-    struct EnumUniversaApplParameter {
-        window_handle: windef::HWND,
-        process_id: minwindef::DWORD,
-        child_window: windef::HWND,
-        child_process: minwindef::DWORD,
+#[derive(Default)]
+pub struct FileVersionInfo {
+    pub product_name: String,
+    pub company_name: String,
+}
+
+pub fn get_file_version_strings(file_name: &str) -> Win32Result<FileVersionInfo> {
+    let mut result = FileVersionInfo::default();
+    let block = get_file_version_info(file_name)?;
+
+    // the translation table is an array of {language, code page} pairs; the first one
+    // names the StringFileInfo sub-block holding the strings
+    let (language, code_page) = match ver_query_value(&block, "\\VarFileInfo\\Translation") {
+        Some((buffer, byte_count)) if byte_count >= 4 => unsafe {
+            let translation = buffer as *const minwindef::WORD;
+            (*translation, *translation.offset(1))
+        },
+        _ => return Ok(result),
+    };
+    let prefix = format!("\\StringFileInfo\\{:04x}{:04x}\\", language, code_page);
+
+    result.product_name = query_version_string(&block, &(prefix.clone() + "ProductName"));
+    result.company_name = query_version_string(&block, &(prefix + "CompanyName"));
+    Ok(result)
+}
+
+fn query_version_string(block: &[u8], sub_block: &str) -> String {
+    match ver_query_value(block, sub_block) {
+        // for strings the length is in characters, including the terminating null
+        Some((buffer, char_count)) if char_count > 0 => {
+            let chars = unsafe {
+                slice::from_raw_parts(buffer as *const winnt::WCHAR, char_count as usize)
+            };
+            String::from_utf16_lossy(chars)
+                .trim_end_matches('\0')
+                .trim()
+                .to_string()
+        }
+        _ => String::new(),
+    }
+}
+
+pub const APPLICATION_FRAME_WINDOW_CLASS: &str = "ApplicationFrameWindow";
+pub const CORE_WINDOW_CLASS: &str = "Windows.UI.Core.CoreWindow";
+
+// a snapshot of one window, a child of an ApplicationFrameWindow or a top-level one, see
+// choose_universal_app_window() and choose_detached_app_window()
+pub struct WindowInfo {
+    pub process_id: minwindef::DWORD,
+    pub class_name: String,
+    pub title: String,
+    pub is_visible: bool,
+}
+
+// ApplicationFrameHost only draws the frame; the app itself owns a CoreWindow child of it.
+// Other children from foreign processes (e.g. input panes) are a last resort. While the
+// app is launching, resuming or suspended its CoreWindow is not parented to the frame, see
+// choose_detached_app_window().
+pub fn choose_universal_app_window(
+    host_process_id: minwindef::DWORD,
+    children: &[WindowInfo],
+) -> Option<usize> {
+    children
+        .iter()
+        .enumerate()
+        .filter(|&(_, t)| t.process_id != 0 && t.process_id != host_process_id)
+        .min_by_key(
+            |&(_, t)| match (t.class_name == CORE_WINDOW_CLASS, t.is_visible) {
+                (true, true) => 0,
+                (true, false) => 1,
+                (false, true) => 2,
+                (false, false) => 3,
+            },
+        )
+        .map(|(index, _)| index)
+}
+
+// A CoreWindow that is not parented to its frame is a top-level window of the app's process
+// with the title the frame shows. Without a title there is nothing to go by.
+pub fn choose_detached_app_window(
+    host_process_id: minwindef::DWORD,
+    frame_title: &str,
+    windows: &[WindowInfo],
+) -> Option<usize> {
+    if frame_title.is_empty() {
+        return None;
     }
+    windows.iter().position(|t| {
+        t.process_id != 0
+            && t.process_id != host_process_id
+            && t.class_name == CORE_WINDOW_CLASS
+            && t.title == frame_title
+    })
+}
+
+pub struct UniversalApp {
+    pub window_handle: windef::HWND,
+    pub process_id: minwindef::DWORD,
+}
 
-    unsafe extern "system" fn enum_universal_app_callback(
+// a window that went away in the meantime has no process id, and is skipped by the choosers
+fn get_window_info(window_handle: windef::HWND) -> WindowInfo {
+    WindowInfo {
+        process_id: get_window_process_id(window_handle).unwrap_or(0),
+        class_name: get_class_name(window_handle),
+        title: get_window_text(window_handle).unwrap_or_default(),
+        is_visible: is_window_visible(window_handle),
+    }
+}
+
+fn is_universal_app_frame(class_name: &str) -> bool {
+    class_name == APPLICATION_FRAME_WINDOW_CLASS
+}
+
+// Returns None when the window is not an ApplicationFrameWindow (an immersive process in
+// the foreground by itself is already the app) or when the app cannot be told apart from
+// its host right now.
+pub fn get_universal_app(
+    window_handle: windef::HWND,
+    process_id: minwindef::DWORD,
+) -> Option<UniversalApp> {
+    if !is_universal_app_frame(&get_class_name(window_handle)) {
+        return None;
+    }
+
+    unsafe extern "system" fn enum_child_window_callback(
         child_window: windef::HWND,
         lparam: minwindef::LPARAM,
     ) -> minwindef::BOOL {
-        let parameter = &mut *(lparam as minwindef::LPVOID as *mut EnumUniversaApplParameter);
-        let child_process = get_window_process_id(child_window);
-        if child_process != 0 && child_process != parameter.process_id {
-            parameter.child_window = child_window;
-            parameter.child_process = child_process;
-            return minwindef::FALSE;
-        }
-        return minwindef::TRUE;
+        let children = &mut *(lparam as minwindef::LPVOID as *mut Vec<windef::HWND>);
+        children.push(child_window);
+        minwindef::TRUE
     }
 
-    let mut parameter: EnumUniversaApplParameter = unsafe { mem::zeroed() };
-    parameter.window_handle = *window_handle;
-    parameter.process_id = *process_id;
-
-    let address: *mut EnumUniversaApplParameter = &mut parameter;
+    let mut children: Vec<windef::HWND> = Vec::new();
+    let address: *mut Vec<windef::HWND> = &mut children;
     enum_child_windows(
-        *window_handle,
-        Some(enum_universal_app_callback),
+        window_handle,
+        Some(enum_child_window_callback),
         address as minwindef::LPARAM,
     );
 
-    if parameter.child_process == 0 {
-        println!(
-            "Error trying to find the universal app for host window {:?}",
-            get_window_text(*window_handle)
-        );
-        return;
+    let snapshot: Vec<WindowInfo> = children.iter().map(|&t| get_window_info(t)).collect();
+    if let Some(index) = choose_universal_app_window(process_id, &snapshot) {
+        return Some(UniversalApp {
+            window_handle: children[index],
+            process_id: snapshot[index].process_id,
+        });
     }
 
-    *window_handle = parameter.child_window;
-    *process_id = parameter.child_process;
+    // launching, resuming or suspended
+    let frame_title = get_window_text(window_handle).unwrap_or_default();
+    let windows = get_top_level_windows();
+    let snapshot: Vec<WindowInfo> = windows.iter().map(|&t| get_window_info(t)).collect();
+    choose_detached_app_window(process_id, &frame_title, &snapshot).map(|index| UniversalApp {
+        window_handle: windows[index],
+        process_id: snapshot[index].process_id,
+    })
+}
+
+pub struct MonitorInfo {
+    // e.g. \\.\DISPLAY1
+    pub device_name: String,
+    pub rect: windef::RECT,
+}
+
+pub fn get_monitors() -> Vec<MonitorInfo> {
+    unsafe extern "system" fn enum_monitor_callback(
+        monitor: windef::HMONITOR,
+        _: windef::HDC,
+        _: windef::LPRECT,
+        lparam: minwindef::LPARAM,
+    ) -> minwindef::BOOL {
+        let monitors = &mut *(lparam as minwindef::LPVOID as *mut Vec<windef::HMONITOR>);
+        monitors.push(monitor);
+        minwindef::TRUE
+    }
+
+    let mut monitors: Vec<windef::HMONITOR> = Vec::new();
+    let address: *mut Vec<windef::HMONITOR> = &mut monitors;
+    enum_display_monitors(Some(enum_monitor_callback), address as minwindef::LPARAM);
+
+    // a monitor unplugged in the meantime is left out
+    monitors
+        .into_iter()
+        .filter_map(|monitor| get_monitor_info(monitor).ok())
+        .map(|info| {
+            let length = info
+                .szDevice
+                .iter()
+                .position(|&c| c == 0)
+                .unwrap_or(info.szDevice.len());
+            MonitorInfo {
+                device_name: String::from_utf16_lossy(&info.szDevice[..length]),
+                rect: info.rcMonitor,
+            }
+        })
+        .collect()
+}
+
+// all of them, topmost first
+fn get_top_level_windows() -> Vec<windef::HWND> {
+    unsafe extern "system" fn enum_window_callback(
+        window: windef::HWND,
+        lparam: minwindef::LPARAM,
+    ) -> minwindef::BOOL {
+        let windows = &mut *(lparam as minwindef::LPVOID as *mut Vec<windef::HWND>);
+        windows.push(window);
+        minwindef::TRUE
+    }
+
+    let mut windows: Vec<windef::HWND> = Vec::new();
+    let address: *mut Vec<windef::HWND> = &mut windows;
+    enum_windows(Some(enum_window_callback), address as minwindef::LPARAM);
+    windows
+}
+
+pub struct VisibleWindow {
+    pub window_handle: windef::HWND,
+    pub process_id: minwindef::DWORD,
+    pub title: String,
+    pub rect: windef::RECT,
+}
+
+// Top-level windows someone could be looking at, topmost first: shown, not minimized, not
+// cloaked, with a title, and not a tool window such as a floating palette.
+pub fn get_visible_windows() -> Vec<VisibleWindow> {
+    get_top_level_windows()
+        .into_iter()
+        .filter(|&window| {
+            let ex_style = get_window_extra(window, winuser::GWL_EXSTYLE) as minwindef::DWORD;
+            is_window_visible(window)
+                && !is_iconic(window)
+                && !is_window_cloaked(window)
+                && ex_style & winuser::WS_EX_TOOLWINDOW == 0
+        })
+        .filter_map(|window| {
+            // GetWindowTextW does not wait on the window's thread, which a snapshot of
+            // every window cannot afford to do
+            let title = get_window_text(window).ok()?;
+            if title.is_empty() {
+                return None;
+            }
+            Some(VisibleWindow {
+                window_handle: window,
+                process_id: get_window_process_id(window).ok()?,
+                title,
+                rect: get_window_rect(window).ok()?,
+            })
+        })
+        .collect()
 }
 
 // fn wnd_proc(hwnd: windef::HWND, msg: minwindef::UINT, wparam: minwindef::WPARAM, lparam: LPARAM) -> minwindef::LRESULT
@@ -184,7 +661,7 @@ pub fn create_window<W>(
     style: minwindef::DWORD,
     instance_handle: minwindef::HINSTANCE,
     wnd_extra: ctypes::c_int,
-) -> windef::HWND
+) -> Win32Result<windef::HWND>
 where
     W: WindowTrait,
 {
@@ -228,10 +705,10 @@ where
 
     unsafe {
         if winuser::RegisterClassW(&wnd_class) == 0 {
-            return ptr::null_mut();
+            return Err(Win32Error::last_error("RegisterClassW"));
         }
 
-        winuser::CreateWindowExW(
+        let hwnd = winuser::CreateWindowExW(
             0,
             class_name_vec.as_ptr(),
             window_name_vec.as_ptr(),
@@ -244,8 +721,173 @@ where
             ptr::null_mut(), // hMenu
             instance_handle,
             window as *mut W as minwindef::LPVOID,
-        ) // Passed to WM_NCCREATE as CREATESTRUCT.lpCreateParams
+        ); // Passed to WM_NCCREATE as CREATESTRUCT.lpCreateParams
+        if hwnd.is_null() {
+            return Err(Win32Error::last_error("CreateWindowExW"));
+        }
+        Ok(hwnd)
+    }
+}
+
+// text is cut short to leave room for the terminating zero
+fn copy_wide(text: &str, buffer: &mut [u16]) {
+    let text: Vec<u16> = text.encode_utf16().take(buffer.len() - 1).collect();
+    buffer[..text.len()].copy_from_slice(&text);
+}
+
+// One notification area icon per window is all this app needs. Tips longer than the 127
+// characters Windows allows are cut short.
+//
+// The struct is packed in 32-bit builds, so its strings are filled in a copy rather than
+// through a reference to the field.
+fn notify_icon_data(hwnd: windef::HWND, tip: &str) -> shellapi::NOTIFYICONDATAW {
+    let mut data: shellapi::NOTIFYICONDATAW = unsafe { mem::zeroed() };
+    data.cbSize = mem::size_of::<shellapi::NOTIFYICONDATAW>() as minwindef::DWORD;
+    data.hWnd = hwnd;
+    data.uID = 1;
+    let mut buffer = data.szTip;
+    copy_wide(tip, &mut buffer);
+    data.szTip = buffer;
+    data
+}
+
+// mouse events on the icon arrive as callback_message, with the mouse message in lparam
+pub fn add_notify_icon(
+    hwnd: windef::HWND,
+    callback_message: minwindef::UINT,
+    tip: &str,
+) -> Win32Result<()> {
+    let mut data = notify_icon_data(hwnd, tip);
+    data.uFlags = shellapi::NIF_MESSAGE | shellapi::NIF_ICON | shellapi::NIF_TIP;
+    data.uCallbackMessage = callback_message;
+    data.hIcon = load_system_icon(winuser::IDI_APPLICATION);
+    shell_notify_icon(shellapi::NIM_ADD, &mut data)
+}
+
+pub fn set_notify_icon_tip(hwnd: windef::HWND, tip: &str) -> Win32Result<()> {
+    let mut data = notify_icon_data(hwnd, tip);
+    data.uFlags = shellapi::NIF_TIP;
+    shell_notify_icon(shellapi::NIM_MODIFY, &mut data)
+}
+
+// shown as a toast on Windows 10 and later; the tip stays as it is
+pub fn show_notify_balloon(hwnd: windef::HWND, title: &str, text: &str) -> Win32Result<()> {
+    let mut data = notify_icon_data(hwnd, "");
+    data.uFlags = shellapi::NIF_INFO;
+    data.dwInfoFlags = shellapi::NIIF_INFO;
+    let mut buffer = data.szInfoTitle;
+    copy_wide(title, &mut buffer);
+    data.szInfoTitle = buffer;
+    let mut buffer = data.szInfo;
+    copy_wide(text, &mut buffer);
+    data.szInfo = buffer;
+    shell_notify_icon(shellapi::NIM_MODIFY, &mut data)
+}
+
+pub fn delete_notify_icon(hwnd: windef::HWND) -> Win32Result<()> {
+    let mut data = notify_icon_data(hwnd, "");
+    shell_notify_icon(shellapi::NIM_DELETE, &mut data)
+}
+
+pub enum MenuItem<'a> {
+    Command(minwindef::UINT, &'a str),
+    Disabled(&'a str),
+    Separator,
+}
+
+// Shows a context menu at the mouse position and returns the id of the chosen command.
+// The window has to be in the foreground, or the menu will not go away when clicking
+// elsewhere.
+pub fn show_popup_menu(
+    hwnd: windef::HWND,
+    items: &[MenuItem],
+) -> Win32Result<Option<minwindef::UINT>> {
+    let menu = create_popup_menu()?;
+    let result = (|| {
+        for item in items {
+            match *item {
+                MenuItem::Command(id, text) => append_menu(menu, winuser::MF_STRING, id, text)?,
+                MenuItem::Disabled(text) => {
+                    append_menu(menu, winuser::MF_STRING | winuser::MF_GRAYED, 0, text)?
+                }
+                MenuItem::Separator => append_menu(menu, winuser::MF_SEPARATOR, 0, "")?,
+            }
+        }
+        let position = get_cursor_pos()?;
+        set_foreground_window(hwnd);
+        let id = track_popup_menu(menu, hwnd, position);
+        post_message(hwnd, winuser::WM_NULL, 0, 0)?;
+        Ok(if id == 0 { None } else { Some(id) })
+    })();
+    destroy_menu(menu);
+    result
+}
+
+// A window covering its whole monitor; the desktop does that too without being anything
+// anybody watches.
+pub fn is_fullscreen_window(hwnd: windef::HWND) -> bool {
+    let class_name = get_class_name(hwnd);
+    if class_name == "Progman" || class_name == "WorkerW" {
+        return false;
     }
+    let monitor = match monitor_from_window(hwnd) {
+        Some(t) => t,
+        None => return false,
+    };
+    match (get_window_rect(hwnd), get_monitor_info(monitor)) {
+        (Ok(window), Ok(info)) => {
+            let screen = info.rcMonitor;
+            window.left <= screen.left
+                && window.top <= screen.top
+                && window.right >= screen.right
+                && window.bottom >= screen.bottom
+        }
+        _ => false,
+    }
+}
+
+// browsers play sound from a helper process, so the image path is what tells who it is
+pub fn get_audio_playing_image_paths() -> Win32Result<Vec<String>> {
+    let image_paths = get_audio_playing_process_ids()?
+        .into_iter()
+        .filter_map(|process_id| {
+            open_process_limited(process_id)
+                .and_then(|handle| query_full_process_image_name(&handle))
+                .ok()
+        })
+        .collect();
+    Ok(image_paths)
+}
+
+// both tick counts wrap around after 49.7 days, their difference does not
+pub fn get_idle_time_in_seconds() -> Win32Result<u32> {
+    let last_input = get_last_input_info()?;
+    Ok(get_tick_count().wrapping_sub(last_input) / 1000)
+}
+
+// Seconds since 1601, for comparing and adding to times; a SYSTEMTIME read from the log is
+// local time, and stays local time through the conversion.
+pub fn system_time_to_seconds(time: &minwinbase::SYSTEMTIME) -> Option<u64> {
+    let file_time = system_time_to_file_time(time).ok()?;
+    // FILETIME counts 100 nanosecond intervals
+    let ticks = (file_time.dwHighDateTime as u64) << 32 | file_time.dwLowDateTime as u64;
+    Some(ticks / 10_000_000)
+}
+
+pub fn seconds_to_system_time(seconds: u64) -> Option<minwinbase::SYSTEMTIME> {
+    let ticks = seconds * 10_000_000;
+    let file_time = minwindef::FILETIME {
+        dwLowDateTime: ticks as minwindef::DWORD,
+        dwHighDateTime: (ticks >> 32) as minwindef::DWORD,
+    };
+    file_time_to_system_time(&file_time).ok()
+}
+
+// a time that cannot be converted is left as it is
+pub fn subtract_seconds(time: &minwinbase::SYSTEMTIME, seconds: u32) -> minwinbase::SYSTEMTIME {
+    system_time_to_seconds(time)
+        .and_then(|t| seconds_to_system_time(t.saturating_sub(seconds as u64)))
+        .unwrap_or(*time)
 }
 
 pub fn get_local_ip() -> String {
@@ -274,3 +916,67 @@ pub fn get_local_ip() -> String {
     }
     "".to_string()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HOST: minwindef::DWORD = 100;
+    const APP: minwindef::DWORD = 200;
+
+    fn window(process_id: minwindef::DWORD, class_name: &str, title: &str) -> WindowInfo {
+        WindowInfo {
+            process_id,
+            class_name: class_name.to_string(),
+            title: title.to_string(),
+            is_visible: true,
+        }
+    }
+
+    #[test]
+    fn frame_with_core_window_picks_it() {
+        let children = [
+            window(HOST, "ApplicationFrameTitleBarWindow", ""),
+            window(APP + 1, "Windows.UI.Input.InputSite.WindowClass", ""),
+            window(APP, CORE_WINDOW_CLASS, "Calculator"),
+        ];
+        assert_eq!(choose_universal_app_window(HOST, &children), Some(2));
+    }
+
+    #[test]
+    fn suspended_app_is_found_among_top_level_windows() {
+        let children = [
+            window(HOST, "ApplicationFrameTitleBarWindow", ""),
+            window(HOST, "ApplicationFrameInputSinkWindow", ""),
+        ];
+        assert_eq!(choose_universal_app_window(HOST, &children), None);
+
+        let windows = [
+            window(HOST, APPLICATION_FRAME_WINDOW_CLASS, "Calculator"),
+            window(APP + 1, CORE_WINDOW_CLASS, "Photos"),
+            window(APP, CORE_WINDOW_CLASS, "Calculator"),
+        ];
+        assert_eq!(
+            choose_detached_app_window(HOST, "Calculator", &windows),
+            Some(2)
+        );
+        assert_eq!(choose_detached_app_window(HOST, "", &windows), None);
+        assert_eq!(choose_detached_app_window(HOST, "Mail", &windows), None);
+    }
+
+    #[test]
+    fn other_windows_are_not_frames() {
+        assert!(is_universal_app_frame(APPLICATION_FRAME_WINDOW_CLASS));
+        assert!(!is_universal_app_frame(CORE_WINDOW_CLASS));
+        assert!(!is_universal_app_frame("Notepad"));
+
+        // a window that went away has no process id
+        let children = [window(0, CORE_WINDOW_CLASS, "")];
+        assert_eq!(choose_universal_app_window(HOST, &children), None);
+        let windows = [window(0, CORE_WINDOW_CLASS, "Calculator")];
+        assert_eq!(
+            choose_detached_app_window(HOST, "Calculator", &windows),
+            None
+        );
+    }
+}
@@ -0,0 +1,60 @@
+extern crate winapi;
+
+use self::winapi::{um::handleapi, um::winnt};
+
+use std::mem;
+
+// a kernel handle that is closed when dropped
+pub struct OwnedHandle(winnt::HANDLE);
+
+impl OwnedHandle {
+    // takes over a handle returned from a successful call; functions that return
+    // INVALID_HANDLE_VALUE on failure must be checked by the caller
+    pub fn new(handle: winnt::HANDLE) -> Option<OwnedHandle> {
+        if handle.is_null() || handle == handleapi::INVALID_HANDLE_VALUE {
+            None
+        } else {
+            Some(OwnedHandle(handle))
+        }
+    }
+
+    pub fn as_raw(&self) -> winnt::HANDLE {
+        self.0
+    }
+
+    // for handles that must outlive their owner, such as the single instance mutex
+    pub fn into_raw(self) -> winnt::HANDLE {
+        let handle = self.0;
+        mem::forget(self);
+        handle
+    }
+}
+
+// a kernel handle can be used from any thread
+unsafe impl Send for OwnedHandle {}
+
+impl Drop for OwnedHandle {
+    fn drop(&mut self) {
+        if !self.0.is_null() {
+            // pub unsafe extern "system" fn CloseHandle(hObject: HANDLE) -> BOOL
+            unsafe {
+                handleapi::CloseHandle(self.0);
+            }
+        }
+    }
+}
+
+// a handle from OpenProcess(), so that process functions cannot be handed anything else
+pub struct ProcessHandle {
+    handle: OwnedHandle,
+}
+
+impl ProcessHandle {
+    pub fn new(handle: OwnedHandle) -> ProcessHandle {
+        ProcessHandle { handle }
+    }
+
+    pub fn as_raw(&self) -> winnt::HANDLE {
+        self.handle.as_raw()
+    }
+}
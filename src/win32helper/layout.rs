@@ -0,0 +1,104 @@
+#![allow(non_snake_case, clippy::upper_case_acronyms)]
+
+// The PEB and the process parameters are laid out by the bitness of the target process,
+// not ours: a 64-bit reader sees 32-bit layouts in WOW64 targets, and a 32-bit reader on
+// 64-bit Windows sees 64-bit layouts in native targets. P is the target's pointer type.
+//
+// The layouts must not change with our own bitness either. u32 and u64 have the same
+// alignment on all Windows targets, but the sizes are asserted at compile time so that a
+// 32-bit build (cargo check --target i686-pc-windows-gnu) checks its own layouts, while the
+// tests below check the offsets.
+pub trait RemotePointer: Copy + Into<u64> {}
+impl RemotePointer for u32 {}
+impl RemotePointer for u64 {}
+
+#[repr(C)]
+pub struct PROCESS_ENVIRONMENT_BLOCK<P: RemotePointer> {
+    Reserved1: [u8; 2],
+    pub BeingDebugged: u8,
+    Reserved2: u8,
+    Reserved3: [P; 2],
+    pub Ldr: P,
+    pub ProcessParameters: P,
+}
+
+#[repr(C)]
+pub struct UNICODE_STRING<P: RemotePointer> {
+    pub Length: u16,
+    pub MaximumLength: u16,
+    pub Buffer: P,
+}
+
+#[repr(C)]
+pub struct CURDIR<P: RemotePointer> {
+    pub DosPath: UNICODE_STRING<P>,
+    pub Handle: P,
+}
+
+#[repr(C)]
+pub struct RTL_USER_PROCESS_PARAMETERS<P: RemotePointer> {
+    Reserved1: [u8; 16],
+    Reserved2: [P; 5], // ConsoleHandle, ConsoleFlags, StandardInput/Output/Error
+    pub CurrentDirectory: CURDIR<P>,
+    Reserved3: [P; 2], // DllPath
+    pub ImagePathName: UNICODE_STRING<P>,
+    pub CommandLine: UNICODE_STRING<P>,
+    pub Environment: P,
+}
+
+const _: () = {
+    use std::mem::size_of;
+    assert!(size_of::<UNICODE_STRING<u32>>() == 0x8);
+    assert!(size_of::<UNICODE_STRING<u64>>() == 0x10);
+    assert!(size_of::<CURDIR<u32>>() == 0xc);
+    assert!(size_of::<CURDIR<u64>>() == 0x18);
+    assert!(size_of::<PROCESS_ENVIRONMENT_BLOCK<u32>>() == 0x14);
+    assert!(size_of::<PROCESS_ENVIRONMENT_BLOCK<u64>>() == 0x28);
+    assert!(size_of::<RTL_USER_PROCESS_PARAMETERS<u32>>() == 0x4c);
+    assert!(size_of::<RTL_USER_PROCESS_PARAMETERS<u64>>() == 0x88);
+};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::mem;
+
+    // offsets as documented for PEB32/PEB64
+    #[test]
+    fn unicode_string_layout() {
+        assert_eq!(mem::size_of::<UNICODE_STRING<u32>>(), 0x8);
+        assert_eq!(mem::size_of::<UNICODE_STRING<u64>>(), 0x10);
+        assert_eq!(mem::offset_of!(UNICODE_STRING<u32>, Buffer), 0x4);
+        assert_eq!(mem::offset_of!(UNICODE_STRING<u64>, Buffer), 0x8);
+    }
+
+    #[test]
+    fn process_environment_block_layout() {
+        assert_eq!(
+            mem::offset_of!(PROCESS_ENVIRONMENT_BLOCK<u32>, ProcessParameters),
+            0x10
+        );
+        assert_eq!(
+            mem::offset_of!(PROCESS_ENVIRONMENT_BLOCK<u64>, ProcessParameters),
+            0x20
+        );
+    }
+
+    #[test]
+    fn process_parameters_layout_32() {
+        type Parameters = RTL_USER_PROCESS_PARAMETERS<u32>;
+        assert_eq!(mem::offset_of!(Parameters, CurrentDirectory), 0x24);
+        assert_eq!(mem::offset_of!(Parameters, ImagePathName), 0x38);
+        assert_eq!(mem::offset_of!(Parameters, CommandLine), 0x40);
+        assert_eq!(mem::offset_of!(Parameters, Environment), 0x48);
+    }
+
+    #[test]
+    fn process_parameters_layout_64() {
+        type Parameters = RTL_USER_PROCESS_PARAMETERS<u64>;
+        assert_eq!(mem::offset_of!(Parameters, CurrentDirectory), 0x38);
+        assert_eq!(mem::offset_of!(Parameters, ImagePathName), 0x60);
+        assert_eq!(mem::offset_of!(Parameters, CommandLine), 0x70);
+        assert_eq!(mem::offset_of!(Parameters, Environment), 0x80);
+    }
+}
@@ -1,8 +1,19 @@
 pub use self::wrapper::*;
 mod wrapper;
 
+pub use self::error::*;
+mod error;
+
+pub use self::handle::*;
+mod handle;
+
 pub use self::extra::*;
 mod extra;
 
 pub use self::ntdll::*;
 mod ntdll;
+
+mod layout;
+
+pub use self::audio::*;
+mod audio;
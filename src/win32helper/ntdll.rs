@@ -6,6 +6,12 @@ use self::winapi::{shared::basetsd, shared::minwindef, shared::ntdef, um::winnt}
 
 use std::mem;
 
+#[cfg(target_pointer_width = "64")]
+use super::read_process_memory_raw;
+use super::{ProcessHandle, Win32Error, Win32Result};
+
+pub use super::layout::*;
+
 pub fn NT_SUCCESS(status: ntdef::NTSTATUS) -> bool {
     status >= 0
 }
@@ -38,20 +44,23 @@ extern "system" {
 // pub unsafe extern "system" fn NtQueryInformationProcess(ProcessHandle: HANDLE, ProcessInformationClass: PROCESSINFOCLASS,
 //      ProcessInformation: PVOID, ProcessInformationLength: ULONG, ReturnLength: &mut ULONG) -> NTSTATUS;
 pub fn nt_query_information_process<T>(
-    process_handle: winnt::HANDLE,
+    process_handle: &ProcessHandle,
     information_class: PROCESSINFOCLASS,
     buffer: *mut T,
-) -> bool {
+) -> Win32Result<()> {
     let mut return_length: minwindef::ULONG = 0;
     unsafe {
         let status = NtQueryInformationProcess(
-            process_handle,
+            process_handle.as_raw(),
             information_class,
             buffer as minwindef::LPVOID,
             mem::size_of::<T>() as minwindef::ULONG,
             &mut return_length,
         );
-        NT_SUCCESS(status)
+        if !NT_SUCCESS(status) {
+            return Err(Win32Error::nt_status("NtQueryInformationProcess", status));
+        }
+        Ok(())
     }
 }
 
@@ -61,57 +70,114 @@ pub struct PROCESS_BASIC_INFORMATION {
     pub PebBaseAddress: winnt::PVOID, // PPEB,
     Reserved2: [winnt::PVOID; 2],
     pub UniqueProcessId: basetsd::ULONG_PTR,
-    Reserved3: winnt::PVOID,
+    pub InheritedFromUniqueProcessId: basetsd::ULONG_PTR,
 }
 
 #[repr(C)]
-pub struct PROCESS_ENVIRONMENT_BLOCK {
-    Reserved1: [u8; 2],
-    pub BeingDebugged: u8,
-    Reserved2: u8,
-    Reserved3: [winnt::PVOID; 2],
-    pub Ldr: winnt::PVOID,
-    pub ProcessParameters: winnt::PVOID,
+pub struct PROCESS_BASIC_INFORMATION_64 {
+    Reserved1: u64,
+    pub PebBaseAddress: u64,
+    Reserved2: [u64; 2],
+    pub UniqueProcessId: u64,
+    pub InheritedFromUniqueProcessId: u64,
 }
 
-type POINTER32 = u32;
+// A 64-bit build reads 64-bit targets directly...
+#[cfg(target_pointer_width = "64")]
+pub fn nt_query_information_process_64<T>(
+    process_handle: &ProcessHandle,
+    information_class: PROCESSINFOCLASS,
+    buffer: *mut T,
+) -> Win32Result<()> {
+    nt_query_information_process(process_handle, information_class, buffer)
+}
 
-#[repr(C)]
-pub struct PROCESS_ENVIRONMENT_BLOCK_32 {
-    Reserved1: [u8; 2],
-    pub BeingDebugged: u8,
-    Reserved2: u8,
-    Reserved3: [POINTER32; 2],
-    pub Ldr: POINTER32,
-    pub ProcessParameters: POINTER32,
+#[cfg(target_pointer_width = "64")]
+pub fn nt_read_virtual_memory_64(
+    process_handle: &ProcessHandle,
+    base_address: u64,
+    buffer: minwindef::LPVOID,
+    size: usize,
+) -> Win32Result<()> {
+    read_process_memory_raw(
+        process_handle,
+        base_address as minwindef::LPCVOID,
+        buffer,
+        size,
+    )
 }
 
-// #[repr(C)]
-// pub struct UNICODE_STRING {
-//     Length: minwindef::USHORT,
-//     MaximumLength: minwindef::USHORT,
-//     Buffer: winnt::PVOID,
-// }
+// ...while a 32-bit one has to go through the WOW64 layer, which only the 32-bit ntdll
+// exports. Both fail with STATUS_NOT_IMPLEMENTED on 32-bit Windows, where there are no
+// 64-bit targets to begin with.
+#[cfg(target_pointer_width = "32")]
+#[link(name = "ntdll")]
+extern "system" {
+    pub fn NtWow64QueryInformationProcess64(
+        ProcessHandle: winnt::HANDLE,
+        ProcessInformationClass: PROCESSINFOCLASS,
+        ProcessInformation: winnt::PVOID,
+        ProcessInformationLength: minwindef::ULONG,
+        ReturnLength: &mut minwindef::ULONG,
+    ) -> ntdef::NTSTATUS;
 
-#[repr(C)]
-pub struct UNICODE_STRING_32 {
-    pub Length: minwindef::USHORT,
-    pub MaximumLength: minwindef::USHORT,
-    pub Buffer: POINTER32,
+    pub fn NtWow64ReadVirtualMemory64(
+        ProcessHandle: winnt::HANDLE,
+        BaseAddress: u64,
+        Buffer: winnt::PVOID,
+        Size: u64,
+        NumberOfBytesRead: &mut u64,
+    ) -> ntdef::NTSTATUS;
 }
 
-#[repr(C)]
-pub struct RTL_USER_PROCESS_PARAMETERS {
-    Reserved1: [u8; 16],
-    Reserved2: [winnt::PVOID; 10],
-    pub ImagePathName: ntdef::UNICODE_STRING,
-    pub CommandLine: ntdef::UNICODE_STRING,
+// pub unsafe extern "system" fn NtWow64QueryInformationProcess64(ProcessHandle: HANDLE, ProcessInformationClass: PROCESSINFOCLASS,
+//      ProcessInformation: PVOID, ProcessInformationLength: ULONG, ReturnLength: &mut ULONG) -> NTSTATUS;
+#[cfg(target_pointer_width = "32")]
+pub fn nt_query_information_process_64<T>(
+    process_handle: &ProcessHandle,
+    information_class: PROCESSINFOCLASS,
+    buffer: *mut T,
+) -> Win32Result<()> {
+    let mut return_length: minwindef::ULONG = 0;
+    unsafe {
+        let status = NtWow64QueryInformationProcess64(
+            process_handle.as_raw(),
+            information_class,
+            buffer as minwindef::LPVOID,
+            mem::size_of::<T>() as minwindef::ULONG,
+            &mut return_length,
+        );
+        if !NT_SUCCESS(status) {
+            return Err(Win32Error::nt_status(
+                "NtWow64QueryInformationProcess64",
+                status,
+            ));
+        }
+        Ok(())
+    }
 }
 
-#[repr(C)]
-pub struct RTL_USER_PROCESS_PARAMETERS_32 {
-    Reserved1: [u8; 16],
-    Reserved2: [POINTER32; 10],
-    pub ImagePathName: UNICODE_STRING_32,
-    pub CommandLine: UNICODE_STRING_32,
+// pub unsafe extern "system" fn NtWow64ReadVirtualMemory64(ProcessHandle: HANDLE, BaseAddress: ULONG64, Buffer: PVOID,
+//      Size: ULONG64, NumberOfBytesRead: &mut ULONG64) -> NTSTATUS;
+#[cfg(target_pointer_width = "32")]
+pub fn nt_read_virtual_memory_64(
+    process_handle: &ProcessHandle,
+    base_address: u64,
+    buffer: minwindef::LPVOID,
+    size: usize,
+) -> Win32Result<()> {
+    let mut bytes_read: u64 = 0;
+    unsafe {
+        let status = NtWow64ReadVirtualMemory64(
+            process_handle.as_raw(),
+            base_address,
+            buffer,
+            size as u64,
+            &mut bytes_read,
+        );
+        if !NT_SUCCESS(status) {
+            return Err(Win32Error::nt_status("NtWow64ReadVirtualMemory64", status));
+        }
+        Ok(())
+    }
 }
@@ -9,125 +9,525 @@ use self::winapi::{
     shared::windef,
     shared::winerror,
 
-    // um::consoleapi,
+    um::combaseapi,
+    um::consoleapi,
+    um::dwmapi,
     um::errhandlingapi,
-    um::handleapi,
+    um::fileapi,
     um::libloaderapi,
     um::memoryapi,
     um::minwinbase,
+    um::namedpipeapi,
+    um::objbase,
     um::processthreadsapi,
+    um::shellapi,
     um::synchapi,
     um::sysinfoapi,
+    um::timezoneapi,
+    um::tlhelp32,
+    um::winbase,
+    um::wincon,
     um::winnt,
     um::winuser,
+    um::winver,
     um::wow64apiset,
 };
 
 use std::mem;
 use std::ptr;
 
+use super::*;
+
+pub use self::winapi::shared::windef::HWND;
+
 #[cfg(target_pointer_width = "32")]
 pub const POINTER_SIZE: usize = 4;
 #[cfg(target_pointer_width = "64")]
 pub const POINTER_SIZE: usize = 8;
 
 // pub unsafe extern "system" fn GetForegroundWindow() -> HWND
-pub fn get_foreground_window() -> windef::HWND {
-    unsafe { winuser::GetForegroundWindow() }
+// the foreground window can be NULL, e.g. while it is losing activation
+pub fn get_foreground_window() -> Option<windef::HWND> {
+    let hwnd = unsafe { winuser::GetForegroundWindow() };
+    if hwnd.is_null() {
+        None
+    } else {
+        Some(hwnd)
+    }
 }
 
 // pub unsafe extern "system" fn GetWindowThreadProcessId(hWnd: HWND, lpdwProcessId: LPDWORD) -> DWORD
-pub fn get_window_process_id(hwnd: windef::HWND) -> minwindef::DWORD {
+pub fn get_window_process_id(hwnd: windef::HWND) -> Win32Result<minwindef::DWORD> {
     let mut process_id: minwindef::DWORD = 0;
-    unsafe {
-        winuser::GetWindowThreadProcessId(hwnd, &mut process_id);
+    if unsafe { winuser::GetWindowThreadProcessId(hwnd, &mut process_id) } == 0 {
+        return Err(Win32Error::last_error("GetWindowThreadProcessId"));
     }
-    process_id
+    Ok(process_id)
 }
 
 // pub unsafe extern "system" fn OpenProcess(dwDesiredAccess: DWORD, bInheritHandle: BOOL, dwProcessId: DWORD) -> HANDLE
-pub fn open_process(process_id: minwindef::DWORD) -> winnt::HANDLE {
-    unsafe {
-        processthreadsapi::OpenProcess(
-            winnt::PROCESS_QUERY_INFORMATION | winnt::PROCESS_VM_READ,
-            minwindef::FALSE,
-            process_id,
+fn open_process_with_access(
+    process_id: minwindef::DWORD,
+    desired_access: minwindef::DWORD,
+) -> Win32Result<ProcessHandle> {
+    let handle =
+        unsafe { processthreadsapi::OpenProcess(desired_access, minwindef::FALSE, process_id) };
+    match OwnedHandle::new(handle) {
+        Some(t) => Ok(ProcessHandle::new(t)),
+        None => Err(Win32Error::last_error("OpenProcess")),
+    }
+}
+
+// enough to read the PEB, but denied for elevated processes when we are not
+pub fn open_process(process_id: minwindef::DWORD) -> Win32Result<ProcessHandle> {
+    open_process_with_access(
+        process_id,
+        winnt::PROCESS_QUERY_INFORMATION | winnt::PROCESS_VM_READ,
+    )
+}
+
+// granted for elevated processes as well, though not for all protected ones
+pub fn open_process_limited(process_id: minwindef::DWORD) -> Win32Result<ProcessHandle> {
+    open_process_with_access(process_id, winnt::PROCESS_QUERY_LIMITED_INFORMATION)
+}
+
+// pub unsafe extern "system" fn QueryFullProcessImageNameW(hProcess: HANDLE, dwFlags: DWORD, lpExeName: LPWSTR, lpdwSize: PDWORD) -> BOOL
+pub fn query_full_process_image_name(process_handle: &ProcessHandle) -> Win32Result<String> {
+    let max_char_count: usize = 32768;
+    let mut buffer: Vec<winnt::WCHAR> = vec![0; max_char_count];
+    let mut char_count = max_char_count as minwindef::DWORD;
+    if unsafe {
+        winbase::QueryFullProcessImageNameW(
+            process_handle.as_raw(),
+            0, // Win32 path format
+            buffer.as_mut_ptr(),
+            &mut char_count,
         )
+    } == minwindef::FALSE
+    {
+        return Err(Win32Error::last_error("QueryFullProcessImageNameW"));
     }
+    buffer.truncate(char_count as usize);
+    Ok(String::from_utf16_lossy(&buffer))
 }
 
-// pub unsafe extern "system" fn GetWindowTextW(hWnd: HWND, lpString: LPWSTR, nMaxCount: c_int) -> c_int
-pub fn get_window_text(hwnd: windef::HWND) -> String {
-    let max_char_count: usize = 300;
-    let mut buffer: Vec<winnt::WCHAR> = Vec::with_capacity(max_char_count);
+pub struct ProcessEntry {
+    pub process_id: minwindef::DWORD,
+    pub parent_process_id: minwindef::DWORD,
+    pub exe_file: String,
+}
+
+// pub unsafe extern "system" fn CreateToolhelp32Snapshot(dwFlags: DWORD, th32ProcessID: DWORD) -> HANDLE
+// pub unsafe extern "system" fn Process32FirstW(hSnapshot: HANDLE, lppe: LPPROCESSENTRY32W) -> BOOL
+// pub unsafe extern "system" fn Process32NextW(hSnapshot: HANDLE, lppe: LPPROCESSENTRY32W) -> BOOL
+pub fn get_process_snapshot() -> Win32Result<Vec<ProcessEntry>> {
+    let snapshot = OwnedHandle::new(unsafe {
+        tlhelp32::CreateToolhelp32Snapshot(tlhelp32::TH32CS_SNAPPROCESS, 0)
+    })
+    .ok_or_else(|| Win32Error::last_error("CreateToolhelp32Snapshot"))?;
+
+    let mut entries = Vec::new();
     unsafe {
-        let char_count = winuser::GetWindowTextW(
+        let mut entry: tlhelp32::PROCESSENTRY32W = mem::zeroed();
+        entry.dwSize = mem::size_of::<tlhelp32::PROCESSENTRY32W>() as minwindef::DWORD;
+        if tlhelp32::Process32FirstW(snapshot.as_raw(), &mut entry) == minwindef::FALSE {
+            return Err(Win32Error::last_error("Process32FirstW"));
+        }
+        loop {
+            let length = entry
+                .szExeFile
+                .iter()
+                .position(|&c| c == 0)
+                .unwrap_or(entry.szExeFile.len());
+            entries.push(ProcessEntry {
+                process_id: entry.th32ProcessID,
+                parent_process_id: entry.th32ParentProcessID,
+                exe_file: String::from_utf16_lossy(&entry.szExeFile[..length]),
+            });
+            if tlhelp32::Process32NextW(snapshot.as_raw(), &mut entry) == minwindef::FALSE {
+                break;
+            }
+        }
+    }
+    Ok(entries)
+}
+
+// pub unsafe extern "system" fn GetWindowTextLengthW(hWnd: HWND) -> c_int
+// pub unsafe extern "system" fn GetWindowTextW(hWnd: HWND, lpString: LPWSTR, nMaxCount: c_int) -> c_int
+pub fn get_window_text(hwnd: windef::HWND) -> Win32Result<String> {
+    // zero is returned for both an empty title and a failure
+    set_last_error(0);
+    let length = unsafe { winuser::GetWindowTextLengthW(hwnd) } as usize;
+    if length == 0 {
+        return match get_last_error() {
+            0 => Ok(String::new()),
+            _ => Err(Win32Error::last_error("GetWindowTextLengthW")),
+        };
+    }
+
+    // the length may be larger than the actual text, never smaller
+    let max_char_count = length + 1;
+    let mut buffer: Vec<winnt::WCHAR> = vec![0; max_char_count];
+    set_last_error(0);
+    let char_count = unsafe {
+        winuser::GetWindowTextW(
             hwnd,
             buffer.as_mut_ptr() as winnt::LPWSTR,
             max_char_count as ctypes::c_int,
-        ) as usize;
-        if char_count == 0 {
-            return String::new();
-        }
-        buffer.set_len(char_count);
+        ) as usize
+    };
+    if char_count == 0 && get_last_error() != 0 {
+        return Err(Win32Error::last_error("GetWindowTextW"));
+    }
+    buffer.truncate(char_count);
+    Ok(String::from_utf16_lossy(&buffer))
+}
+
+// pub unsafe extern "system" fn SendMessageTimeoutW(hWnd: HWND, Msg: UINT, wParam: WPARAM, lParam: LPARAM, fuFlags: UINT, uTimeout: UINT,
+//                                                   lpdwResult: PDWORD_PTR) -> LRESULT
+pub fn send_message_timeout(
+    hwnd: windef::HWND,
+    msg: minwindef::UINT,
+    wparam: minwindef::WPARAM,
+    lparam: minwindef::LPARAM,
+    timeout_in_milliseconds: minwindef::UINT,
+) -> Win32Result<basetsd::DWORD_PTR> {
+    let mut result: basetsd::DWORD_PTR = 0;
+    if unsafe {
+        winuser::SendMessageTimeoutW(
+            hwnd,
+            msg,
+            wparam,
+            lparam,
+            winuser::SMTO_ABORTIFHUNG | winuser::SMTO_BLOCK,
+            timeout_in_milliseconds,
+            &mut result,
+        )
+    } == 0
+    {
+        // ERROR_TIMEOUT when the window did not answer in time
+        return Err(Win32Error::last_error("SendMessageTimeoutW"));
     }
+    Ok(result)
+}
+
+// WM_GETTEXTLENGTH is answered by the window itself, which may say anything
+pub const MAX_WINDOW_TEXT_CHARS: usize = 32767;
+
+// Same as get_window_text(), but through WM_GETTEXT with a timeout, so that a hung window
+// cannot block the caller for longer than that.
+pub fn get_window_text_timeout(
+    hwnd: windef::HWND,
+    timeout_in_milliseconds: minwindef::UINT,
+) -> Win32Result<String> {
+    let length = send_message_timeout(
+        hwnd,
+        winuser::WM_GETTEXTLENGTH,
+        0,
+        0,
+        timeout_in_milliseconds,
+    )? as usize;
+    if length == 0 {
+        return Ok(String::new());
+    }
+    if length > MAX_WINDOW_TEXT_CHARS {
+        return Err(Win32Error {
+            function: "get_window_text_timeout",
+            code: ErrorCode::LastError(winerror::ERROR_INVALID_DATA),
+        });
+    }
+
+    let max_char_count = length + 1;
+    let mut buffer: Vec<winnt::WCHAR> = vec![0; max_char_count];
+    let char_count = send_message_timeout(
+        hwnd,
+        winuser::WM_GETTEXT,
+        max_char_count as minwindef::WPARAM,
+        buffer.as_mut_ptr() as minwindef::LPARAM,
+        timeout_in_milliseconds,
+    )? as usize;
+    buffer.truncate(char_count.min(length));
+    Ok(String::from_utf16_lossy(&buffer))
+}
+
+// pub unsafe extern "system" fn IsHungAppWindow(hwnd: HWND) -> BOOL
+// true when the window has not been processing messages for several seconds
+pub fn is_hung_app_window(hwnd: windef::HWND) -> bool {
+    unsafe { winuser::IsHungAppWindow(hwnd) != minwindef::FALSE }
+}
+
+// pub unsafe extern "system" fn GetClassNameW(hWnd: HWND, lpClassName: LPWSTR, nMaxCount: c_int) -> c_int
+pub fn get_class_name(hwnd: windef::HWND) -> String {
+    // class names are limited to 256 characters
+    let max_char_count: usize = 257;
+    let mut buffer: Vec<winnt::WCHAR> = vec![0; max_char_count];
+    let char_count = unsafe {
+        winuser::GetClassNameW(
+            hwnd,
+            buffer.as_mut_ptr() as winnt::LPWSTR,
+            max_char_count as ctypes::c_int,
+        ) as usize
+    };
+    buffer.truncate(char_count);
     String::from_utf16_lossy(&buffer)
 }
 
+// pub unsafe extern "system" fn IsWindowVisible(hWnd: HWND) -> BOOL
+pub fn is_window_visible(hwnd: windef::HWND) -> bool {
+    unsafe { winuser::IsWindowVisible(hwnd) != minwindef::FALSE }
+}
+
+// pub unsafe extern "system" fn GetWindowRect(hWnd: HWND, lpRect: LPRECT) -> BOOL
+pub fn get_window_rect(hwnd: windef::HWND) -> Win32Result<windef::RECT> {
+    unsafe {
+        let mut rect: windef::RECT = mem::zeroed();
+        if winuser::GetWindowRect(hwnd, &mut rect) == minwindef::FALSE {
+            return Err(Win32Error::last_error("GetWindowRect"));
+        }
+        Ok(rect)
+    }
+}
+
+// pub unsafe extern "system" fn MonitorFromWindow(hwnd: HWND, dwFlags: DWORD) -> HMONITOR
+// None when the window is on no monitor at all
+pub fn monitor_from_window(hwnd: windef::HWND) -> Option<windef::HMONITOR> {
+    let monitor = unsafe { winuser::MonitorFromWindow(hwnd, winuser::MONITOR_DEFAULTTONULL) };
+    if monitor.is_null() {
+        None
+    } else {
+        Some(monitor)
+    }
+}
+
+// pub unsafe extern "system" fn GetMonitorInfoW(hMonitor: HMONITOR, lpmi: LPMONITORINFO) -> BOOL
+// the extended version, which adds the device name
+pub fn get_monitor_info(monitor: windef::HMONITOR) -> Win32Result<winuser::MONITORINFOEXW> {
+    unsafe {
+        let mut info: winuser::MONITORINFOEXW = mem::zeroed();
+        info.cbSize = mem::size_of::<winuser::MONITORINFOEXW>() as minwindef::DWORD;
+        let info_pointer = &mut info as *mut winuser::MONITORINFOEXW;
+        if winuser::GetMonitorInfoW(monitor, info_pointer as winuser::LPMONITORINFO)
+            == minwindef::FALSE
+        {
+            return Err(Win32Error::last_error("GetMonitorInfoW"));
+        }
+        Ok(info)
+    }
+}
+
+// pub unsafe extern "system" fn EnumDisplayMonitors(hdc: HDC, lprcClip: LPCRECT, lpfnEnum: MONITORENUMPROC, dwData: LPARAM) -> BOOL
+pub fn enum_display_monitors(
+    callback: winuser::MONITORENUMPROC,
+    lparam: minwindef::LPARAM,
+) -> bool {
+    unsafe {
+        winuser::EnumDisplayMonitors(ptr::null_mut(), ptr::null(), callback, lparam)
+            != minwindef::FALSE
+    }
+}
+
+// pub unsafe extern "system" fn IsIconic(hWnd: HWND) -> BOOL
+pub fn is_iconic(hwnd: windef::HWND) -> bool {
+    unsafe { winuser::IsIconic(hwnd) != minwindef::FALSE }
+}
+
+// pub unsafe extern "system" fn DwmGetWindowAttribute(hWnd: HWND, dwAttribute: DWORD, pvAttribute: PVOID, cbAttribute: DWORD) -> HRESULT
+// Cloaked windows are visible as far as IsWindowVisible() is concerned, but not on screen,
+// e.g. suspended universal apps and windows on other virtual desktops.
+pub fn is_window_cloaked(hwnd: windef::HWND) -> bool {
+    let mut cloaked: minwindef::DWORD = 0;
+    let hresult = unsafe {
+        dwmapi::DwmGetWindowAttribute(
+            hwnd,
+            dwmapi::DWMWA_CLOAKED,
+            &mut cloaked as *mut minwindef::DWORD as minwindef::LPVOID,
+            mem::size_of::<minwindef::DWORD>() as minwindef::DWORD,
+        )
+    };
+    hresult >= 0 && cloaked != 0
+}
+
+// pub unsafe extern "system" fn GetFileVersionInfoSizeW(lptstrFilename: LPCWSTR, lpdwHandle: *mut DWORD) -> DWORD
+// pub unsafe extern "system" fn GetFileVersionInfoW(lptstrFilename: LPCWSTR, dwHandle: DWORD, dwLen: DWORD, lpData: *mut c_void) -> BOOL
+pub fn get_file_version_info(file_name: &str) -> Win32Result<Vec<u8>> {
+    let file_name_vec = to_wide_chars(file_name);
+    unsafe {
+        let mut handle: minwindef::DWORD = 0;
+        let size = winver::GetFileVersionInfoSizeW(file_name_vec.as_ptr(), &mut handle);
+        if size == 0 {
+            return Err(Win32Error::last_error("GetFileVersionInfoSizeW"));
+        }
+        let mut buffer: Vec<u8> = vec![0; size as usize];
+        if winver::GetFileVersionInfoW(
+            file_name_vec.as_ptr(),
+            0,
+            size,
+            buffer.as_mut_ptr() as minwindef::LPVOID,
+        ) == minwindef::FALSE
+        {
+            return Err(Win32Error::last_error("GetFileVersionInfoW"));
+        }
+        Ok(buffer)
+    }
+}
+
+// pub unsafe extern "system" fn VerQueryValueW(pBlock: LPCVOID, lpSubBlock: LPCWSTR, lplpBuffer: &mut LPVOID, puLen: PUINT) -> BOOL
+pub fn ver_query_value(
+    block: &[u8],
+    sub_block: &str,
+) -> Option<(minwindef::LPVOID, minwindef::UINT)> {
+    let sub_block_vec = to_wide_chars(sub_block);
+    let mut buffer: minwindef::LPVOID = ptr::null_mut();
+    let mut length: minwindef::UINT = 0;
+    unsafe {
+        if winver::VerQueryValueW(
+            block.as_ptr() as minwindef::LPCVOID,
+            sub_block_vec.as_ptr(),
+            &mut buffer,
+            &mut length,
+        ) == minwindef::FALSE
+        {
+            return None;
+        }
+    }
+    Some((buffer, length))
+}
+
 // pub unsafe extern "system" fn ReadProcessMemory(hProcess: HANDLE, lpBaseAddress: LPCVOID, lpBuffer: LPVOID, nSize: SIZE_T, lpNumberOfBytesRead: *mut SIZE_T) -> BOOL
 pub fn read_process_memory_raw(
-    process_handle: winnt::HANDLE,
+    process_handle: &ProcessHandle,
     base_address: minwindef::LPCVOID,
     buffer: minwindef::LPVOID,
     size: usize,
-) -> bool {
+) -> Win32Result<()> {
     unsafe {
         let mut bytes_read: basetsd::SIZE_T = 0;
         let result = memoryapi::ReadProcessMemory(
-            process_handle,
+            process_handle.as_raw(),
             base_address,
             buffer as minwindef::LPVOID,
             size as basetsd::SIZE_T,
             &mut bytes_read,
         );
-        result != 0
+        if result == 0 {
+            return Err(Win32Error::last_error("ReadProcessMemory"));
+        }
+        Ok(())
     }
 }
 
-pub fn read_process_memory<T>(
-    process_handle: winnt::HANDLE,
-    base_address: minwindef::LPCVOID,
-    buffer: *mut T,
-) -> bool {
-    read_process_memory_raw(
-        process_handle,
-        base_address,
-        buffer as minwindef::LPVOID,
-        mem::size_of::<T>(),
-    )
-}
-
 // pub unsafe extern "system" fn IsWow64Process(hProcess: HANDLE, Wow64Process: PBOOL) -> BOOL
-pub fn is_wow64_process(process_handle: winnt::HANDLE) -> bool {
+pub fn is_wow64_process(process_handle: &ProcessHandle) -> Win32Result<bool> {
     let mut result: minwindef::BOOL = minwindef::FALSE;
-    unsafe {
-        wow64apiset::IsWow64Process(process_handle, &mut result);
+    if unsafe { wow64apiset::IsWow64Process(process_handle.as_raw(), &mut result) }
+        == minwindef::FALSE
+    {
+        return Err(Win32Error::last_error("IsWow64Process"));
     }
-    result != minwindef::FALSE
+    Ok(result != minwindef::FALSE)
+}
+
+// pub unsafe extern "system" fn GetCurrentProcess() -> HANDLE
+// a 32-bit build is 64-bit Windows' WOW64 guest
+pub fn is_64bit_windows() -> bool {
+    if cfg!(target_pointer_width = "64") {
+        return true;
+    }
+    let mut result: minwindef::BOOL = minwindef::FALSE;
+    let succeeded =
+        unsafe { wow64apiset::IsWow64Process(processthreadsapi::GetCurrentProcess(), &mut result) }
+            != minwindef::FALSE;
+    succeeded && result != minwindef::FALSE
 }
 
 // pub unsafe extern "system" fn IsImmersiveProcess(hProcess: HANDLE) -> BOOL
-pub fn is_immersive_process(process_handle: winnt::HANDLE) -> bool {
-    unsafe { winuser::IsImmersiveProcess(process_handle) != minwindef::FALSE }
+// FALSE doubles as the failure value, and a process we cannot ask is not worth treating
+// as immersive
+pub fn is_immersive_process(process_handle: &ProcessHandle) -> bool {
+    unsafe { winuser::IsImmersiveProcess(process_handle.as_raw()) != minwindef::FALSE }
 }
 
-// pub unsafe extern "system" fn CloseHandle(hObject: HANDLE) -> BOOL
-pub fn close_handle(handle: winnt::HANDLE) {
+// LONG WINAPI GetPackageFamilyName(
+//   _In_      HANDLE hProcess,
+//   _Inout_   UINT32 *packageFamilyNameLength,
+//   _Out_opt_ PWSTR  packageFamilyName
+// );
+// LONG WINAPI GetApplicationUserModelId(
+//   _In_      HANDLE hProcess,
+//   _Inout_   UINT32 *applicationUserModelIdLength,
+//   _Out_opt_ PWSTR  applicationUserModelId
+// );
+#[allow(non_snake_case)]
+#[link(name = "kernel32")]
+extern "system" {
+    pub fn GetPackageFamilyName(
+        hProcess: winnt::HANDLE,
+        packageFamilyNameLength: *mut u32,
+        packageFamilyName: winnt::LPWSTR,
+    ) -> winnt::LONG;
+
+    pub fn GetApplicationUserModelId(
+        hProcess: winnt::HANDLE,
+        applicationUserModelIdLength: *mut u32,
+        applicationUserModelId: winnt::LPWSTR,
+    ) -> winnt::LONG;
+}
+
+type GetPackageString =
+    unsafe extern "system" fn(winnt::HANDLE, *mut u32, winnt::LPWSTR) -> winnt::LONG;
+
+// Both return the error code rather than setting the last error. A process that is not
+// packaged yields APPMODEL_ERROR_NO_PACKAGE/APPLICATION, which is not a failure to us.
+fn get_package_string(
+    process_handle: &ProcessHandle,
+    function: GetPackageString,
+    function_name: &'static str,
+) -> Win32Result<String> {
+    const APPMODEL_ERROR_NO_PACKAGE: winnt::LONG = 15700;
+    const APPMODEL_ERROR_NO_APPLICATION: winnt::LONG = 15703;
+
     unsafe {
-        handleapi::CloseHandle(handle);
+        let mut char_count: u32 = 0;
+        let result = function(process_handle.as_raw(), &mut char_count, ptr::null_mut());
+        if result == APPMODEL_ERROR_NO_PACKAGE || result == APPMODEL_ERROR_NO_APPLICATION {
+            return Ok(String::new());
+        }
+        if result != winerror::ERROR_INSUFFICIENT_BUFFER as winnt::LONG {
+            return Err(Win32Error {
+                function: function_name,
+                code: ErrorCode::LastError(result as minwindef::DWORD),
+            });
+        }
+        let mut buffer: Vec<winnt::WCHAR> = vec![0; char_count as usize];
+        let result = function(
+            process_handle.as_raw(),
+            &mut char_count,
+            buffer.as_mut_ptr(),
+        );
+        if result != winerror::ERROR_SUCCESS as winnt::LONG {
+            return Err(Win32Error {
+                function: function_name,
+                code: ErrorCode::LastError(result as minwindef::DWORD),
+            });
+        }
+        // char_count includes the terminating null
+        buffer.truncate((char_count as usize).saturating_sub(1));
+        Ok(String::from_utf16_lossy(&buffer))
     }
 }
 
+pub fn get_package_family_name(process_handle: &ProcessHandle) -> Win32Result<String> {
+    get_package_string(process_handle, GetPackageFamilyName, "GetPackageFamilyName")
+}
+
+pub fn get_application_user_model_id(process_handle: &ProcessHandle) -> Win32Result<String> {
+    get_package_string(
+        process_handle,
+        GetApplicationUserModelId,
+        "GetApplicationUserModelId",
+    )
+}
+
 // type WNDENUMPROC = Option<unsafe  extern "system" fn(HWND, LPARAM) -> BOOL>;
 // pub unsafe extern "system" fn EnumChildWindows(hwndParent: HWND, lpEnumFunc: WNDENUMPROC, lpParam: LPARAM) -> BOOL
 pub fn enum_child_windows(
@@ -138,6 +538,12 @@ pub fn enum_child_windows(
     unsafe { winuser::EnumChildWindows(parent_window, callback, lparam) != minwindef::FALSE }
 }
 
+// pub unsafe extern "system" fn EnumWindows(lpEnumFunc: WNDENUMPROC, lParam: LPARAM) -> BOOL
+// top-level windows, topmost first
+pub fn enum_windows(callback: winuser::WNDENUMPROC, lparam: minwindef::LPARAM) -> bool {
+    unsafe { winuser::EnumWindows(callback, lparam) != minwindef::FALSE }
+}
+
 pub fn to_wide_chars(s: &str) -> Vec<u16> {
     let mut v: Vec<u16> = s.encode_utf16().collect();
     v.push(0);
@@ -148,8 +554,9 @@ pub fn to_wide_chars(s: &str) -> Vec<u16> {
 pub const WINDOW_EXTRA_SLOT_WND_PROC: ctypes::c_int = 0;
 pub const WINDOW_EXTRA_SLOT_USER: ctypes::c_int = POINTER_SIZE as ctypes::c_int;
 
+// 32-bit winapi maps the *Ptr functions to GetWindowLongW/SetWindowLongW, which take LONG
 pub fn get_window_extra(hwnd: windef::HWND, index: ctypes::c_int) -> basetsd::LONG_PTR {
-    unsafe { winuser::GetWindowLongPtrW(hwnd, index) }
+    unsafe { winuser::GetWindowLongPtrW(hwnd, index) as basetsd::LONG_PTR }
 }
 
 pub fn set_window_extra(
@@ -157,7 +564,7 @@ pub fn set_window_extra(
     index: ctypes::c_int,
     value: basetsd::LONG_PTR,
 ) -> basetsd::LONG_PTR {
-    unsafe { winuser::SetWindowLongPtrW(hwnd, index, value) }
+    unsafe { winuser::SetWindowLongPtrW(hwnd, index, value as _) as basetsd::LONG_PTR }
 }
 
 // fn window_extra_real_index(index: ctypes::c_int) -> ctypes::c_int {
@@ -186,12 +593,25 @@ pub fn set_window_extra(
 //     return true;
 // }
 
+// pub unsafe extern "system" fn CoInitializeEx(pvReserved: LPVOID, dwCoInit: DWORD) -> HRESULT
+// once per thread; S_FALSE just means it already was
+pub fn co_initialize_multithreaded() -> Win32Result<()> {
+    let hresult =
+        unsafe { combaseapi::CoInitializeEx(ptr::null_mut(), objbase::COINIT_MULTITHREADED) };
+    if hresult < 0 {
+        return Err(Win32Error::hresult("CoInitializeEx", hresult));
+    }
+    Ok(())
+}
+
 // pub unsafe extern "system" fn GetModuleHandleW(lpModuleName: LPCWSTR) -> HMODULE
+// NULL on failure; the caller decides whether that matters
 pub fn get_module_handle(module_name: winnt::LPCWSTR) -> minwindef::HMODULE {
     unsafe { libloaderapi::GetModuleHandleW(module_name) }
 }
 
 pub fn get_current_instance() -> minwindef::HINSTANCE {
+    // the module of the running executable always exists
     get_module_handle(ptr::null()) as minwindef::HINSTANCE
 }
 
@@ -221,10 +641,174 @@ pub fn message_loop() {
     }
 }
 
-// // pub unsafe extern "system" fn AllocConsole() -> BOOL
-// pub fn alloc_console() -> bool {
-//     unsafe { consoleapi::AllocConsole() != minwindef::FALSE }
-// }
+// pub unsafe extern "system" fn PostMessageW(hWnd: HWND, Msg: UINT, wParam: WPARAM, lParam: LPARAM) -> BOOL
+pub fn post_message(
+    hwnd: windef::HWND,
+    msg: minwindef::UINT,
+    wparam: minwindef::WPARAM,
+    lparam: minwindef::LPARAM,
+) -> Win32Result<()> {
+    if unsafe { winuser::PostMessageW(hwnd, msg, wparam, lparam) } == minwindef::FALSE {
+        return Err(Win32Error::last_error("PostMessageW"));
+    }
+    Ok(())
+}
+
+// pub unsafe extern "system" fn SendMessageW(hWnd: HWND, Msg: UINT, wParam: WPARAM, lParam: LPARAM) -> LRESULT
+// blocks until the window has handled it; only for windows of our own process
+pub fn send_message(
+    hwnd: windef::HWND,
+    msg: minwindef::UINT,
+    wparam: minwindef::WPARAM,
+    lparam: minwindef::LPARAM,
+) -> minwindef::LRESULT {
+    unsafe { winuser::SendMessageW(hwnd, msg, wparam, lparam) }
+}
+
+// pub unsafe extern "system" fn DestroyWindow(hWnd: HWND) -> BOOL
+pub fn destroy_window(hwnd: windef::HWND) -> Win32Result<()> {
+    if unsafe { winuser::DestroyWindow(hwnd) } == minwindef::FALSE {
+        return Err(Win32Error::last_error("DestroyWindow"));
+    }
+    Ok(())
+}
+
+// pub unsafe extern "system" fn RegisterWindowMessageW(lpString: LPCWSTR) -> UINT
+pub fn register_window_message(name: &str) -> Win32Result<minwindef::UINT> {
+    let name_vec = to_wide_chars(name);
+    let msg = unsafe { winuser::RegisterWindowMessageW(name_vec.as_ptr()) };
+    if msg == 0 {
+        return Err(Win32Error::last_error("RegisterWindowMessageW"));
+    }
+    Ok(msg)
+}
+
+// pub unsafe extern "system" fn LoadIconW(hInstance: HINSTANCE, lpIconName: LPCWSTR) -> HICON
+// one of the system icons, e.g. IDI_APPLICATION
+pub fn load_system_icon(icon_name: winnt::LPCWSTR) -> windef::HICON {
+    unsafe { winuser::LoadIconW(ptr::null_mut(), icon_name) }
+}
+
+// pub unsafe extern "system" fn Shell_NotifyIconW(dwMessage: DWORD, lpData: PNOTIFYICONDATAW) -> BOOL
+pub fn shell_notify_icon(
+    message: minwindef::DWORD,
+    data: &mut shellapi::NOTIFYICONDATAW,
+) -> Win32Result<()> {
+    if unsafe { shellapi::Shell_NotifyIconW(message, data) } == minwindef::FALSE {
+        return Err(Win32Error::last_error("Shell_NotifyIconW"));
+    }
+    Ok(())
+}
+
+// pub unsafe extern "system" fn CreatePopupMenu() -> HMENU
+pub fn create_popup_menu() -> Win32Result<windef::HMENU> {
+    let menu = unsafe { winuser::CreatePopupMenu() };
+    if menu.is_null() {
+        return Err(Win32Error::last_error("CreatePopupMenu"));
+    }
+    Ok(menu)
+}
+
+// pub unsafe extern "system" fn AppendMenuW(hMenu: HMENU, uFlags: UINT, uIDNewItem: UINT_PTR, lpNewItem: LPCWSTR) -> BOOL
+pub fn append_menu(
+    menu: windef::HMENU,
+    flags: minwindef::UINT,
+    id: minwindef::UINT,
+    text: &str,
+) -> Win32Result<()> {
+    let text_vec = to_wide_chars(text);
+    let result =
+        unsafe { winuser::AppendMenuW(menu, flags, id as basetsd::UINT_PTR, text_vec.as_ptr()) };
+    if result == minwindef::FALSE {
+        return Err(Win32Error::last_error("AppendMenuW"));
+    }
+    Ok(())
+}
+
+// pub unsafe extern "system" fn TrackPopupMenu(hMenu: HMENU, uFlags: UINT, x: c_int, y: c_int, nReserved: c_int, hWnd: HWND, prcRect: *const RECT) -> BOOL
+// the id of the chosen item, 0 when the menu was dismissed
+pub fn track_popup_menu(
+    menu: windef::HMENU,
+    hwnd: windef::HWND,
+    position: windef::POINT,
+) -> minwindef::UINT {
+    unsafe {
+        winuser::TrackPopupMenu(
+            menu,
+            winuser::TPM_RETURNCMD | winuser::TPM_RIGHTBUTTON,
+            position.x,
+            position.y,
+            0,
+            hwnd,
+            ptr::null(),
+        ) as minwindef::UINT
+    }
+}
+
+// pub unsafe extern "system" fn DestroyMenu(hMenu: HMENU) -> BOOL
+pub fn destroy_menu(menu: windef::HMENU) {
+    unsafe {
+        winuser::DestroyMenu(menu);
+    }
+}
+
+// pub unsafe extern "system" fn GetCursorPos(lpPoint: LPPOINT) -> BOOL
+pub fn get_cursor_pos() -> Win32Result<windef::POINT> {
+    let mut point = windef::POINT { x: 0, y: 0 };
+    if unsafe { winuser::GetCursorPos(&mut point) } == minwindef::FALSE {
+        return Err(Win32Error::last_error("GetCursorPos"));
+    }
+    Ok(point)
+}
+
+// pub unsafe extern "system" fn SetForegroundWindow(hWnd: HWND) -> BOOL
+pub fn set_foreground_window(hwnd: windef::HWND) -> bool {
+    unsafe { winuser::SetForegroundWindow(hwnd) != minwindef::FALSE }
+}
+
+// pub unsafe extern "system" fn ShellExecuteW(hwnd: HWND, lpOperation: LPCWSTR, lpFile: LPCWSTR, lpParameters: LPCWSTR,
+//                                             lpDirectory: LPCWSTR, nShowCmd: c_int) -> HINSTANCE
+// opens a document, folder or URL with whatever is registered for it
+pub fn shell_execute(file: &str) -> Win32Result<()> {
+    let operation_vec = to_wide_chars("open");
+    let file_vec = to_wide_chars(file);
+    let result = unsafe {
+        shellapi::ShellExecuteW(
+            ptr::null_mut(),
+            operation_vec.as_ptr(),
+            file_vec.as_ptr(),
+            ptr::null(),
+            ptr::null(),
+            winuser::SW_SHOWNORMAL,
+        )
+    };
+    // anything above 32 is success, the rest are error codes of their own
+    if result as usize <= 32 {
+        return Err(Win32Error {
+            function: "ShellExecuteW",
+            code: ErrorCode::LastError(result as usize as minwindef::DWORD),
+        });
+    }
+    Ok(())
+}
+
+// pub unsafe extern "system" fn AllocConsole() -> BOOL
+pub fn alloc_console() -> Win32Result<()> {
+    if unsafe { consoleapi::AllocConsole() } == minwindef::FALSE {
+        return Err(Win32Error::last_error("AllocConsole"));
+    }
+    Ok(())
+}
+
+pub const ATTACH_PARENT_PROCESS: minwindef::DWORD = wincon::ATTACH_PARENT_PROCESS;
+
+// pub unsafe extern "system" fn AttachConsole(dwProcessId: DWORD) -> BOOL
+pub fn attach_console(process_id: minwindef::DWORD) -> Win32Result<()> {
+    if unsafe { wincon::AttachConsole(process_id) } == minwindef::FALSE {
+        return Err(Win32Error::last_error("AttachConsole"));
+    }
+    Ok(())
+}
 
 fn to_winapi_bool(x: bool) -> minwindef::BOOL {
     if x {
@@ -285,15 +869,82 @@ fn to_winapi_bool(x: bool) -> minwindef::BOOL {
 // }
 
 // pub unsafe extern "system" fn CreateMutexW(lpMutexAttributes: LPSECURITY_ATTRIBUTES, bInitialOwner: BOOL, lpName: LPCWSTR) -> HANDLE
-pub fn create_mutex(initial_owner: bool, name: &str) -> winnt::HANDLE {
+pub fn create_mutex(initial_owner: bool, name: &str) -> Win32Result<OwnedHandle> {
     let name_vec = to_wide_chars(name);
-    unsafe {
+    let handle = unsafe {
         synchapi::CreateMutexW(
             ptr::null_mut(),
             to_winapi_bool(initial_owner),
             name_vec.as_ptr(),
         )
+    };
+    OwnedHandle::new(handle).ok_or_else(|| Win32Error::last_error("CreateMutexW"))
+}
+
+// pub unsafe extern "system" fn CreateNamedPipeW(lpName: LPCWSTR, dwOpenMode: DWORD, dwPipeMode: DWORD, nMaxInstances: DWORD, nOutBufferSize: DWORD, nInBufferSize: DWORD, nDefaultTimeOut: DWORD, lpSecurityAttributes: LPSECURITY_ATTRIBUTES) -> HANDLE
+// a byte mode duplex pipe for local clients, with the default security of the creator
+pub fn create_named_pipe(name: &str, first_instance: bool) -> Win32Result<OwnedHandle> {
+    let name_vec = to_wide_chars(name);
+    let mut open_mode = winbase::PIPE_ACCESS_DUPLEX;
+    if first_instance {
+        open_mode |= winbase::FILE_FLAG_FIRST_PIPE_INSTANCE;
+    }
+    let buffer_size = 4096;
+    let handle = unsafe {
+        namedpipeapi::CreateNamedPipeW(
+            name_vec.as_ptr(),
+            open_mode,
+            winbase::PIPE_TYPE_BYTE
+                | winbase::PIPE_READMODE_BYTE
+                | winbase::PIPE_REJECT_REMOTE_CLIENTS,
+            winbase::PIPE_UNLIMITED_INSTANCES,
+            buffer_size,
+            buffer_size,
+            0,
+            ptr::null_mut(),
+        )
+    };
+    OwnedHandle::new(handle).ok_or_else(|| Win32Error::last_error("CreateNamedPipeW"))
+}
+
+// pub unsafe extern "system" fn ConnectNamedPipe(hNamedPipe: HANDLE, lpOverlapped: LPOVERLAPPED) -> BOOL
+// waits for a client; one that came before the call counts as well
+pub fn connect_named_pipe(pipe: &OwnedHandle) -> Win32Result<()> {
+    if unsafe { namedpipeapi::ConnectNamedPipe(pipe.as_raw(), ptr::null_mut()) } == minwindef::FALSE
+        && get_last_error() != winerror::ERROR_PIPE_CONNECTED
+    {
+        return Err(Win32Error::last_error("ConnectNamedPipe"));
+    }
+    Ok(())
+}
+
+// pub unsafe extern "system" fn WaitNamedPipeW(lpNamedPipeName: LPCWSTR, nTimeOut: DWORD) -> BOOL
+pub fn wait_named_pipe(name: &str, timeout_in_milliseconds: minwindef::DWORD) -> Win32Result<()> {
+    let name_vec = to_wide_chars(name);
+    if unsafe { namedpipeapi::WaitNamedPipeW(name_vec.as_ptr(), timeout_in_milliseconds) }
+        == minwindef::FALSE
+    {
+        return Err(Win32Error::last_error("WaitNamedPipeW"));
     }
+    Ok(())
+}
+
+// pub unsafe extern "system" fn CreateFileW(lpFileName: LPCWSTR, dwDesiredAccess: DWORD, dwShareMode: DWORD, lpSecurityAttributes: LPSECURITY_ATTRIBUTES, dwCreationDisposition: DWORD, dwFlagsAndAttributes: DWORD, hTemplateFile: HANDLE) -> HANDLE
+// the client end of a named pipe
+pub fn open_named_pipe(name: &str) -> Win32Result<OwnedHandle> {
+    let name_vec = to_wide_chars(name);
+    let handle = unsafe {
+        fileapi::CreateFileW(
+            name_vec.as_ptr(),
+            winnt::GENERIC_READ | winnt::GENERIC_WRITE,
+            0,
+            ptr::null_mut(),
+            fileapi::OPEN_EXISTING,
+            0,
+            ptr::null_mut(),
+        )
+    };
+    OwnedHandle::new(handle).ok_or_else(|| Win32Error::last_error("CreateFileW"))
 }
 
 // pub unsafe extern "system" fn GetLastError() -> DWORD
@@ -302,17 +953,23 @@ pub fn get_last_error() -> minwindef::DWORD {
 }
 
 // pub unsafe extern "system" fn SetLastError(dwErrCode: DWORD)
-// pub fn set_last_error(value: minwindef::DWORD) {
-//     unsafe { errhandlingapi::SetLastError(value) };
-// }
+pub fn set_last_error(value: minwindef::DWORD) {
+    unsafe { errhandlingapi::SetLastError(value) };
+}
 
 pub fn is_app_already_runniing(name: &str) -> bool {
-    let handle = create_mutex(false, name);
-
     // - if the function fails, the return value is NULL.
     // - if the named mutex already exists before this function call, the return value is a non-null handle to the existing object,
     //   GetLastError returns ERROR_ALREADY_EXISTS, bInitialOwner is ignored.
-    (handle == ptr::null_mut() || get_last_error() == winerror::ERROR_ALREADY_EXISTS)
+    match create_mutex(false, name) {
+        Ok(handle) => {
+            let already_exists = get_last_error() == winerror::ERROR_ALREADY_EXISTS;
+            // the mutex marks this instance for as long as the process lives
+            handle.into_raw();
+            already_exists
+        }
+        Err(_) => true,
+    }
 }
 
 // wparam for WM_POWERBROADCAST
@@ -371,8 +1028,12 @@ pub fn register_power_setting_notification(
     recipient: winnt::HANDLE,
     setting: &guiddef::GUID,
     flags: minwindef::DWORD,
-) -> HPOWERNOTIFY {
-    unsafe { RegisterPowerSettingNotification(recipient, setting, flags) }
+) -> Win32Result<HPOWERNOTIFY> {
+    let notify = unsafe { RegisterPowerSettingNotification(recipient, setting, flags) };
+    if notify.is_null() {
+        return Err(Win32Error::last_error("RegisterPowerSettingNotification"));
+    }
+    Ok(notify)
 }
 
 // flags for register_power_setting_notification
@@ -392,8 +1053,14 @@ extern "system" {
     ) -> minwindef::BOOL;
 }
 
-pub fn wts_register_session_notification(hwnd: windef::HWND, flags: minwindef::DWORD) -> bool {
-    unsafe { WTSRegisterSessionNotification(hwnd, flags) != minwindef::FALSE }
+pub fn wts_register_session_notification(
+    hwnd: windef::HWND,
+    flags: minwindef::DWORD,
+) -> Win32Result<()> {
+    if unsafe { WTSRegisterSessionNotification(hwnd, flags) } == minwindef::FALSE {
+        return Err(Win32Error::last_error("WTSRegisterSessionNotification"));
+    }
+    Ok(())
 }
 
 // flags for wts_register_session_notification
@@ -409,21 +1076,77 @@ pub fn get_local_time() -> minwinbase::SYSTEMTIME {
     }
 }
 
+// pub unsafe extern "system" fn GetSystemTime(lpSystemTime: LPSYSTEMTIME)
+// in UTC
+pub fn get_system_time() -> minwinbase::SYSTEMTIME {
+    unsafe {
+        let mut now: minwinbase::SYSTEMTIME = mem::zeroed();
+        sysinfoapi::GetSystemTime(&mut now);
+        now
+    }
+}
+
+// pub unsafe extern "system" fn SystemTimeToFileTime(lpSystemTime: *const SYSTEMTIME, lpFileTime: LPFILETIME) -> BOOL
+pub fn system_time_to_file_time(
+    system_time: &minwinbase::SYSTEMTIME,
+) -> Win32Result<minwindef::FILETIME> {
+    unsafe {
+        let mut file_time: minwindef::FILETIME = mem::zeroed();
+        if timezoneapi::SystemTimeToFileTime(system_time, &mut file_time) == minwindef::FALSE {
+            return Err(Win32Error::last_error("SystemTimeToFileTime"));
+        }
+        Ok(file_time)
+    }
+}
+
+// pub unsafe extern "system" fn FileTimeToSystemTime(lpFileTime: *const FILETIME, lpSystemTime: LPSYSTEMTIME) -> BOOL
+pub fn file_time_to_system_time(
+    file_time: &minwindef::FILETIME,
+) -> Win32Result<minwinbase::SYSTEMTIME> {
+    unsafe {
+        let mut system_time: minwinbase::SYSTEMTIME = mem::zeroed();
+        if timezoneapi::FileTimeToSystemTime(file_time, &mut system_time) == minwindef::FALSE {
+            return Err(Win32Error::last_error("FileTimeToSystemTime"));
+        }
+        Ok(system_time)
+    }
+}
+
+// pub unsafe extern "system" fn GetTickCount() -> DWORD
+pub fn get_tick_count() -> minwindef::DWORD {
+    unsafe { sysinfoapi::GetTickCount() }
+}
+
+// pub unsafe extern "system" fn GetLastInputInfo(plii: PLASTINPUTINFO) -> BOOL
+// the tick count of the last input in this session
+pub fn get_last_input_info() -> Win32Result<minwindef::DWORD> {
+    let mut info = winuser::LASTINPUTINFO {
+        cbSize: mem::size_of::<winuser::LASTINPUTINFO>() as minwindef::UINT,
+        dwTime: 0,
+    };
+    if unsafe { winuser::GetLastInputInfo(&mut info) } == minwindef::FALSE {
+        return Err(Win32Error::last_error("GetLastInputInfo"));
+    }
+    Ok(info.dwTime)
+}
+
 pub fn output_timestamp() {
     let now = get_local_time();
     print!("{}:{}:{} - ", now.wHour, now.wMinute, now.wSecond);
 }
 
 // pub unsafe extern "system" fn SetTimer(hWnd: HWND, nIDEvent: UINT_PTR, uElapse: UINT, lpTimerFunc: TIMERPROC) -> UINT_PTR
-pub fn set_timer(hwnd: windef::HWND, id: u32, elaps: minwindef::UINT) {
-    unsafe {
-        winuser::SetTimer(hwnd, id as basetsd::UINT_PTR, elaps, None);
+pub fn set_timer(hwnd: windef::HWND, id: u32, elaps: minwindef::UINT) -> Win32Result<()> {
+    if unsafe { winuser::SetTimer(hwnd, id as basetsd::UINT_PTR, elaps, None) } == 0 {
+        return Err(Win32Error::last_error("SetTimer"));
     }
+    Ok(())
 }
 
 // pub unsafe extern "system" fn KillTimer(hWnd: HWND, uIDEvent: UINT_PTR) -> BOOL
-pub fn kill_timer(hwnd: windef::HWND, id: u32) {
-    unsafe {
-        winuser::KillTimer(hwnd, id as basetsd::UINT_PTR);
+pub fn kill_timer(hwnd: windef::HWND, id: u32) -> Win32Result<()> {
+    if unsafe { winuser::KillTimer(hwnd, id as basetsd::UINT_PTR) } == minwindef::FALSE {
+        return Err(Win32Error::last_error("KillTimer"));
     }
+    Ok(())
 }